@@ -0,0 +1,117 @@
+//! A stand-in for the score server so the client can be tested on localhost
+//!
+//! `cargo run --bin mock_score_server [port]` then point `score_server` in settings.toml at `http://127.0.0.1:<port>`
+//! Port 0 picks a free port, the first line printed says which. Scores are only kept in memory
+
+use std::{collections::HashMap, env, io::{BufRead, BufReader, Read, Write}, net::{TcpListener, TcpStream}};
+
+use anyhow::{Result, bail};
+use serde_json::{Value, json};
+
+const DEFAULT_PORT: u16 = 7878;
+const LEADERBOARD_SIZE: usize = 10;
+
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else { bail!("Bad request line: {:?}", request_line) };
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim();
+        if header.is_empty() { break }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse()?;
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request { method: method.to_string(), path: path.to_string(), body: String::from_utf8(body)? })
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &Value) -> Result<()> {
+    let body = body.to_string();
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+fn handle(stream: &mut TcpStream, scores: &mut HashMap<String, Vec<Value>>) -> Result<()> {
+    let request = read_request(stream)?;
+    println!("{} {}", request.method, request.path);
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/api/scores") => {
+            let submission: Value = match serde_json::from_str(&request.body) {
+                Ok(submission) => submission,
+                Err(e) => return respond(stream, "400 Bad Request", &json!({ "error": e.to_string() })),
+            };
+
+            let Some(map_hash) = submission["map_hash"].as_str() else {
+                return respond(stream, "400 Bad Request", &json!({ "error": "missing map_hash" }))
+            };
+
+            let entries = scores.entry(map_hash.to_string()).or_default();
+            entries.push(submission.clone());
+            entries.sort_by_key(|entry| std::cmp::Reverse(entry["score"].as_u64().unwrap_or(0)));
+
+            respond(stream, "201 Created", &json!({ "ok": true }))
+        },
+        ("GET", path) if path.starts_with("/api/leaderboard/") => {
+            let map_hash = &path["/api/leaderboard/".len()..];
+
+            let leaderboard: Vec<Value> = scores.get(map_hash).map(|entries| entries.iter().take(LEADERBOARD_SIZE).map(|entry| json!({
+                "initials": entry["initials"],
+                "score": entry["score"],
+                "level": entry["level"],
+                "ranked": entry["ranked"],
+                "date": entry["date"],
+            })).collect()).unwrap_or_default();
+
+            respond(stream, "200 OK", &Value::Array(leaderboard))
+        },
+        _ => respond(stream, "404 Not Found", &json!({ "error": "not found" })),
+    }
+}
+
+fn main() -> Result<()> {
+    let port = match env::args().nth(1) {
+        Some(port) => port.parse()?,
+        None => DEFAULT_PORT,
+    };
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Mock score server listening on http://127.0.0.1:{}", listener.local_addr()?.port());
+
+    let mut scores: HashMap<String, Vec<Value>> = HashMap::new();
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => { eprintln!("Connection failed: {}", e); continue },
+        };
+
+        if let Err(e) = handle(&mut stream, &mut scores) {
+            eprintln!("Request failed: {}", e);
+        }
+    }
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ use bevy::prelude::*;
 // use bevy_inspector_egui::{WorldInspectorPlugin, RegisterInspectable};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
-use crate::game::{player::{PlayerMovement, PlayerData, GameData}, map::{TileCollider, Food}, MovementHelper, enemy::Ghost, AnimationDescriptor};
+use pacman::game::{player::{PlayerMovement, PlayerData, GameData}, map::{TileCollider, Food}, MovementHelper, enemy::Ghost, AnimationDescriptor};
 
 pub struct DebugPlugin;
 
@@ -0,0 +1,74 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+
+use super::{enemy::GhostPersonality, map::walls::wall_atlas, text::{GLYPH_PIXELS, SHEET_COLUMNS, SHEET_ROWS}};
+
+/// Every texture atlas the game uses, loaded once at startup
+/// Sprites share these handles instead of adding an atlas each
+#[derive(Resource, Clone)]
+pub struct GameAssets {
+    /// sprites.png, Pac-Man (also the life icons)
+    pub player: Handle<TextureAtlas>,
+    pub blinky: Handle<TextureAtlas>,
+    pub pinky: Handle<TextureAtlas>,
+    pub inky: Handle<TextureAtlas>,
+    pub clyde: Handle<TextureAtlas>,
+    /// Generated auto-tile pieces, the ghost house door is in here too
+    pub walls: Handle<TextureAtlas>,
+    pub pacdot: Handle<TextureAtlas>,
+    pub power_pellet: Handle<TextureAtlas>,
+    /// text.png
+    pub font: Handle<TextureAtlas>,
+    /// The fruit row of general_sprites.png
+    pub fruit: Handle<TextureAtlas>,
+    /// maze.png, only used when testing the map layout
+    pub maze: Handle<TextureAtlas>,
+}
+
+impl GameAssets {
+    pub fn ghost(&self, personality: GhostPersonality) -> Handle<TextureAtlas> {
+        match personality {
+            GhostPersonality::Blinky => self.blinky.clone(),
+            GhostPersonality::Pinky => self.pinky.clone(),
+            GhostPersonality::Inky => self.inky.clone(),
+            GhostPersonality::Clyde => self.clyde.clone(),
+        }
+    }
+}
+
+impl FromWorld for GameAssets {
+    fn from_world(world: &mut World) -> Self {
+        let start = Instant::now();
+
+        let asset_server = world.resource::<AssetServer>().clone();
+        let walls = wall_atlas(&mut world.resource_mut::<Assets<Image>>());
+
+        let mut texture_atlases = world.resource_mut::<Assets<TextureAtlas>>();
+        let walls = texture_atlases.add(walls);
+
+        let mut grid = |path: &str, tile_size: Vec2, columns: usize, rows: usize, offset: Option<Vec2>| {
+            texture_atlases.add(TextureAtlas::from_grid(asset_server.load(path), tile_size, columns, rows, None, offset))
+        };
+
+        let ghost_size = Vec2 { x: 16.0, y: 14.0 };
+
+        let assets = Self {
+            player: grid("sprites.png", Vec2 { x: 15.0, y: 15.0 }, 2, 4, None),
+            blinky: grid("ghosts/blinky.png", ghost_size, 1, 8, None),
+            pinky: grid("ghosts/pinky.png", ghost_size, 1, 8, None),
+            inky: grid("ghosts/inky.png", ghost_size, 1, 8, None),
+            clyde: grid("ghosts/clyde.png", ghost_size, 1, 8, None),
+            walls: walls,
+            pacdot: grid("pacdot.png", Vec2 { x: 2.0, y: 2.0 }, 1, 1, None),
+            power_pellet: grid("powerpellet.png", Vec2 { x: 8.0, y: 8.0 }, 1, 1, None),
+            font: grid("text.png", Vec2::splat(GLYPH_PIXELS), SHEET_COLUMNS, SHEET_ROWS, None),
+            fruit: grid("general_sprites.png", Vec2::splat(16.0), 8, 1, Some(Vec2 { x: 488.0, y: 48.0 })),
+            maze: grid("maze.png", Vec2 { x: 224.0, y: 248.0 }, 1, 1, None),
+        };
+
+        debug!("Loaded {} texture atlases in {:?}", texture_atlases.len(), start.elapsed());
+
+        assets
+    }
+}
@@ -0,0 +1,485 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, time::Instant};
+
+use bevy::{prelude::*, app::AppExit};
+use serde_json::Value;
+
+use super::{map::{grid::MapGrid, CurrentMap, Food}, map::MapTile, movement::TileMover, player::{PlayerData, GameData, PlayerMovement, Seat}, enemy::{Ghost, GhostPersonality}, players::Players, input::{Action, ActionState}, util::{get_settings, Xorshift}, CurrentDirection, EntityState, MovementHelper, GameState};
+
+const DIRECTIONS: [CurrentDirection; 4] = [CurrentDirection::Up, CurrentDirection::Down, CurrentDirection::Left, CurrentDirection::Right];
+
+/// Seconds on the title screen before the demo starts
+const ATTRACT_TIME: f32 = 20.0;
+
+/// Energized ticks left where ghosts are treated as dangerous again
+const FRIGHT_MARGIN: u64 = 180;
+
+/// How far ahead Clyde gives up chasing, as in the arcade
+const CLYDE_SHY_DISTANCE: f32 = 8.0;
+
+/// Benchmarks stop here if the autopilot never dies
+const DEFAULT_BENCHMARK_LEVELS: usize = 10;
+
+/// How well the autopilot plays, presets are in BotDifficulty::preset and any field can be set in [autopilot]
+/// A fixed difficulty makes a repeatable opponent for trying out ghost AI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BotDifficulty {
+    /// Tiles (in steps) around a ghost that are avoided
+    pub danger_radius: i32,
+    /// How many tiles of each ghost's route to its target are avoided
+    pub lookahead: usize,
+    /// Frightened ghosts closer than this (in steps) are chased, 0 never hunts
+    pub hunt_range: usize,
+    /// Ticks between decisions
+    pub think_every: u32,
+    /// Chance (0 - 1) of a random turn at each decision
+    pub mistakes: f32,
+}
+
+impl Default for BotDifficulty {
+    fn default() -> Self {
+        Self::preset("normal").unwrap()
+    }
+}
+
+impl BotDifficulty {
+    pub fn preset(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "easy" => Some(Self { danger_radius: 1, lookahead: 0, hunt_range: 0, think_every: 12, mistakes: 0.15 }),
+            "normal" => Some(Self { danger_radius: 2, lookahead: 3, hunt_range: 6, think_every: 4, mistakes: 0.03 }),
+            "hard" => Some(Self { danger_radius: 3, lookahead: 6, hunt_range: 12, think_every: 1, mistakes: 0.0 }),
+            _ => None,
+        }
+    }
+
+    /// The preset named by `difficulty` (normal if missing) with any other values in the section on top
+    pub fn from_settings(section: &Value) -> Self {
+        let name = section["difficulty"].as_str().unwrap_or("normal");
+        let mut difficulty = Self::preset(name).unwrap_or_else(|| { warn!("Unknown autopilot difficulty {:?}", name); Self::default() });
+
+        if let Some(value) = section["danger_radius"].as_i64() { difficulty.danger_radius = value.max(0) as i32 }
+        if let Some(value) = section["lookahead"].as_u64() { difficulty.lookahead = value as usize }
+        if let Some(value) = section["hunt_range"].as_u64() { difficulty.hunt_range = value as usize }
+        if let Some(value) = section["think_every"].as_u64() { difficulty.think_every = (value as u32).max(1) }
+        if let Some(value) = section["mistakes"].as_f64() { difficulty.mistakes = (value as f32).clamp(0.0, 1.0) }
+
+        difficulty
+    }
+
+    pub fn load() -> Self {
+        match get_settings() {
+            Ok(settings) => Self::from_settings(&settings["autopilot"]),
+            Err(e) => { error!("Failed to read autopilot settings: {}", e); Self::default() },
+        }
+    }
+}
+
+/// Drives a Pac-Man instead of PlayerMovement::r#move
+#[derive(Component, Debug, Clone)]
+pub struct Autopilot {
+    pub difficulty: BotDifficulty,
+    rng: Xorshift,
+    ticks: u32,
+}
+
+impl Autopilot {
+    pub fn new(difficulty: BotDifficulty, seed: u64) -> Self {
+        Self { difficulty: difficulty, rng: Xorshift::new(seed), ticks: 0 }
+    }
+}
+
+/// Tiles one move away, walking off the map from a teleport comes out at its partner
+fn neighbours(grid: &MapGrid, tile: IVec2) -> impl Iterator<Item = (CurrentDirection, IVec2)> + '_ {
+    DIRECTIONS.into_iter().filter_map(move |direction| {
+        let next = tile + direction.tile_step();
+        if grid.is_walkable(next) { return Some((direction, next)) }
+        if !grid.in_bounds(next) { return grid.teleport_destination(tile).map(|destination| (direction, destination)) }
+        None
+    })
+}
+
+/// Steps from the nearest source to every tile that can be reached
+fn distances(grid: &MapGrid, sources: impl Iterator<Item = IVec2>) -> HashMap<IVec2, usize> {
+    let mut found = HashMap::new();
+    let mut queue = VecDeque::new();
+
+    for source in sources {
+        if found.insert(source, 0).is_none() { queue.push_back(source) }
+    }
+
+    while let Some(tile) = queue.pop_front() {
+        let steps = found[&tile];
+        for (_, next) in neighbours(grid, tile) {
+            if !found.contains_key(&next) {
+                found.insert(next, steps + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    found
+}
+
+/// The first move on the shortest path to a goal that stays off the blocked tiles
+/// None if no goal can be reached within `max_steps`
+fn first_step(grid: &MapGrid, from: IVec2, blocked: &HashSet<IVec2>, max_steps: usize, goal: impl Fn(IVec2) -> bool) -> Option<CurrentDirection> {
+    let mut seen = HashSet::from([from]);
+    let mut queue = VecDeque::new();
+
+    for (direction, next) in neighbours(grid, from) {
+        if blocked.contains(&next) || !seen.insert(next) { continue }
+        queue.push_back((next, direction, 1));
+    }
+
+    while let Some((tile, first, steps)) = queue.pop_front() {
+        if goal(tile) { return Some(first) }
+        if steps >= max_steps { continue }
+
+        for (_, next) in neighbours(grid, tile) {
+            if blocked.contains(&next) || !seen.insert(next) { continue }
+            queue.push_back((next, first, steps + 1));
+        }
+    }
+
+    None
+}
+
+/// The tile a ghost is chasing, worked out the same way as the arcade
+fn predicted_target(personality: GhostPersonality, ghost: IVec2, pacman: &TileMover, blinky: Option<IVec2>) -> IVec2 {
+    let ahead = |tiles: i32| pacman.tile + pacman.heading.tile_step() * tiles;
+
+    match personality {
+        GhostPersonality::Blinky => pacman.tile,
+        GhostPersonality::Pinky => ahead(4),
+        GhostPersonality::Inky => {
+            let pivot = ahead(2);
+            blinky.map(|blinky| pivot * 2 - blinky).unwrap_or(pivot)
+        },
+        GhostPersonality::Clyde => if (ghost - pacman.tile).as_vec2().length() > CLYDE_SHY_DISTANCE { pacman.tile } else { ghost },
+    }
+}
+
+/// The tiles a ghost will cross heading for its target, it never turns back on itself
+fn predicted_route(grid: &MapGrid, ghost: IVec2, heading: CurrentDirection, target: IVec2, steps: usize) -> Vec<IVec2> {
+    let mut route = Vec::new();
+    let (mut tile, mut heading) = (ghost, heading);
+
+    for _ in 0..steps {
+        let next = neighbours(grid, tile)
+            .filter(|(direction, _)| *direction != heading.opposite() || heading == CurrentDirection::Idle)
+            .min_by_key(|(_, next)| (*next - target).as_vec2().length_squared() as i64);
+
+        let Some((direction, next)) = next else { break };
+        route.push(next);
+        tile = next;
+        heading = direction;
+    }
+
+    route
+}
+
+/// Where a ghost is and which way it's going
+struct GhostView {
+    personality: GhostPersonality,
+    tile: IVec2,
+    heading: CurrentDirection,
+}
+
+/// Picks Pac-Man's next direction, None to keep going
+fn decide(grid: &MapGrid, pacman: &TileMover, data: &PlayerData, food: &HashSet<IVec2>, ghosts: &[GhostView], autopilot: &mut Autopilot) -> Option<CurrentDirection> {
+    let difficulty = autopilot.difficulty;
+    let everywhere = grid.width * grid.height;
+
+    if autopilot.rng.next_f32() < difficulty.mistakes {
+        let options: Vec<CurrentDirection> = neighbours(grid, pacman.tile).map(|(direction, _)| direction).collect();
+        return options.get(autopilot.rng.below(options.len())).copied()
+    }
+
+    let frightened = matches!(data.state, EntityState::Energized(time) if time > FRIGHT_MARGIN);
+
+    // Frightened ghosts are food
+    if frightened {
+        if difficulty.hunt_range > 0 {
+            let prey: HashSet<IVec2> = ghosts.iter().map(|ghost| ghost.tile).collect();
+            if let Some(direction) = first_step(grid, pacman.tile, &HashSet::new(), difficulty.hunt_range, |tile| prey.contains(&tile)) {
+                return Some(direction)
+            }
+        }
+
+        return first_step(grid, pacman.tile, &HashSet::new(), everywhere, |tile| food.contains(&tile))
+    }
+
+    // Around every ghost and along where it's going
+    let blinky = ghosts.iter().find(|ghost| ghost.personality == GhostPersonality::Blinky).map(|ghost| ghost.tile);
+    let mut danger = HashSet::new();
+    for ghost in ghosts {
+        let near = distances(grid, [ghost.tile].into_iter());
+        danger.extend(near.into_iter().filter(|(_, steps)| *steps as i32 <= difficulty.danger_radius).map(|(tile, _)| tile));
+
+        let target = predicted_target(ghost.personality, ghost.tile, pacman, blinky);
+        danger.extend(predicted_route(grid, ghost.tile, ghost.heading, target, difficulty.lookahead));
+    }
+    danger.remove(&pacman.tile);
+
+    if let Some(direction) = first_step(grid, pacman.tile, &danger, everywhere, |tile| food.contains(&tile)) {
+        return Some(direction)
+    }
+
+    // Cornered, get as far from the ghosts as possible
+    let from_ghosts = distances(grid, ghosts.iter().map(|ghost| ghost.tile));
+    neighbours(grid, pacman.tile)
+        .max_by_key(|(direction, next)| (from_ghosts.get(next).copied().unwrap_or(everywhere), *direction == pacman.heading))
+        .map(|(direction, _)| direction)
+}
+
+/// Set from the command line, plays one game with the autopilot as fast as possible then exits
+/// `--benchmark [--seed N] [--levels N] [--difficulty easy|normal|hard]`
+#[derive(Debug, Clone, Resource)]
+pub struct Benchmark {
+    pub seed: u64,
+    /// Stops once this many levels are cleared
+    pub levels: usize,
+    pub difficulty: BotDifficulty,
+    pub ticks: u64,
+    pub start: Instant,
+}
+
+impl Benchmark {
+    /// None unless --benchmark was passed
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        if !args.iter().any(|arg| arg == "--benchmark") { return None }
+
+        let value = |name: &str| args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1));
+
+        let difficulty = match value("--difficulty") {
+            Some(name) => BotDifficulty::preset(name).unwrap_or_else(|| { warn!("Unknown autopilot difficulty {:?}", name); BotDifficulty::load() }),
+            None => BotDifficulty::load(),
+        };
+
+        Some(Self {
+            seed: value("--seed").and_then(|seed| seed.parse().ok()).unwrap_or(0),
+            levels: value("--levels").and_then(|levels| levels.parse().ok()).unwrap_or(DEFAULT_BENCHMARK_LEVELS),
+            difficulty: difficulty,
+            ticks: 0,
+            start: Instant::now(),
+        })
+    }
+
+    fn report(&self, levels: usize, score: usize, reason: &str) {
+        info!("Benchmark seed {}: cleared {} level(s), score {}, {} ticks in {:.1?} ({})", self.seed, levels, score, self.ticks, self.start.elapsed(), reason);
+    }
+}
+
+/// Time left on the title screen before the demo
+#[derive(Resource)]
+pub struct AttractTimer(pub Timer);
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(AttractTimer(Timer::from_seconds(ATTRACT_TIME, TimerMode::Once)))
+            .add_system(Self::attract.in_set(OnUpdate(GameState::Title)))
+            .add_system(Self::seat_autopilot.in_schedule(OnExit(GameState::Title)))
+            .add_system(Self::drive.in_set(OnUpdate(GameState::Playing)).before(PlayerMovement::tick))
+            .add_system(Self::end_demo.in_set(OnUpdate(GameState::Playing)))
+            .add_system(Self::end_demo.in_set(OnUpdate(GameState::Ready)))
+            .add_system(Self::benchmark_tick.in_set(OnUpdate(GameState::Playing)))
+            .add_system(Self::benchmark_over.in_schedule(OnEnter(GameState::EnteringInitials)))
+
+        ;
+    }
+}
+
+impl BotPlugin {
+    /// Starts the demo after a while on the title screen (straight away when benchmarking)
+    pub fn attract(
+        mut commands: Commands,
+        time: Res<Time>,
+        actions: Res<ActionState>,
+        benchmark: Option<Res<Benchmark>>,
+        mut timer: ResMut<AttractTimer>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        if Action::ALL.iter().any(|action| actions.pressed(*action)) {
+            timer.0.reset();
+        }
+
+        timer.0.tick(time.delta());
+
+        if timer.0.finished() || benchmark.is_some() {
+            info!("Starting the demo");
+            timer.0.reset();
+            commands.insert_resource(Players::demo());
+            next_state.set(GameState::Ready);
+        }
+    }
+
+    /// Player one's Pac-Man is the autopilot's in a demo
+    pub fn seat_autopilot(
+        mut commands: Commands,
+        players: Res<Players>,
+        benchmark: Option<Res<Benchmark>>,
+        pacmen: Query<(Entity, &Seat), With<PlayerMovement>>,
+    ) {
+        let (difficulty, seed) = match benchmark {
+            Some(benchmark) => (benchmark.difficulty, benchmark.seed),
+            None => (BotDifficulty::load(), 0),
+        };
+
+        for (entity, seat) in &pacmen {
+            if players.demo && seat.0 == 0 {
+                commands.entity(entity).insert(Autopilot::new(difficulty, seed));
+            } else {
+                commands.entity(entity).remove::<Autopilot>();
+            }
+        }
+    }
+
+    pub fn drive(
+        grid: Res<MapGrid>,
+        current_map: Res<CurrentMap>,
+        mut bots: Query<(&mut Autopilot, &TileMover, &PlayerData, &mut MovementHelper), With<PlayerMovement>>,
+        food: Query<(&Food, &MapTile)>,
+        ghosts: Query<(&Ghost, &Transform, &MovementHelper), Without<PlayerMovement>>,
+    ) {
+        if bots.is_empty() { return }
+
+        let food: HashSet<IVec2> = food.iter().filter(|(food, _)| !food.is_eaten).map(|(_, tile)| tile.0).collect();
+        let ghosts: Vec<GhostView> = ghosts.iter()
+            .filter(|(ghost, _, _)| ghost.state == EntityState::Alive)
+            .map(|(ghost, transform, helper)| GhostView {
+                personality: ghost.personality,
+                tile: current_map.world_to_tile(transform.translation),
+                heading: helper.direction.unwrap_or(CurrentDirection::Idle),
+            })
+            .collect();
+
+        for (mut autopilot, mover, data, mut helper) in &mut bots {
+            if data.out { continue }
+
+            autopilot.ticks += 1;
+            if autopilot.ticks % autopilot.difficulty.think_every != 0 && helper.direction.is_some() { continue }
+
+            if let Some(direction) = decide(&grid, mover, data, &food, &ghosts, &mut autopilot) {
+                helper.direction = Some(direction);
+            }
+        }
+    }
+
+    /// Any key ends the demo, benchmarks can't be interrupted
+    pub fn end_demo(
+        actions: Res<ActionState>,
+        players: Res<Players>,
+        benchmark: Option<Res<Benchmark>>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        if !players.demo || benchmark.is_some() { return }
+
+        if [Action::Confirm, Action::Back, Action::Pause].into_iter().any(|action| actions.just_pressed(action)) {
+            info!("Demo over");
+            next_state.set(GameState::Title);
+        }
+    }
+
+    pub fn benchmark_tick(
+        benchmark: Option<ResMut<Benchmark>>,
+        player: Query<(&Seat, &PlayerData, &GameData), With<PlayerMovement>>,
+        mut exit: EventWriter<AppExit>,
+    ) {
+        let Some(mut benchmark) = benchmark else { return };
+        benchmark.ticks += 1;
+
+        let Some((_, data, game_data)) = player.iter().find(|(seat, _, _)| seat.0 == 0) else { return };
+        if game_data.level >= benchmark.levels {
+            benchmark.report(game_data.level, data.score, "level limit");
+            exit.send(AppExit);
+        }
+    }
+
+    pub fn benchmark_over(
+        benchmark: Option<Res<Benchmark>>,
+        player: Query<(&Seat, &PlayerData, &GameData), With<PlayerMovement>>,
+        mut exit: EventWriter<AppExit>,
+    ) {
+        let Some(benchmark) = benchmark else { return };
+        let Some((_, data, game_data)) = player.iter().find(|(seat, _, _)| seat.0 == 0) else { return };
+
+        benchmark.report(game_data.level, data.score, "game over");
+        exit.send(AppExit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One long corridor, x 1 - 14 on row 1
+    const CORRIDOR: &str = "################\n#..............#\n################\n";
+
+    fn at(x: i32) -> IVec2 {
+        IVec2::new(x, 1)
+    }
+
+    /// Mistakes are turned off so the choice is down to the rest of the difficulty
+    fn choose(preset: &str, state: EntityState, ghost: GhostView, food: &[i32]) -> Option<CurrentDirection> {
+        let grid = MapGrid::new(CORRIDOR);
+        let pacman = TileMover::new(at(5));
+        let data = PlayerData { state: state, ..default() };
+        let food: HashSet<IVec2> = food.iter().map(|x| at(*x)).collect();
+        let mut autopilot = Autopilot::new(BotDifficulty { mistakes: 0.0, ..BotDifficulty::preset(preset).unwrap() }, 1);
+
+        decide(&grid, &pacman, &data, &food, &[ghost], &mut autopilot)
+    }
+
+    #[test]
+    fn first_step_stays_in_range_and_off_blocked_tiles() {
+        let grid = MapGrid::new(CORRIDOR);
+        let goal = |tile: IVec2| tile == at(9);
+
+        assert_eq!(first_step(&grid, at(5), &HashSet::new(), 4, goal), Some(CurrentDirection::Right));
+        assert_eq!(first_step(&grid, at(5), &HashSet::new(), 3, goal), None);
+        assert_eq!(first_step(&grid, at(5), &HashSet::from([at(7)]), 20, goal), None);
+    }
+
+    #[test]
+    fn predicted_routes_never_turn_back() {
+        let grid = MapGrid::new(CORRIDOR);
+
+        assert_eq!(predicted_route(&grid, at(3), CurrentDirection::Right, at(10), 3), vec![at(4), at(5), at(6)]);
+        assert_eq!(predicted_route(&grid, at(3), CurrentDirection::Left, at(10), 5), vec![at(2), at(1)]);
+    }
+
+    #[test]
+    fn only_easy_walks_into_a_ghost() {
+        // The nearest dot is two tiles away, right where Blinky is coming from
+        let blinky = || GhostView { personality: GhostPersonality::Blinky, tile: at(1), heading: CurrentDirection::Right };
+
+        assert_eq!(choose("easy", EntityState::Alive, blinky(), &[3, 10]), Some(CurrentDirection::Left));
+        assert_eq!(choose("normal", EntityState::Alive, blinky(), &[3, 10]), Some(CurrentDirection::Right));
+        assert_eq!(choose("hard", EntityState::Alive, blinky(), &[3, 10]), Some(CurrentDirection::Right));
+    }
+
+    #[test]
+    fn only_hard_hunts_far_away() {
+        // A frightened ghost eight tiles away, a dot two tiles the other way
+        let blinky = || GhostView { personality: GhostPersonality::Blinky, tile: at(13), heading: CurrentDirection::Left };
+        let energized = EntityState::Energized(FRIGHT_MARGIN + 60);
+
+        assert_eq!(choose("easy", energized, blinky(), &[3]), Some(CurrentDirection::Left));
+        assert_eq!(choose("normal", energized, blinky(), &[3]), Some(CurrentDirection::Left));
+        assert_eq!(choose("hard", energized, blinky(), &[3]), Some(CurrentDirection::Right));
+    }
+
+    #[test]
+    fn difficulty_from_settings() {
+        let hard = BotDifficulty::preset("hard").unwrap();
+
+        assert_eq!(BotDifficulty::from_settings(&Value::Null), BotDifficulty::default());
+        assert_eq!(BotDifficulty::from_settings(&serde_json::json!({ "difficulty": "nightmare" })), BotDifficulty::default());
+        assert_eq!(
+            BotDifficulty::from_settings(&serde_json::json!({ "difficulty": "HARD", "lookahead": 2, "think_every": 0, "mistakes": 2.0, "danger_radius": -1 })),
+            BotDifficulty { lookahead: 2, think_every: 1, mistakes: 1.0, danger_radius: 0, ..hard }
+        );
+    }
+}
@@ -0,0 +1,439 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use bevy::{prelude::*, asset::FileAssetIo, ecs::system::SystemParam, render::{render_resource::{Extent3d, TextureDimension, TextureFormat}, texture::ImageSampler}};
+
+use super::{map::{asset::{is_map_asset, legacy_map, MapFile, MapPath, ASSET_MAP}, campaign::Campaign, analysis::blocking_problems, header::{map_hash, MapHeader}, render::{thumbnail, THUMBNAIL_SIZE}, tiled::{is_tiled, read_map}, MapEnforcer, TileMap, DEFAULT_MAP}, editor::MapEditor, score::HighScoreTable, players::TitleScreen, player::PlayerMovement, movement::TileMover, enemy::Ghost, text::{BitmapText, SetBitmapText, BitmapTextBundle, TextAlign}, input::{Action, ActionState, InputBindings}, assets::GameAssets, GameState, TILE_SIZE};
+
+/// Maps the player has added, next to settings.toml
+pub const USER_MAP_DIR: &str = "./maps";
+
+/// Maps that come with the game, in the assets folder
+pub const BUNDLED_MAP_DIR: &str = "maps";
+
+const BROWSER_Z: f32 = 950.0;
+
+/// Names either side of the selected one in the list
+const LIST_REACH: usize = 2;
+const ROW_GLYPHS: usize = 34;
+
+/// Shown at twice its size
+const THUMBNAIL_SCALE: f32 = 2.0;
+
+/// A map the browser found
+#[derive(Debug, Clone)]
+pub struct MapEntry {
+    /// The header's title, or the file name
+    pub name: String,
+    pub author: Option<String>,
+    /// None for the built in map
+    pub path: Option<PathBuf>,
+    /// Empty if the file couldn't be read
+    pub text: String,
+    /// Why it can't be played, it can't be picked while there are any
+    pub errors: Vec<String>,
+    /// Best local score and initials
+    pub best: Option<(usize, String)>,
+    /// None until the map is first selected, drawing every map up front stalls on big folders
+    /// Then None inside if it can't be drawn
+    pub thumbnail: Option<Option<Handle<Image>>>,
+}
+
+impl MapEntry {
+    /// Only the quick checks, the full analysis is left to the editor
+    fn new(name: String, path: Option<PathBuf>, text: anyhow::Result<String>, table: &HighScoreTable) -> Self {
+        let (text, errors) = match text {
+            Ok(text) => { let errors = blocking_problems(&text); (text, errors) },
+            Err(e) => { warn!("Can't read {}: {}", name, e); (String::new(), vec!["CAN NOT BE READ".to_string()]) },
+        };
+
+        let header = MapHeader::parse(&text).ok().map(|(header, _)| header);
+        let best = table.best(&map_hash(&text)).map(|entry| (entry.score, entry.initials.clone()));
+
+        Self {
+            name: header.as_ref().and_then(|header| header.title.clone()).unwrap_or(name),
+            author: header.and_then(|header| header.author),
+            path: path,
+            text: text,
+            errors: errors,
+            best: best,
+            thumbnail: None,
+        }
+    }
+
+    pub fn is_playable(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Draws the thumbnail the first time
+    fn draw_thumbnail(&mut self, images: &mut Assets<Image>) {
+        if self.thumbnail.is_some() { return }
+
+        self.thumbnail = Some(thumbnail(&self.text, THUMBNAIL_SIZE).ok().map(|image| {
+            let size = Extent3d { width: image.width(), height: image.height(), depth_or_array_layers: 1 };
+            let mut image = Image::new(size, TextureDimension::D2, image.into_raw(), TextureFormat::Rgba8UnormSrgb);
+            image.sampler_descriptor = ImageSampler::nearest();
+            images.add(image)
+        }));
+    }
+}
+
+/// .map and Tiled files in a folder, by file name
+fn map_files(folder: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(folder) else { return Vec::new() };
+
+    let mut files: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && (path.extension().is_some_and(|extension| extension == "map") || is_tiled(path)))
+        .collect();
+
+    files.sort();
+    files
+}
+
+/// The map picked in the browser, shown on the title screen
+#[derive(Debug, Clone, Resource)]
+pub struct ChosenMap {
+    pub name: String,
+    pub path: Option<PathBuf>,
+}
+
+/// Every map that can be picked, only exists in GameState::MapSelect
+#[derive(Debug, Clone, Resource)]
+pub struct MapBrowser {
+    pub entries: Vec<MapEntry>,
+    pub selected: usize,
+}
+
+impl MapBrowser {
+    /// The built in map, then the bundled maps, then the player's own
+    pub fn scan(table: &HighScoreTable) -> Self {
+        let mut entries = vec![MapEntry::new("ARCADE".to_string(), None, Ok(DEFAULT_MAP.to_string()), table)];
+
+        let assets = FileAssetIo::get_base_path().join("assets");
+        let mut files = map_files(&assets.join(BUNDLED_MAP_DIR));
+        if assets.join(ASSET_MAP).exists() { files.push(assets.join(ASSET_MAP)) }
+        files.extend(legacy_map());
+        files.extend(map_files(Path::new(USER_MAP_DIR)));
+
+        for path in files {
+            // Absolute like MapPath, so picking it again finds it
+            let path = fs::canonicalize(&path).unwrap_or(path);
+            let name = path.file_stem().map(|stem| stem.to_string_lossy().to_uppercase()).unwrap_or_default();
+            entries.push(MapEntry::new(name, Some(path.clone()), read_map(&path), table));
+        }
+
+        Self { entries: entries, selected: 0 }
+    }
+
+    pub fn selected(&self) -> &MapEntry {
+        &self.entries[self.selected]
+    }
+
+    /// Wraps around either end, the new map's thumbnail is drawn if it hasn't been
+    pub fn select(&mut self, index: isize, images: &mut Assets<Image>) {
+        self.selected = index.rem_euclid(self.entries.len() as isize) as usize;
+        self.entries[self.selected].draw_thumbnail(images);
+    }
+}
+
+/// What changes when a map is picked
+#[derive(SystemParam)]
+pub struct MapSwitch<'w, 's> {
+    assets: Res<'w, GameAssets>,
+    asset_server: Res<'w, AssetServer>,
+    old_map: Query<'w, 's, Entity, With<MapEnforcer>>,
+    ghosts: Query<'w, 's, Entity, With<Ghost>>,
+    pacmen: Query<'w, 's, &'static mut TileMover, With<PlayerMovement>>,
+}
+
+impl MapSwitch<'_, '_> {
+    /// Builds the map in place of the old one and plays it from now on
+    fn play(&mut self, commands: &mut Commands, entry: &MapEntry) {
+        let (_, spawn) = TileMap::replace(commands, &self.assets, &entry.text, self.old_map.iter().chain(self.ghosts.iter()), &[]);
+        commands.insert_resource(Campaign::single(entry.text.clone()));
+
+        if let Some(spawn) = spawn {
+            for mut mover in &mut self.pacmen {
+                mover.place(spawn);
+            }
+        }
+
+        // Same as --map, so the editor and hot reloading work on it
+        match &entry.path {
+            Some(path) => {
+                commands.insert_resource(MapPath(path.clone()));
+                match is_map_asset(path) {
+                    true => commands.insert_resource(MapFile(self.asset_server.load(path.clone()))),
+                    false => commands.remove_resource::<MapFile>(),
+                }
+            },
+            None => {
+                commands.remove_resource::<MapPath>();
+                commands.remove_resource::<MapFile>();
+            },
+        }
+    }
+}
+
+/// Marks everything spawned for the browser's screen
+#[derive(Component)]
+pub struct BrowserScreen;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowserText {
+    Name, Author, Details,
+    /// Offset from the selected map in the list
+    Row(isize),
+}
+
+#[derive(Component)]
+pub struct BrowserThumbnail;
+
+pub struct MapBrowserPlugin;
+
+impl Plugin for MapBrowserPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(Self::title_line.in_schedule(OnEnter(GameState::Title)))
+            .add_system(Self::open.in_set(OnUpdate(GameState::Title)))
+            .add_system(Self::begin.in_schedule(OnEnter(GameState::MapSelect)))
+            .add_system(Self::input.in_set(OnUpdate(GameState::MapSelect)))
+            .add_system(Self::display.in_set(OnUpdate(GameState::MapSelect)).after(Self::input))
+            .add_system(Self::finish.in_schedule(OnExit(GameState::MapSelect)))
+
+        ;
+    }
+}
+
+impl MapBrowserPlugin {
+    /// Which map is up, and how to change it
+    pub fn title_line(mut commands: Commands, chosen: Option<Res<ChosenMap>>, bindings: Res<InputBindings>) {
+        let key = bindings.get(Action::Maps).first().map(|binding| binding.label()).unwrap_or_default();
+        let label = match chosen {
+            Some(chosen) => format!("{} - {}", key, chosen.name),
+            None => format!("{} - MAPS", key),
+        };
+
+        let text = BitmapText::new(label).with_colour(Color::CYAN).with_align(TextAlign::Centre).with_width(ROW_GLYPHS);
+        commands.spawn(BitmapTextBundle::new(text, Vec3::new(0.0, 8.0 * TILE_SIZE * 2.0 * -7.5, BROWSER_Z), TILE_SIZE)).insert(TitleScreen);
+    }
+
+    /// Maps (M) on the title screen
+    pub fn open(actions: Res<ActionState>, mut next_state: ResMut<NextState<GameState>>) {
+        if actions.just_pressed(Action::Maps) {
+            next_state.set(GameState::MapSelect);
+        }
+    }
+
+    pub fn begin(mut commands: Commands, table: Res<HighScoreTable>, mut images: ResMut<Assets<Image>>, chosen: Option<Res<ChosenMap>>) {
+        let mut browser = MapBrowser::scan(&table);
+        let selected = chosen.and_then(|chosen| browser.entries.iter().position(|entry| entry.path == chosen.path)).unwrap_or(0);
+        browser.select(selected as isize, &mut images);
+
+        info!("Found {} map(s)", browser.entries.len());
+        commands.insert_resource(browser);
+
+        let scale = TILE_SIZE;
+        let text = |text: BitmapText, y: f32| BitmapTextBundle::new(text.with_align(TextAlign::Centre).with_width(ROW_GLYPHS), Vec3::new(0.0, y, BROWSER_Z), scale);
+
+        // Hides the maze behind
+        commands.spawn(SpriteBundle {
+            sprite: Sprite { color: Color::BLACK, custom_size: Some(Vec2::splat(10000.0)), ..Default::default() },
+            transform: Transform::from_xyz(0.0, 0.0, BROWSER_Z - 20.0),
+            ..Default::default()
+        }).insert(BrowserScreen);
+
+        commands.spawn(text(BitmapText::new("SELECT A MAP").with_colour(Color::YELLOW), 340.0)).insert(BrowserScreen);
+        commands.spawn(SpriteBundle {
+            transform: Transform::from_xyz(0.0, 170.0, BROWSER_Z - 10.0).with_scale(Vec3::splat(THUMBNAIL_SCALE)),
+            ..Default::default()
+        }).insert(BrowserScreen).insert(BrowserThumbnail);
+
+        commands.spawn(text(BitmapText::new(""), 10.0)).insert(BrowserScreen).insert(BrowserText::Name);
+        commands.spawn(text(BitmapText::new(""), -20.0)).insert(BrowserScreen).insert(BrowserText::Author);
+        commands.spawn(text(BitmapText::new(""), -50.0)).insert(BrowserScreen).insert(BrowserText::Details);
+
+        for offset in -(LIST_REACH as isize)..=LIST_REACH as isize {
+            commands.spawn(text(BitmapText::new(""), -200.0 - offset as f32 * 30.0)).insert(BrowserScreen).insert(BrowserText::Row(offset));
+        }
+    }
+
+    /// Up/Down picks a map, Confirm plays on it, Pause or Back leave it as it was
+    pub fn input(
+        mut commands: Commands,
+        actions: Res<ActionState>,
+        browser: Option<ResMut<MapBrowser>>,
+        mut images: ResMut<Assets<Image>>,
+        editor: Option<Res<MapEditor>>,
+        mut switch: MapSwitch,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        let Some(mut browser) = browser else { return };
+        let selected = browser.selected as isize;
+
+        if actions.just_pressed(Action::Up) {
+            browser.select(selected - 1, &mut images);
+        }
+
+        if actions.just_pressed(Action::Down) {
+            browser.select(selected + 1, &mut images);
+        }
+
+        if actions.just_pressed(Action::Back) || actions.just_pressed(Action::Pause) {
+            next_state.set(GameState::Title);
+        }
+
+        if !actions.just_pressed(Action::Confirm) || !browser.selected().is_playable() { return }
+
+        let entry = browser.selected().clone();
+        info!("Playing {} next", entry.name);
+
+        switch.play(&mut commands, &entry);
+
+        // The editor picks up the new map next time it's opened
+        if editor.is_some_and(|editor| editor.dirty) { warn!("Dropping unsaved changes in the editor") }
+        commands.remove_resource::<MapEditor>();
+
+        commands.insert_resource(ChosenMap { name: entry.name, path: entry.path });
+        next_state.set(GameState::Title);
+    }
+
+    pub fn display(
+        browser: Option<Res<MapBrowser>>,
+        mut texts: Query<(&BrowserText, &mut BitmapText)>,
+        mut thumbnails: Query<(&mut Handle<Image>, &mut Visibility), With<BrowserThumbnail>>,
+    ) {
+        let Some(browser) = browser else { return };
+        if !browser.is_changed() { return }
+
+        let entry = browser.selected();
+
+        for (kind, mut text) in &mut texts {
+            match kind {
+                BrowserText::Name => {
+                    text.set(entry.name.clone());
+                    text.set_colour(if entry.is_playable() { Color::YELLOW } else { Color::GRAY });
+                },
+                BrowserText::Author => {
+                    text.set(entry.author.as_ref().map(|author| format!("BY {}", author)).unwrap_or_default());
+                },
+                BrowserText::Details => {
+                    // The font has no comma
+                    let (details, details_colour) = match (entry.is_playable(), &entry.best) {
+                        (false, _) => (entry.errors.join(" - "), Color::RED),
+                        (true, Some((score, initials))) => (format!("BEST {} {}", score, initials), Color::WHITE),
+                        (true, None) => ("NO SCORES YET".to_string(), Color::WHITE),
+                    };
+                    text.set(details);
+                    text.set_colour(details_colour);
+                },
+                BrowserText::Row(offset) => {
+                    let row = usize::try_from(browser.selected as isize + offset).ok().and_then(|index| browser.entries.get(index));
+                    match row {
+                        Some(row) => {
+                            text.set(row.name.clone());
+                            text.set_colour(if !row.is_playable() { Color::GRAY } else if *offset == 0 { Color::YELLOW } else { Color::WHITE });
+                        },
+                        None => text.set(""),
+                    }
+                },
+            }
+        }
+
+        for (mut handle, mut visibility) in &mut thumbnails {
+            match entry.thumbnail.clone().flatten() {
+                Some(thumbnail) => { *handle = thumbnail; *visibility = Visibility::Visible },
+                None => *visibility = Visibility::Hidden,
+            }
+        }
+    }
+
+    /// The thumbnails go with the browser
+    pub fn finish(mut commands: Commands, screen: Query<Entity, With<BrowserScreen>>) {
+        for entity in &screen {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        commands.remove_resource::<MapBrowser>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{score::HighScoreEntry, env::{EnvAction, EnvConfig, PacmanEnv}};
+
+    fn entry(text: anyhow::Result<String>, table: &HighScoreTable) -> MapEntry {
+        MapEntry::new("FILE".to_string(), None, text, table)
+    }
+
+    #[test]
+    fn entries_show_the_header_and_best_score() {
+        let text = "---\ntitle = \"Tiny\"\nauthor = \"Me\"\n---\n#####\n#S..#\n#####\n".to_string();
+        let mut table = HighScoreTable::default();
+        table.maps.insert(map_hash(&text), vec![HighScoreEntry { initials: "AAA".to_string(), score: 120, level: 1, date: String::new(), map_hash: map_hash(&text), settings_hash: String::new(), ranked: true }]);
+
+        let entry = entry(Ok(text), &table);
+
+        assert_eq!((entry.name.as_str(), entry.author.as_deref()), ("Tiny", Some("Me")));
+        assert_eq!(entry.best, Some((120, "AAA".to_string())));
+        assert!(entry.is_playable());
+        assert_eq!(entry.thumbnail, None);
+    }
+
+    #[test]
+    fn entries_list_every_error() {
+        let table = HighScoreTable::default();
+
+        assert_eq!(entry(Ok("#####\n#   #\n#####\n".to_string()), &table).errors, ["NO SPAWN", "NO FOOD"]);
+        assert_eq!(entry(Err(anyhow::anyhow!("gone")), &table).errors, ["CAN NOT BE READ"]);
+
+        let unnamed = entry(Ok("#S.#\n".to_string()), &table);
+        assert_eq!((unnamed.name.as_str(), unnamed.author), ("FILE", None));
+    }
+
+    #[test]
+    fn maps_without_pinky_can_be_played() {
+        let text = include_str!("../tests/blinky_ai_test_level.map");
+        assert!(entry(Ok(text.to_string()), &HighScoreTable::default()).is_playable());
+
+        // Picking it builds the maze and spawns Blinky
+        let mut env = PacmanEnv::new(EnvConfig { noop_max: 0, ..Default::default() });
+        env.reset(0, Some(text));
+        assert!(!env.step(EnvAction::Noop).done);
+    }
+
+    #[test]
+    fn finds_maps_by_extension() {
+        let folder = std::env::temp_dir().join(format!("pacman-browser-{}", std::process::id()));
+        fs::create_dir_all(folder.join("nested.map")).unwrap();
+        for file in ["b.tmx", "a.map", "notes.txt", "c.json"] {
+            fs::write(folder.join(file), "").unwrap();
+        }
+
+        let files = map_files(&folder);
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(files, ["a.map", "b.tmx", "c.json"].map(|file| folder.join(file)));
+        assert!(map_files(&folder).is_empty());
+    }
+
+    #[test]
+    fn thumbnails_are_drawn_when_selected() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_plugin(AssetPlugin::default()).add_asset::<Image>();
+        let mut images = app.world.resource_mut::<Assets<Image>>();
+
+        let table = HighScoreTable::default();
+        let mut browser = MapBrowser { entries: vec![entry(Ok(DEFAULT_MAP.to_string()), &table), entry(Err(anyhow::anyhow!("gone")), &table)], selected: 0 };
+
+        browser.select(-1, &mut images);
+        assert_eq!(browser.selected, 1);
+        assert_eq!(browser.selected().thumbnail, Some(None));
+        assert_eq!(browser.entries[0].thumbnail, None);
+
+        browser.select(2, &mut images);
+        assert_eq!(browser.selected, 0);
+        assert!(matches!(browser.selected().thumbnail, Some(Some(_))));
+        assert_eq!(images.len(), 1);
+    }
+}
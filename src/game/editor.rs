@@ -0,0 +1,622 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{prelude::*, asset::{FileAssetIo, LoadState}, ecs::system::SystemParam, input::mouse::MouseWheel, window::PrimaryWindow};
+
+use super::{map::{analysis::{analyze, blocking_problems}, tiled::is_tiled, asset::{MapAsset, MapFile, MapPath, ASSET_MAP}, campaign::Campaign, header::MapHeader, CurrentMap, MapEnforcer, TileMap}, enemy::Ghost, players::{Players, PlayersPlugin}, bot::BotPlugin, input::{Action, ActionState}, text::{BitmapText, SetBitmapText, BitmapTextBundle}, assets::GameAssets, GameController, GameState, TILE_SIZE};
+
+const EDITOR_Z: f32 = 960.0;
+
+/// One glyph takes up one tile, like the HUD
+const GLYPH_SCALE: f32 = TILE_SIZE * 10.0 / 8.0;
+const TILE_SPACING: f32 = TILE_SIZE * 10.0;
+
+/// Strokes kept for undo
+const MAX_UNDO: usize = 100;
+
+/// Everything that can be placed, in palette order, 1 - 0 pick the first ten
+pub const PALETTE: [(char, &str); 14] = [
+    ('#', "WALL"),
+    ('.', "DOT"),
+    ('@', "POWER PELLET"),
+    ('F', "FRUIT"),
+    ('G', "GATE"),
+    ('H', "HOUSE"),
+    ('S', "SPAWN"),
+    ('B', "BLINKY"),
+    ('P', "PINKY"),
+    ('I', "INKY"),
+    ('C', "CLYDE"),
+    ('|', "TELEPORT"),
+    ('~', "GHOST FORCE"),
+    (' ', "EMPTY"),
+];
+
+const TOOL_KEYS: [KeyCode; 10] = [
+    KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5,
+    KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9, KeyCode::Key0,
+];
+
+/// Something wrong with a map, blocking ones stop it being played
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub message: String,
+    pub blocking: bool,
+}
+
+impl Problem {
+    fn blocking(message: impl Into<String>) -> Self {
+        Self { message: message.into(), blocking: true }
+    }
+
+    fn warning(message: impl Into<String>) -> Self {
+        Self { message: message.into(), blocking: false }
+    }
+}
+
+/// Everything wrong with a map, blocking problems first
+pub fn problems(map: &str) -> Vec<Problem> {
+    let mut problems: Vec<Problem> = blocking_problems(map).into_iter().map(Problem::blocking).collect();
+
+    let Ok((_, body)) = MapHeader::split(map) else { return problems };
+    let count = |c: char| body.chars().filter(|tile| *tile == c).count();
+
+    if count('S') > 1 { problems.push(Problem::warning(format!("{} SPAWNS", count('S')))) }
+    if count('|') % 2 == 1 { problems.push(Problem::warning("A TELEPORT HAS NO PAIR")) }
+    if count('#') == 0 { problems.push(Problem::warning("NO WALLS")) }
+    if count('@') == 0 { problems.push(Problem::warning("NO POWER PELLETS")) }
+    if count('F') == 0 { problems.push(Problem::warning("NO FRUIT")) }
+    if count('G') == 0 { problems.push(Problem::warning("NO GHOST HOUSE GATE")) }
+
+    for (c, name) in [('B', "BLINKY"), ('P', "PINKY"), ('I', "INKY"), ('C', "CLYDE")] {
+        if count(c) == 0 { problems.push(Problem::warning(format!("NO {}", name))) }
+    }
+    // Inky is left out of the game, see Ghost::spawn
+    if count('I') > 0 && count('P') == 0 { problems.push(Problem::warning("INKY NEEDS PINKY")) }
+
+    // Same checks as `pacman analyze`
+    if let Ok(report) = analyze(map) {
+        if report.spawn.is_some() && !report.unreachable_food.is_empty() { problems.push(Problem::warning(format!("{} DOTS CAN'T BE REACHED", report.unreachable_food.len()))) }
+        if !report.dead_ends.is_empty() { problems.push(Problem::warning(format!("{} DEAD ENDS", report.dead_ends.len()))) }
+        if !report.wide_corridors.is_empty() { problems.push(Problem::warning("CORRIDORS WIDER THAN A TILE")) }
+    }
+
+    problems
+}
+
+/// The map being edited, it lives on when the editor is closed so nothing is lost going back to the title
+#[derive(Debug, Clone, Resource)]
+pub struct MapEditor {
+    /// The header exactly as written, it isn't edited here
+    header: String,
+    /// Always rectangular, trailing spaces are dropped when saving
+    rows: Vec<Vec<char>>,
+    /// Index into PALETTE
+    pub tool: usize,
+    undo: Vec<Vec<Vec<char>>>,
+    redo: Vec<Vec<Vec<char>>>,
+    /// The rows from before the stroke the mouse is drawing, until it changes something
+    stroke: Option<Vec<Vec<char>>>,
+    pub path: PathBuf,
+    /// Changed since it was saved
+    pub dirty: bool,
+    /// The maze needs building again
+    rebuild: bool,
+    /// Checked again only when the rows change, analyzing every frame stalls on big maps
+    problems: Vec<Problem>,
+    pub message: Option<(String, Color)>,
+}
+
+impl MapEditor {
+    pub fn new(map: &str, path: PathBuf) -> Self {
+        // Without a closing fence nothing can be told apart, it all goes in the header and is saved back as it was
+        let split = MapHeader::split(map);
+        let body = split.as_ref().map_or("", |(_, body)| *body);
+        let header = map[..map.len() - body.len()].to_string();
+        let message = split.is_err().then(|| ("HEADER NEVER CLOSED".to_string(), Color::RED));
+
+        let mut rows: Vec<Vec<char>> = body.lines().map(|line| line.chars().collect()).collect();
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        for row in &mut rows {
+            row.resize(width, ' ');
+        }
+
+        let mut editor = Self { header: header, rows: rows, tool: 0, undo: Vec::new(), redo: Vec::new(), stroke: None, path: path, dirty: false, rebuild: true, problems: Vec::new(), message: message };
+        editor.problems = problems(&editor.text());
+        editor
+    }
+
+    /// The map as it would be saved
+    pub fn text(&self) -> String {
+        let mut text = self.header.clone();
+
+        for row in &self.rows {
+            text.push_str(row.iter().collect::<String>().trim_end());
+            text.push('\n');
+        }
+
+        text
+    }
+
+    pub fn get(&self, tile: IVec2) -> Option<char> {
+        if tile.x < 0 || tile.y < 0 { return None }
+        self.rows.get(tile.y as usize).and_then(|row| row.get(tile.x as usize)).copied()
+    }
+
+    /// Only inside the map, false if nothing changed
+    pub fn paint(&mut self, tile: IVec2, c: char) -> bool {
+        if self.get(tile).is_none_or(|existing| existing == c) { return false }
+
+        // The first change of a stroke is where it's undone to
+        if let Some(before) = self.stroke.take() {
+            self.undo.push(before);
+            if self.undo.len() > MAX_UNDO { self.undo.remove(0); }
+            self.redo.clear();
+        }
+
+        self.rows[tile.y as usize][tile.x as usize] = c;
+        self.changed();
+        true
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let Some(rows) = self.undo.pop() else { return false };
+        self.redo.push(std::mem::replace(&mut self.rows, rows));
+        self.changed();
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(rows) = self.redo.pop() else { return false };
+        self.undo.push(std::mem::replace(&mut self.rows, rows));
+        self.changed();
+        true
+    }
+
+    fn changed(&mut self) {
+        self.dirty = true;
+        self.rebuild = true;
+        self.problems = problems(&self.text());
+    }
+
+    /// Everything wrong with the map as it is now, blocking problems first
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    pub fn save(&mut self) -> std::io::Result<()> {
+        fs::write(&self.path, self.text())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// The file name as the font can draw it
+    fn name(&self) -> String {
+        self.path.file_stem().map(|name| name.to_string_lossy().to_uppercase()).unwrap_or_default()
+    }
+}
+
+/// Open the editor once the title screen is up, from `--editor`
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct OpenEditor;
+
+/// A test play from the editor is running, holds the campaign to put back afterwards
+#[derive(Debug, Clone, Resource)]
+pub struct TestPlay(pub Campaign);
+
+/// Marks everything spawned for the editor's screen
+#[derive(Component)]
+pub struct EditorScreen;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorText {
+    Title, Tool, Problems, Message
+}
+
+/// Shows the tile under the mouse
+#[derive(Component)]
+pub struct EditorCursor;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(Self::open.in_set(OnUpdate(GameState::Title)))
+            .add_system(Self::begin.in_schedule(OnEnter(GameState::Editor)))
+            .add_system(Self::input.in_set(OnUpdate(GameState::Editor)))
+            .add_system(Self::redraw.in_set(OnUpdate(GameState::Editor)).after(Self::input))
+            .add_system(Self::display.in_set(OnUpdate(GameState::Editor)).after(Self::redraw).after(BitmapText::layout))
+            .add_system(Self::finish.in_schedule(OnExit(GameState::Editor)))
+            .add_system(Self::stop_test.in_set(OnUpdate(GameState::Ready)))
+            .add_system(Self::stop_test.in_set(OnUpdate(GameState::Playing)))
+
+            // A test play starts straight from the editor, the same as leaving the title
+            .add_system(GameController::new_game.in_schedule(OnExit(GameState::Editor)))
+            .add_system(PlayersPlugin::seat_pacmen.in_schedule(OnExit(GameState::Editor)))
+            .add_system(PlayersPlugin::seat_ghost.in_schedule(OnExit(GameState::Editor)))
+            // The title screen may have handed Pac-Man to the autopilot after a demo
+            .add_system(BotPlugin::seat_autopilot.in_schedule(OnExit(GameState::Editor)))
+
+        ;
+    }
+}
+
+impl EditorPlugin {
+    /// Editor (E) on the title screen, or straight away with `--editor` once the map file is in
+    pub fn open(
+        mut commands: Commands,
+        actions: Res<ActionState>,
+        request: Option<Res<OpenEditor>>,
+        map_file: Option<Res<MapFile>>,
+        asset_server: Res<AssetServer>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        let loading = map_file.is_some_and(|map_file| asset_server.get_load_state(&map_file.0) == LoadState::Loading);
+        if !actions.just_pressed(Action::Editor) && (request.is_none() || loading) { return }
+
+        commands.remove_resource::<OpenEditor>();
+        next_state.set(GameState::Editor);
+    }
+
+    /// Picks up the map being played the first time, and puts the campaign back after a test play
+    pub fn begin(
+        mut commands: Commands,
+        editor: Option<ResMut<MapEditor>>,
+        test_play: Option<Res<TestPlay>>,
+        campaign: Option<Res<Campaign>>,
+        map_file: Option<Res<MapFile>>,
+        maps: Res<Assets<MapAsset>>,
+        map_path: Option<Res<MapPath>>,
+    ) {
+        if let Some(test_play) = test_play {
+            commands.insert_resource(test_play.0.clone());
+            commands.remove_resource::<TestPlay>();
+        }
+
+        if let Some(mut editor) = editor {
+            editor.rebuild = true;
+            return
+        }
+
+        let path = match map_path {
+            // Tiled maps are saved as a .map next to them, they can't be written back
+            Some(map_path) if is_tiled(&map_path.0) => map_path.0.with_extension("map"),
+            Some(map_path) => map_path.0.clone(),
+            None => FileAssetIo::get_base_path().join("assets").join(ASSET_MAP),
+        };
+
+        let map = match (map_file.and_then(|map_file| maps.get(&map_file.0)), campaign) {
+            (Some(map), _) => map.text.clone(),
+            (None, Some(campaign)) => campaign.map(0).to_string(),
+            (None, None) => String::new(),
+        };
+
+        info!("Editing {}", path.display());
+        commands.insert_resource(MapEditor::new(&map, path));
+    }
+
+    /// Left click paints with the tool, right click clears, 1 - 0 and the wheel pick the tool
+    /// Undo (Ctrl+Z), Redo (Ctrl+Y), Save (Ctrl+S), TestPlay (F5), and Pause or Back go back to the title
+    pub fn input(
+        mut commands: Commands,
+        keyboard: Res<Input<KeyCode>>,
+        actions: Res<ActionState>,
+        mut mouse: EditorMouse,
+        campaign: Res<Campaign>,
+        editor: Option<ResMut<MapEditor>>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        let Some(mut editor) = editor else { return };
+        if let Some(tool) = TOOL_KEYS.iter().position(|key| keyboard.just_pressed(*key)) {
+            editor.tool = tool;
+        }
+
+        let scrolled: f32 = mouse.wheel.iter().map(|event| event.y).sum();
+        if scrolled != 0.0 {
+            let step = if scrolled > 0.0 { PALETTE.len() - 1 } else { 1 };
+            editor.tool = (editor.tool + step) % PALETTE.len();
+        }
+
+        let mouse_buttons = &mouse.buttons;
+        if let Some(tile) = mouse.hovered_tile() {
+            if mouse_buttons.any_just_pressed([MouseButton::Left, MouseButton::Right]) {
+                editor.stroke = Some(editor.rows.clone());
+            }
+
+            if mouse_buttons.pressed(MouseButton::Left) {
+                let c = PALETTE[editor.tool].0;
+                editor.paint(tile, c);
+            } else if mouse_buttons.pressed(MouseButton::Right) {
+                editor.paint(tile, ' ');
+            }
+        }
+
+        if !mouse_buttons.any_pressed([MouseButton::Left, MouseButton::Right]) {
+            editor.stroke = None;
+        }
+
+        if actions.just_pressed(Action::Undo) && !editor.undo() {
+            editor.message = Some(("NOTHING TO UNDO".to_string(), Color::WHITE));
+        }
+
+        if actions.just_pressed(Action::Redo) && !editor.redo() {
+            editor.message = Some(("NOTHING TO REDO".to_string(), Color::WHITE));
+        }
+
+        if actions.just_pressed(Action::Save) {
+            editor.message = match editor.save() {
+                Ok(()) => { info!("Saved the map to {}", editor.path.display()); Some(("SAVED".to_string(), Color::GREEN)) },
+                Err(e) => { error!("Failed to save the map to {}: {}", editor.path.display(), e); Some(("SAVE FAILED".to_string(), Color::RED)) },
+            };
+        }
+
+        if actions.just_pressed(Action::TestPlay) {
+            if editor.problems().iter().any(|problem| problem.blocking) {
+                editor.message = Some(("FIX THE MAP FIRST".to_string(), Color::RED));
+            } else {
+                info!("Test playing the map");
+                commands.insert_resource(TestPlay(campaign.clone()));
+                commands.insert_resource(Campaign::single(editor.text()));
+                commands.insert_resource(Players::new(1));
+                next_state.set(GameState::Ready);
+            }
+        }
+
+        if actions.just_pressed(Action::Pause) || actions.just_pressed(Action::Back) {
+            if editor.dirty { info!("Leaving the editor with unsaved changes, they're kept until the game closes") }
+            next_state.set(GameState::Title);
+        }
+    }
+
+    /// Builds the maze again after a change, it looks just like it will in game
+    pub fn redraw(
+        mut commands: Commands,
+        assets: Res<GameAssets>,
+        editor: Option<ResMut<MapEditor>>,
+        old_map: Query<Entity, With<MapEnforcer>>,
+        ghosts: Query<Entity, With<Ghost>>,
+    ) {
+        let Some(mut editor) = editor else { return };
+        if !editor.rebuild { return }
+
+        editor.rebuild = false;
+        TileMap::replace(&mut commands, &assets, &editor.text(), old_map.iter().chain(ghosts.iter()), &[]);
+    }
+
+    /// The editor's rows go over the HUD, they're laid out again when the map changes size
+    /// After the text layout, so text despawned here has already been given its glyphs
+    pub fn display(
+        mut commands: Commands,
+        mut laid_out: Local<Option<UVec2>>,
+        editor: Option<Res<MapEditor>>,
+        mouse: EditorMouse,
+        screen: Query<Entity, With<EditorScreen>>,
+        mut texts: Query<(&EditorText, &mut BitmapText)>,
+        mut cursor: Query<(&mut Transform, &mut Visibility), With<EditorCursor>>,
+    ) {
+        let Some(editor) = editor else { return };
+        let current_map = &mouse.current_map;
+
+        let size = UVec2::new(current_map.width as u32, current_map.height as u32);
+        if *laid_out != Some(size) || screen.is_empty() {
+            for entity in &screen {
+                commands.entity(entity).despawn_recursive();
+            }
+
+            Self::spawn_screen(&mut commands, current_map);
+            *laid_out = Some(size);
+            return
+        }
+
+        let problems = editor.problems();
+
+        for (kind, mut text) in &mut texts {
+            match kind {
+                EditorText::Title => {
+                    text.set(format!("EDITOR - {}{}", editor.name(), if editor.dirty { " - UNSAVED" } else { "" }));
+                },
+                EditorText::Tool => {
+                    let (_, name) = PALETTE[editor.tool];
+                    let key = if editor.tool < TOOL_KEYS.len() { format!("{}", (editor.tool + 1) % 10) } else { "-".to_string() };
+                    text.set(format!("{} {}", key, name));
+                    text.set_colour(Color::YELLOW);
+                },
+                EditorText::Problems => {
+                    match problems.first() {
+                        None => { text.set("MAP OK"); text.set_colour(Color::GREEN) },
+                        Some(first) => {
+                            let more = if problems.len() > 1 { format!(" AND {} MORE", problems.len() - 1) } else { String::new() };
+                            text.set(format!("{}{}", first.message, more));
+                            text.set_colour(if first.blocking { Color::RED } else { Color::YELLOW });
+                        },
+                    }
+                },
+                EditorText::Message => {
+                    let (message, colour) = editor.message.clone().unwrap_or(("F5 TEST PLAY".to_string(), Color::WHITE));
+                    text.set(message);
+                    text.set_colour(colour);
+                },
+            }
+        }
+
+        let hovered = mouse.hovered_tile().filter(|tile| editor.get(*tile).is_some());
+        for (mut transform, mut visibility) in &mut cursor {
+            match hovered {
+                Some(tile) => {
+                    let pos = current_map.tile_pos(tile.x as f32, tile.y as f32);
+                    transform.translation = Vec3 { x: pos.x, y: pos.y, z: EDITOR_Z };
+                    *visibility = Visibility::Visible;
+                },
+                None => *visibility = Visibility::Hidden,
+            }
+        }
+    }
+
+    fn spawn_screen(commands: &mut Commands, current_map: &CurrentMap) {
+        let width = current_map.width.max(1) as f32;
+        let height = current_map.height as f32;
+        let left = current_map.tile_pos(0.0, 0.0).x;
+        let centre = current_map.tile_pos((width - 1.0) / 2.0, 0.0).x;
+
+        // Covers the HUD, two rows above the maze and two below
+        for y in [-1.5, height + 0.5] {
+            commands.spawn(SpriteBundle {
+                sprite: Sprite { color: Color::BLACK, custom_size: Some(Vec2::new(width * TILE_SPACING, 2.0 * TILE_SPACING)), ..Default::default() },
+                transform: Transform::from_translation(Vec3 { x: centre, y: current_map.tile_pos(0.0, y).y, z: EDITOR_Z - 5.0 }),
+                ..Default::default()
+            }).insert(EditorScreen);
+        }
+
+        let rows = [(EditorText::Title, -2.0), (EditorText::Tool, -1.0), (EditorText::Problems, height), (EditorText::Message, height + 1.0)];
+        for (kind, row) in rows {
+            let text = BitmapText::new("").with_width(current_map.width.max(1));
+            commands.spawn(BitmapTextBundle::new(text, Vec3 { x: left, y: current_map.tile_pos(0.0, row).y, z: EDITOR_Z }, GLYPH_SCALE))
+                .insert(EditorScreen)
+                .insert(kind);
+        }
+
+        commands.spawn(SpriteBundle {
+            sprite: Sprite { color: Color::rgba(1.0, 1.0, 1.0, 0.35), custom_size: Some(Vec2::splat(TILE_SPACING)), ..Default::default() },
+            visibility: Visibility::Hidden,
+            ..Default::default()
+        }).insert(EditorScreen).insert(EditorCursor);
+    }
+
+    pub fn finish(mut commands: Commands, screen: Query<Entity, With<EditorScreen>>, editor: Option<ResMut<MapEditor>>) {
+        for entity in &screen {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        if let Some(mut editor) = editor {
+            editor.message = None;
+        }
+    }
+
+    /// TestPlay (F5) again goes back to the editor
+    pub fn stop_test(actions: Res<ActionState>, test_play: Option<Res<TestPlay>>, mut next_state: ResMut<NextState<GameState>>) {
+        if test_play.is_some() && actions.just_pressed(Action::TestPlay) {
+            next_state.set(GameState::Editor);
+        }
+    }
+}
+
+/// The mouse and what it's over
+#[derive(SystemParam)]
+pub struct EditorMouse<'w, 's> {
+    buttons: Res<'w, Input<MouseButton>>,
+    wheel: EventReader<'w, 's, MouseWheel>,
+    windows: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    cameras: Query<'w, 's, (&'static Camera, &'static GlobalTransform), With<Camera2d>>,
+    current_map: Res<'w, CurrentMap>,
+}
+
+impl EditorMouse<'_, '_> {
+    /// The tile under the mouse, the camera's viewport is letterboxed so the cursor is moved into it first
+    fn hovered_tile(&self) -> Option<IVec2> {
+        let window = self.windows.get_single().ok()?;
+        let (camera, transform) = self.cameras.get_single().ok()?;
+
+        // The cursor is from the bottom left, the viewport from the top left
+        let cursor = window.cursor_position()?;
+        let (min, max) = camera.logical_viewport_rect()?;
+        let local = Vec2::new(cursor.x - min.x, cursor.y - (window.height() - max.y));
+
+        let world = camera.viewport_to_world_2d(transform, local)?;
+        Some(self.current_map.world_to_tile(world.extend(0.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::env::{EnvAction, EnvConfig, PacmanEnv};
+
+    const MAP: &str = "---\ntitle = \"Box\"\n---\n#####\n#S.@#\n###\n";
+
+    fn editor() -> MapEditor {
+        MapEditor::new(MAP, PathBuf::from("box.map"))
+    }
+
+    #[test]
+    fn text_keeps_the_header_and_trims_rows() {
+        let editor = editor();
+
+        assert_eq!(editor.get(IVec2::new(4, 2)), Some(' '));
+        assert_eq!(editor.text(), MAP);
+        assert!(!editor.dirty);
+    }
+
+    #[test]
+    fn paints_inside_the_map_only() {
+        let mut editor = editor();
+
+        assert!(editor.paint(IVec2::new(2, 1), '#'));
+        assert!(!editor.paint(IVec2::new(2, 1), '#'));
+        assert!(!editor.paint(IVec2::new(5, 1), '#'));
+        assert!(!editor.paint(IVec2::new(-1, 0), '#'));
+
+        assert!(editor.dirty);
+        assert!(editor.text().ends_with("#####\n#S#@#\n###\n"));
+    }
+
+    #[test]
+    fn undo_and_redo_a_stroke() {
+        let mut editor = editor();
+
+        assert!(!editor.undo());
+        editor.stroke = Some(editor.rows.clone());
+        editor.paint(IVec2::new(2, 1), '#');
+        editor.paint(IVec2::new(3, 1), '#');
+
+        assert!(editor.undo());
+        assert_eq!(editor.text(), MAP);
+        assert!(!editor.undo());
+
+        assert!(editor.redo());
+        assert!(editor.text().ends_with("#S###\n###\n"));
+        assert!(!editor.redo());
+    }
+
+    #[test]
+    fn a_new_stroke_drops_the_redo() {
+        let mut editor = editor();
+
+        editor.stroke = Some(editor.rows.clone());
+        editor.paint(IVec2::new(2, 1), '#');
+        editor.undo();
+        editor.stroke = Some(editor.rows.clone());
+        editor.paint(IVec2::new(3, 1), '.');
+
+        assert!(!editor.redo());
+    }
+
+    #[test]
+    fn painting_out_pinky_only_warns() {
+        let mut editor = MapEditor::new("#######\n#S.BPI#\n#######\n", PathBuf::from("ghosts.map"));
+        let has = |editor: &MapEditor, message: &str| editor.problems().iter().any(|problem| problem.message == message);
+
+        assert!(!has(&editor, "INKY NEEDS PINKY"));
+        editor.paint(IVec2::new(4, 1), '.');
+
+        assert!(has(&editor, "NO PINKY") && has(&editor, "INKY NEEDS PINKY"));
+        assert!(editor.problems().iter().all(|problem| !problem.blocking));
+
+        // Test playing it builds the maze and spawns the ghosts without Inky
+        let mut env = PacmanEnv::new(EnvConfig { noop_max: 0, ..Default::default() });
+        env.reset(0, Some(&editor.text()));
+        assert!(!env.step(EnvAction::Noop).done);
+    }
+
+    #[test]
+    fn problems_follow_the_edits() {
+        let mut editor = editor();
+        let blocking = |editor: &MapEditor| editor.problems().iter().filter(|problem| problem.blocking).count();
+
+        assert_eq!(blocking(&editor), 0);
+        editor.stroke = Some(editor.rows.clone());
+        editor.paint(IVec2::new(2, 1), ' ');
+        assert_eq!(editor.problems().first(), Some(&Problem::blocking("NO FOOD")));
+        editor.undo();
+        assert_eq!(blocking(&editor), 0);
+    }
+}
@@ -1,16 +1,20 @@
-use std::{sync::{Arc, Mutex}, ops::{Add, Sub}, rc::Rc, f32::INFINITY};
+use std::{sync::{Arc, Mutex}, ops::{Add, Sub}, f32::INFINITY};
 
 use bevy::{prelude::*, sprite::collide_aabb::collide};
 use bevy_inspector_egui::prelude::*;
 
 use crate::game::{TILE_SIZE, MovementHelper, AnimationTimer, AnimationDescriptor, ManualAnimationControl};
 
-use super::{util::{get_heighest_distance, calculate_next_step, chase, get_real_pos, calculate_distance, check_collosion, get_pos_infront_of_pacman, PowerPellets}, map::{Food, WallType}, player::PlayerData, ENERGIZED_GHOST_DEBUFF, PINKY_LEAVE_TIME, INKY_LEAVE_TIME, CLYDE_LEAVE_TIME};
+use super::{util::{get_heighest_distance, calculate_next_step, chase, get_real_pos, calculate_distance, check_collosion, get_pos_infront_of_pacman}, map::{Food, FoodSystem, WallType, campaign::Campaign}, player::PlayerData, ENERGIZED_GHOST_DEBUFF, PINKY_LEAVE_TIME, INKY_LEAVE_TIME, CLYDE_LEAVE_TIME};
 
-use super::{player::{PlayerMovement, GameData}, STEP_SIZE, map::{TileCollider, DEFAULT_MAP_ORIGIN}, EntityState, CurrentDirection};
+use super::{player::{PlayerMovement, GameData}, movement::{TileMover, sub_tile_rate}, STEP_SIZE, map::{TileCollider, CurrentMap, grid::MapGrid}, EntityState, CurrentDirection, GameState};
 
 mod util;
 
+use super::fingerprint::Cheats;
+use super::assets::GameAssets;
+use super::input::{Action, SeatActions};
+
 use util::GhostState;
 
 // TODO: Give Blinky "Cruise Elroy"
@@ -29,7 +33,8 @@ const STUCK_TICKS: isize = 60;
 #[derive(Debug, Clone, Copy, Component)]
 pub struct GhostSpawn {
     pub spawn_for: GhostPersonality,
-    pub power_pellet: usize,
+    /// Scatter target in tiles
+    pub scatter: IVec2,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Reflect)]
@@ -37,6 +42,13 @@ pub enum GhostPersonality {
     Blinky, Pinky, Inky, Clyde
 }
 
+impl GhostPersonality {
+    /// As written in settings.toml
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Blinky, Self::Pinky, Self::Inky, Self::Clyde].into_iter().find(|personality| format!("{:?}", personality).eq_ignore_ascii_case(name))
+    }
+}
+
 #[derive(Debug, Clone, Component, Reflect)]
 // #[(Debug)]
 pub struct Ghost {
@@ -53,16 +65,56 @@ pub struct Ghost {
 
     pub steps: Vec<Vec3>,
 
-    pub scatter_zone: usize,
+    /// Scatter target in tiles, it can be off the map
+    pub scatter: IVec2,
 
     pub house_time: u64,
+
+    /// Builds up in the tunnels, the ghost only moves once it reaches 1
+    pub pace: f32,
 }
 
 #[derive(Debug, Clone, Copy, Resource, Component)]
 pub struct Enemy;
 
+/// A ghost driven by a player (by Seat) instead of its AI
+/// The house, frightened and eaten rules still apply, only the targeting is skipped
+#[derive(Debug, Clone, Copy, Component)]
+pub struct PlayerControlled {
+    pub seat: usize,
+    /// Held until the ghost reaches a tile centre it can turn at
+    pub wanted: Option<CurrentDirection>,
+    /// Moves on the tile grid like Pac-Man, None while the ghost is in the house (or eaten)
+    pub mover: Option<TileMover>,
+    /// Leftover sub-tile steps, slower speeds only take a step every few ticks
+    carry: f32,
+}
+
+impl PlayerControlled {
+    pub fn new(seat: usize) -> Self {
+        Self { seat: seat, wanted: None, mover: None, carry: 0.0 }
+    }
+
+    /// Moves `speed` sub-tile steps, `wanted` is only taken at a tile centre (turning back can happen anywhere)
+    fn steer(&mut self, grid: &MapGrid, speed: f32) {
+        let Some(mover) = self.mover.as_mut() else { return };
+
+        self.carry += sub_tile_rate(speed);
+        let steps = self.carry.floor();
+        self.carry -= steps;
+
+        for _ in 0..steps as i32 {
+            let direction = match self.wanted {
+                Some(wanted) if mover.offset == IVec2::ZERO || wanted == mover.heading.opposite() => wanted,
+                _ => mover.heading,
+            };
+            mover.step(grid, direction, 1);
+        }
+    }
+}
+
 impl Ghost {
-    pub fn new(personaility: GhostPersonality, scatter_zone: usize) -> Self {
+    pub fn new(personaility: GhostPersonality, scatter: IVec2) -> Self {
 
         let house_time = match personaility {
             GhostPersonality::Blinky => 0,
@@ -79,28 +131,26 @@ impl Ghost {
             award_ai: true,
             stuck_ticks: None,
             steps: Vec::new(),
-            scatter_zone: scatter_zone,
-            house_time: house_time
+            scatter: scatter,
+            house_time: house_time,
+            pace: 0.0,
         }
     }
 
-    fn _spawn_internal(mut commands: Arc<Mutex<Commands>>, personaility: GhostPersonality, asset_server: &AssetServer, mut texture_atlases: Rc<Mutex<ResMut<Assets<TextureAtlas>>>>, scatter_zone: usize) {
+    fn _spawn_internal(mut commands: Arc<Mutex<Commands>>, personaility: GhostPersonality, assets: &GameAssets, scatter: IVec2) {
 
         debug!("personaility: {:?}", personaility);
 
         // Chaser - Red guy - Urchin - Macky - Shadow - Blinky
-        let (texture_handle, name) = match personaility {
-            GhostPersonality::Blinky => { (asset_server.load("ghosts/blinky.png"), "Blinky") },
-            GhostPersonality::Pinky => (asset_server.load("ghosts/pinky.png"), "Pinky"),
-            GhostPersonality::Inky => (asset_server.load("ghosts/inky.png"), "Inky"),
-            GhostPersonality::Clyde => (asset_server.load("ghosts/clyde.png"), "Clyde"),
+        let name = match personaility {
+            GhostPersonality::Blinky => { "Blinky" },
+            GhostPersonality::Pinky => "Pinky",
+            GhostPersonality::Inky => "Inky",
+            GhostPersonality::Clyde => "Clyde",
         };
 
-        let atlas = TextureAtlas::from_grid(texture_handle, Vec2 { x: 16.0, y: 14.0 }, 1, 8, None, None);
-        let texture_atlas_handle = texture_atlases.lock().unwrap().add(atlas);
-
         commands.clone().lock().unwrap().spawn(SpriteSheetBundle  {
-            texture_atlas: texture_atlas_handle,
+            texture_atlas: assets.ghost(personaility),
              
             transform: Transform {
                 translation: Vec3 { x: 0.0, y: 0.0, z: 900.0 },
@@ -111,7 +161,7 @@ impl Ghost {
             ..Default::default()
         })
         .insert(Name::new(name))
-        .insert(Self::new(personaility, scatter_zone))
+        .insert(Self::new(personaility, scatter))
         .insert(MovementHelper{ direction: None })
         .insert(AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)))
         .insert(AnimationDescriptor{ main_controller: true, reset_on_idle: true, manual: ManualAnimationControl{ max_index: 8, index: 0, current_index: None, enable: false } })
@@ -121,67 +171,82 @@ impl Ghost {
 
     }
 
-    pub fn spawn(mut commands: Commands, spawn_points: Query<(&GhostSpawn, &Transform)>, asset_server: Res<AssetServer>, mut texture_atlases: ResMut<Assets<TextureAtlas>>) {
+    pub fn spawn(mut commands: Commands, spawn_points: Query<(&GhostSpawn, &Transform)>, assets: Res<GameAssets>, cheats: Option<Res<Cheats>>) {
 
-        let texture_atlases = Rc::new(Mutex::new(texture_atlases));
         let commands = Arc::new(Mutex::new(commands));
 
-        let mut pinky_present = false;
+        let cheats = cheats.as_deref().copied().unwrap_or_default();
+
+        // Inky aims off Pinky, without her he'd never leave the house
+        let pinky_present = ALLOW_PINKY && !cheats.disable_pinky && spawn_points.iter().any(|(point, _)| point.spawn_for == GhostPersonality::Pinky);
 
         for (point, point_transform) in &spawn_points {
 
             let personaility = point.spawn_for;
-            let scatter_zone = point.power_pellet;
+            let scatter = point.scatter;
 
             info!("Attempt Spawning {:?}", personaility);
 
             // Check to see if the ghost is allowed
             match personaility {
-                GhostPersonality::Blinky => { if !ALLOW_BLINKY { info!("Sorry Blinky! Not today :("); continue } },
-                GhostPersonality::Pinky => { if !ALLOW_PINKY { info!("Sorry Pinky! Maybe next time! :("); continue } else { pinky_present = true; } },
-                GhostPersonality::Inky => { if !ALLOW_INKY { info!("Sorry Inky! You little trouble maker! :("); continue} },
-                GhostPersonality::Clyde => { if !ALLOW_CLYDE { info!("A dream for you Clyde!"); continue} },
+                GhostPersonality::Blinky => { if !ALLOW_BLINKY || cheats.disable_blinky { info!("Sorry Blinky! Not today :("); continue } },
+                GhostPersonality::Pinky => { if !ALLOW_PINKY || cheats.disable_pinky { info!("Sorry Pinky! Maybe next time! :("); continue } },
+                GhostPersonality::Inky => {
+                    if !ALLOW_INKY || cheats.disable_inky { info!("Sorry Inky! You little trouble maker! :("); continue}
+                    if !pinky_present { warn!("Leaving Inky out, he needs Pinky to aim"); continue }
+                },
+                GhostPersonality::Clyde => { if !ALLOW_CLYDE || cheats.disable_clyde { info!("A dream for you Clyde!"); continue} },
             }
 
-            Self::_spawn_internal(commands.clone(), personaility, &asset_server, texture_atlases.clone(), scatter_zone)
+            Self::_spawn_internal(commands.clone(), personaility, &assets, scatter)
             
         }
 
-        if ALLOW_INKY && !pinky_present {
-            panic!("Pinky must be present for Inky to spawn");
-        }
-
-
     }
 
     /// The Brains of the ghosts
     /// TODO - Re-write
     pub fn tick(
-        mut me: Query<(&mut Self, &mut Transform, &mut MovementHelper), Without<PlayerMovement>>,
+        mut me: Query<(&mut Self, &mut Transform, &mut MovementHelper, Option<&mut PlayerControlled>), Without<PlayerMovement>>,
         target: Query<(&Transform, &MovementHelper, &GameData, &PlayerData), (With<PlayerMovement>, Without<TileCollider>)>,
         walls: Query<(&Transform, &TileCollider), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>)>,
         spawn_points: Query<(&GhostSpawn, &Transform), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>, Without<TileCollider>)>,
         food: Query<(&Food, &Visibility, &Transform), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>, Without<TileCollider>, Without<GhostSpawn>)>,
-        mut power_pellets_data: ResMut<PowerPellets>,
+        current_map: Res<CurrentMap>,
+        grid: Res<MapGrid>,
+        seat_actions: Res<SeatActions>,
     ) {
 
+        let origin = current_map.origin;
         let mut pinky_pos = None;
         let mut inky_index = None;
         let mut raw_target = calculate_next_step(true);
 
-        let (player, player_movement_helper, game_data, player_data) = target.single();
+        // Pac-Men that are out are ignored, each ghost goes after the nearest of the rest
+        // and is frightened while that Pac-Man is energized (in co-op the whole team is, in versus only whoever ate the pellet)
+        let pacmen: Vec<(&Transform, &MovementHelper, bool)> = target.iter()
+            .filter(|(_, _, _, player_data)| !player_data.out)
+            .map(|(player, helper, _, player_data)| (player, helper, matches!(player_data.state, EntityState::Energized(_))))
+            .collect();
+        let Some((_, _, game_data, _)) = target.iter().next() else { return };
+        if pacmen.is_empty() { return }
 
-        let scatter_mode = match player_data.state {
-            EntityState::Alive => {false},
-            EntityState::Dead => {false},
-            EntityState::Respawning => {false},
-            EntityState::Created => {false},
-            EntityState::Energized(_) => {true},
-        };
+        for (ghost_index, (mut ghost, mut transform, mut my_helper, mut controlled)) in &mut me.iter_mut().enumerate() {
 
-        for (ghost_index, (mut ghost, mut transform, mut my_helper)) in &mut me.iter_mut().enumerate() {
+            // A player driven ghost goes back on the grid wherever it leaves the house
+            if ghost.state != EntityState::Alive {
+                if let Some(controlled) = controlled.as_mut() { controlled.mover = None }
+            }
+
+            let scatter_zone = current_map.tile_pos(ghost.scatter.x as f32, ghost.scatter.y as f32);
+            let (player, player_movement_helper, scatter_mode) = nearest_pacman(&pacmen, transform.translation);
 
-            let scatter_zone = get_real_pos(power_pellets_data.pellets.get(&ghost.scatter_zone).unwrap().clone(), DEFAULT_MAP_ORIGIN);
+            // Slowed down in the tunnels
+            if ghost.state == EntityState::Alive && grid.is_tunnel(current_map.world_to_tile(transform.translation)) {
+                ghost.pace += current_map.header.tunnel_speed.min(1.0);
+                if ghost.pace < 1.0 { continue }
+                ghost.pace -= 1.0;
+            }
 
             match ghost.state {
                 EntityState::Alive => {
@@ -190,6 +255,29 @@ impl Ghost {
                         // For game resetting, also enforced else where
                         if game_data.transitioning { ghost.state = EntityState::Created; return }
 
+                        // Driven by a player, frightened ghosts are still slowed down
+                        if let Some(controlled) = controlled.as_mut() {
+                            for (action, direction) in [(Action::Up, CurrentDirection::Up), (Action::Down, CurrentDirection::Down), (Action::Left, CurrentDirection::Left), (Action::Right, CurrentDirection::Right)] {
+                                if seat_actions.pressed(controlled.seat, action) { controlled.wanted = Some(direction) }
+                            }
+
+                            if controlled.mover.is_none() {
+                                controlled.mover = Some(TileMover::new(current_map.world_to_tile(transform.translation)));
+                            }
+
+                            let speed = if scatter_mode { calculate_next_step(true) - ENERGIZED_GHOST_DEBUFF } else { calculate_next_step(true) };
+                            controlled.steer(&grid, speed);
+
+                            // The Transform is drawn from the tile position, as Pac-Man's is
+                            if let Some(mover) = controlled.mover {
+                                let position = current_map.tile_pos(mover.position().x, mover.position().y);
+                                transform.translation.x = position.x;
+                                transform.translation.y = position.y;
+                                my_helper.direction = Some(mover.heading);
+                            }
+                            continue;
+                        }
+
                         let player_pos = if scatter_mode { 
                             raw_target -= ENERGIZED_GHOST_DEBUFF;
                             
@@ -203,7 +291,7 @@ impl Ghost {
                         match ghost.personality {
                             GhostPersonality::Blinky => {
                                         
-                                match chase(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls) {
+                                match chase(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls, origin) {
                                     Some(obj) => {
 
                                         // Corner check
@@ -224,7 +312,7 @@ impl Ghost {
                                             // }
 
                                             // Does this have to be a option? the chance of this returning Option::None is very small
-                                            let heighst = get_heighest_distance(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls);
+                                            let heighst = get_heighest_distance(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls, origin);
 
                                             match heighst {
                                                 Some(o) => {
@@ -262,27 +350,27 @@ impl Ghost {
                                                                             CurrentDirection::Up => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation + Vec3::new(0.0, raw_target, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                                             },
                                                                             CurrentDirection::Down => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation - Vec3::new(0.0, raw_target, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                     
                                                                             },
                                                                             CurrentDirection::Left => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation - Vec3::new(raw_target, 0.0, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                     
                                                                             },
                                                                             CurrentDirection::Right => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation + Vec3::new(raw_target, 0.0, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                                             },
                                                                             CurrentDirection::Idle => {}, // Don't move!
@@ -330,7 +418,7 @@ impl Ghost {
                                                     
                                                     // let mut found_good_pos = false;
                                                     // for item in food.iter() {
-                                                    //     let food_pos = get_real_pos(item.2.translation, DEFAULT_MAP_ORIGIN);
+                                                    //     let food_pos = get_real_pos(item.2.translation, origin);
                                                     //     let distance = calculate_distance(food_pos,my_pos);
                                                     //     info!("Distance: {}", distance);
                                                     //     if distance <= 90.0 {
@@ -356,7 +444,7 @@ impl Ghost {
 
                                 let player_pos = get_pos_infront_of_pacman(player_pos, player_movement_helper.direction.unwrap_or(CurrentDirection::Idle));
 
-                                match chase(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls) {
+                                match chase(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls, origin) {
                                     Some(obj) => {
 
                                         // Corner check
@@ -377,7 +465,7 @@ impl Ghost {
                                             // }
 
                                             // Does this have to be a option? the chance of this returning Option::None is very small
-                                            let heighst = get_heighest_distance(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls);
+                                            let heighst = get_heighest_distance(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls, origin);
 
                                             match heighst {
                                                 Some(o) => {
@@ -415,27 +503,27 @@ impl Ghost {
                                                                             CurrentDirection::Up => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation + Vec3::new(0.0, raw_target, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                                             },
                                                                             CurrentDirection::Down => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation - Vec3::new(0.0, raw_target, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                     
                                                                             },
                                                                             CurrentDirection::Left => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation - Vec3::new(raw_target, 0.0, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                     
                                                                             },
                                                                             CurrentDirection::Right => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation + Vec3::new(raw_target, 0.0, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                                             },
                                                                             CurrentDirection::Idle => {}, // Don't move!
@@ -483,7 +571,7 @@ impl Ghost {
                                                     
                                                     // let mut found_good_pos = false;
                                                     // for item in food.iter() {
-                                                    //     let food_pos = get_real_pos(item.2.translation, DEFAULT_MAP_ORIGIN);
+                                                    //     let food_pos = get_real_pos(item.2.translation, origin);
                                                     //     let distance = calculate_distance(food_pos,my_pos);
                                                     //     info!("Distance: {}", distance);
                                                     //     if distance <= 90.0 {
@@ -514,7 +602,7 @@ impl Ghost {
                                     scatter_zone
                                 } else { player_pos };
 
-                                match chase(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls) {
+                                match chase(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls, origin) {
                                     Some(obj) => {
 
                                         // Corner check
@@ -535,7 +623,7 @@ impl Ghost {
                                             // }
 
                                             // Does this have to be a option? the chance of this returning Option::None is very small
-                                            let heighst = get_heighest_distance(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls);
+                                            let heighst = get_heighest_distance(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls, origin);
 
                                             match heighst {
                                                 Some(o) => {
@@ -573,27 +661,27 @@ impl Ghost {
                                                                             CurrentDirection::Up => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation + Vec3::new(0.0, raw_target, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                                             },
                                                                             CurrentDirection::Down => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation - Vec3::new(0.0, raw_target, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                     
                                                                             },
                                                                             CurrentDirection::Left => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation - Vec3::new(raw_target, 0.0, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                     
                                                                             },
                                                                             CurrentDirection::Right => {
                                                                                 // T/t = Tiles per tick
                                                                                 let target = transform.translation + Vec3::new(raw_target, 0.0, 0.0);
-                                                                                let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                                let touching = !check_collosion(target, &walls, origin);
                                                                                 if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                                             },
                                                                             CurrentDirection::Idle => {}, // Don't move!
@@ -665,7 +753,7 @@ impl Ghost {
                         let wall_type = wall_type.r#type;
                         if wall_type != WallType::Gate { continue; }
 
-                        let wall_pos = get_real_pos(wall_pos.translation, DEFAULT_MAP_ORIGIN);
+                        let wall_pos = get_real_pos(wall_pos.translation, origin);
                         
                         let mut offset = 0.0;
 
@@ -700,7 +788,7 @@ impl Ghost {
                         let personaility = ghost.personality;
                         if spawn_point.spawn_for == personaility {
                             info!("Ghost in Created State! Resetting");
-                            transform.translation = get_real_pos(spawn_pos.translation, DEFAULT_MAP_ORIGIN);
+                            transform.translation = get_real_pos(spawn_pos.translation, origin);
 
                             let house_time = match personaility {
                                 GhostPersonality::Blinky => 0,
@@ -731,10 +819,10 @@ impl Ghost {
 
         // Inky AI is stashed here
         if pinky_pos.is_some() && inky_index.is_some() {
-            for (mut ghost, mut transform, mut my_helper) in &mut me {
-                if !ghost.alive || ghost.personality != GhostPersonality::Inky || !ghost.has_ai { continue; }
+            for (mut ghost, mut transform, mut my_helper, controlled) in &mut me {
+                if !ghost.alive || ghost.personality != GhostPersonality::Inky || !ghost.has_ai || controlled.is_some() { continue; }
                     
-                    let player = player.translation;
+                    let player = nearest_pacman(&pacmen, transform.translation).0.translation;
                     let pinky_pos = pinky_pos.unwrap();
 
                     let dx = 2.0 * (player.x - pinky_pos.x);
@@ -745,7 +833,7 @@ impl Ghost {
                     let player_pos = target.clone();
                     let my_pos = transform.translation;
 
-                    match chase(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls) {
+                    match chase(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls, origin) {
                         Some(obj) => {
 
                             // Corner check
@@ -766,7 +854,7 @@ impl Ghost {
                                 // }
 
                                 // Does this have to be a option? the chance of this returning Option::None is very small
-                                let heighst = get_heighest_distance(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls);
+                                let heighst = get_heighest_distance(player_pos, my_helper.direction.unwrap_or(CurrentDirection::Idle), my_pos, &walls, origin);
 
                                 match heighst {
                                     Some(o) => {
@@ -804,27 +892,27 @@ impl Ghost {
                                                                 CurrentDirection::Up => {
                                                                     // T/t = Tiles per tick
                                                                     let target = transform.translation + Vec3::new(0.0, raw_target, 0.0);
-                                                                    let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                    let touching = !check_collosion(target, &walls, origin);
                                                                     if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                                 },
                                                                 CurrentDirection::Down => {
                                                                     // T/t = Tiles per tick
                                                                     let target = transform.translation - Vec3::new(0.0, raw_target, 0.0);
-                                                                    let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                    let touching = !check_collosion(target, &walls, origin);
                                                                     if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                         
                                                                 },
                                                                 CurrentDirection::Left => {
                                                                     // T/t = Tiles per tick
                                                                     let target = transform.translation - Vec3::new(raw_target, 0.0, 0.0);
-                                                                    let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                    let touching = !check_collosion(target, &walls, origin);
                                                                     if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                         
                                                                 },
                                                                 CurrentDirection::Right => {
                                                                     // T/t = Tiles per tick
                                                                     let target = transform.translation + Vec3::new(raw_target, 0.0, 0.0);
-                                                                    let touching = !check_collosion(target, &walls, DEFAULT_MAP_ORIGIN);
+                                                                    let touching = !check_collosion(target, &walls, origin);
                                                                     if touching { transform.translation = target } else { ghost.stuck_ticks = None; }
                                                                 },
                                                                 CurrentDirection::Idle => {}, // Don't move!
@@ -872,7 +960,7 @@ impl Ghost {
                                         
                                         // let mut found_good_pos = false;
                                         // for item in food.iter() {
-                                        //     let food_pos = get_real_pos(item.2.translation, DEFAULT_MAP_ORIGIN);
+                                        //     let food_pos = get_real_pos(item.2.translation, origin);
                                         //     let distance = calculate_distance(food_pos,my_pos);
                                         //     info!("Distance: {}", distance);
                                         //     if distance <= 90.0 {
@@ -900,19 +988,35 @@ impl Ghost {
 
     }
 
+    /// Slower levels skip the odd tick, ghosts can't go faster than full speed
+    pub fn paced(campaign: Option<Res<Campaign>>, game: Query<&GameData, With<PlayerMovement>>, mut carry: Local<f32>) -> bool {
+        let level = game.iter().map(|game_data| game_data.level).next().unwrap_or(0);
+        *carry += campaign.map(|campaign| campaign.level(level).ghost_speed).unwrap_or(1.0).min(1.0);
+
+        if *carry < 1.0 { return false }
+        *carry -= 1.0;
+        true
+    }
+
     /// Ensures all ghosts are spawned
-    pub fn enforcer(mut commands: Commands, spawn_points: Query<(&GhostSpawn, &Transform)>, asset_server: Res<AssetServer>, 
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    ghosts: Query<(&Enemy)>
+    pub fn enforcer(mut commands: Commands, spawn_points: Query<(&GhostSpawn, &Transform)>, assets: Res<GameAssets>,
+    ghosts: Query<(&Enemy)>, cheats: Option<Res<Cheats>>
     ) {
 
         if !spawn_points.is_empty() && ghosts.is_empty() {
-            Self::spawn(commands, spawn_points, asset_server, texture_atlases);
+            Self::spawn(commands, spawn_points, assets, cheats);
         }
 
     }
 }
 
+/// The Pac-Man closest to a ghost and whether it's energized, there has to be at least one
+fn nearest_pacman<'a>(pacmen: &[(&'a Transform, &'a MovementHelper, bool)], from: Vec3) -> (&'a Transform, &'a MovementHelper, bool) {
+    *pacmen.iter()
+        .min_by(|a, b| a.0.translation.distance_squared(from).total_cmp(&b.0.translation.distance_squared(from)))
+        .unwrap()
+}
+
 // Ghost Core
 pub struct GhostPlugin;
 
@@ -923,12 +1027,82 @@ impl Plugin for GhostPlugin {
 
         app
             // .add_startup_system(Ghost::spawn)
-            .add_system(Ghost::enforcer)
-            .add_system(Ghost::tick)
-            .add_state::<crate::enemy::GhostState>()
+            .add_system(Ghost::enforcer.before(Ghost::tick))
+            // Ghosts chase where Pac-Man is this tick, then he runs into them
+            .add_system(Ghost::tick.in_set(OnUpdate(GameState::Playing)).run_if(Ghost::paced)
+                .after(PlayerMovement::sync_transform)
+                .after(FoodSystem::check_food)
+                .before(PlayerMovement::ghost_collisions))
+            .add_state::<crate::game::enemy::GhostState>()
 
 
         ;
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::movement::SUB_TILES;
+
+    /// World units per tick for one sub-tile step a tick
+    const ONE_STEP: f32 = TILE_SIZE * 10.0 / SUB_TILES as f32;
+
+    fn driven(offset: IVec2, heading: CurrentDirection, wanted: CurrentDirection) -> PlayerControlled {
+        let mut controlled = PlayerControlled::new(1);
+        controlled.wanted = Some(wanted);
+        controlled.mover = Some(TileMover { tile: IVec2::new(1, 0), offset: offset, heading: heading });
+        controlled
+    }
+
+    #[test]
+    fn player_ghosts_turn_at_tile_centres() {
+        let grid = MapGrid::new(include_str!("../../tests/blinky_ai_test_level.map"));
+
+        // Pac-Man would corner from here, a ghost carries on to the middle of the next tile
+        let mut controlled = driven(IVec2::new(2, 0), CurrentDirection::Right, CurrentDirection::Down);
+        for _ in 0..8 { controlled.steer(&grid, ONE_STEP); }
+        assert_eq!(controlled.mover, Some(TileMover { tile: IVec2::new(2, 0), offset: IVec2::ZERO, heading: CurrentDirection::Right }));
+
+        controlled.steer(&grid, ONE_STEP);
+        assert_eq!(controlled.mover, Some(TileMover { tile: IVec2::new(2, 0), offset: IVec2::new(0, 1), heading: CurrentDirection::Down }));
+    }
+
+    #[test]
+    fn frightened_by_the_nearest_pacman_only() {
+        let helper = MovementHelper { direction: None };
+        let eater = Transform::from_xyz(0.0, 0.0, 0.0);
+        let other = Transform::from_xyz(100.0, 0.0, 0.0);
+        let pacmen = [(&eater, &helper, true), (&other, &helper, false)];
+
+        // A versus game, the ghost by the Pac-Man that ate the pellet runs and the one by the other still gives chase
+        assert!(nearest_pacman(&pacmen, Vec3::new(10.0, 0.0, 0.0)).2);
+        assert!(!nearest_pacman(&pacmen, Vec3::new(90.0, 0.0, 0.0)).2);
+    }
+
+    #[test]
+    fn inky_is_left_out_without_pinky() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_plugin(AssetPlugin::default()).add_asset::<Image>().add_asset::<TextureAtlas>()
+            .init_resource::<GameAssets>()
+            .add_system(Ghost::enforcer);
+
+        for personality in [GhostPersonality::Blinky, GhostPersonality::Inky] {
+            app.world.spawn((GhostSpawn { spawn_for: personality, scatter: IVec2::ZERO }, Transform::default()));
+        }
+        app.update();
+
+        let spawned: Vec<GhostPersonality> = app.world.query::<&Ghost>().iter(&app.world).map(|ghost| ghost.personality).collect();
+        assert_eq!(spawned, [GhostPersonality::Blinky]);
+    }
+
+    #[test]
+    fn player_ghosts_turn_back_anywhere() {
+        let grid = MapGrid::new(include_str!("../../tests/blinky_ai_test_level.map"));
+
+        let mut controlled = driven(IVec2::new(2, 0), CurrentDirection::Right, CurrentDirection::Left);
+        controlled.steer(&grid, ONE_STEP);
+        assert_eq!(controlled.mover, Some(TileMover { tile: IVec2::new(1, 0), offset: IVec2::new(1, 0), heading: CurrentDirection::Left }));
+    }
+}
@@ -0,0 +1,510 @@
+use std::time::{Duration, Instant};
+
+use bevy::{prelude::*, core::{TaskPoolPlugin, TypeRegistrationPlugin, FrameCountPlugin}, time::{TimePlugin, TimeSystem, TimeUpdateStrategy}, ecs::schedule::ExecutorKind};
+use rayon::prelude::*;
+
+use super::{map::{self, grid::MapGrid, ConsumableType, CurrentMap, Food, FoodSystem, MapSource, MapTile, DEFAULT_MAP}, player::{Player, PlayerData, GameData, PlayerMovement, Seat}, enemy::{self, Ghost}, movement::TileMover, players::{self, Players}, util::Xorshift, assets::GameAssets, text, input::{self, InputBindings, SeatBindings}, CurrentDirection, EntityState, GameController, GameState, MovementHelper, ENERGIZED_MAX_LENGTH};
+
+/// Time per tick, the same rate ENERGIZED_MAX_LENGTH is measured in
+const TICK: Duration = Duration::from_millis(15);
+
+/// Ticks spent on one Ready screen (or state change) before giving up on it
+const MAX_PAUSE_TICKS: usize = 1000;
+
+/// Layers of Observation::grid
+pub const CHANNELS: usize = 9;
+pub const WALL: usize = 0;
+pub const DOT: usize = 1;
+pub const POWER_PELLET: usize = 2;
+pub const FRUIT: usize = 3;
+pub const PACMAN: usize = 4;
+/// Chasing (or scattering) ghosts
+pub const GHOST: usize = 5;
+/// Ghosts that can be eaten
+pub const FRIGHTENED: usize = 6;
+/// Eaten ghosts heading home
+pub const EYES: usize = 7;
+/// Ghosts waiting in the house
+pub const HOUSE: usize = 8;
+
+/// What each entry of Observation::scalars is
+pub const SCALARS: [&str; 6] = ["lives", "level", "energized", "food_left", "heading_x", "heading_y"];
+
+/// The moves an agent can make, Noop keeps going the same way
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnvAction {
+    #[default]
+    Noop,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl EnvAction {
+    pub const ALL: [EnvAction; 5] = [EnvAction::Noop, EnvAction::Up, EnvAction::Down, EnvAction::Left, EnvAction::Right];
+
+    /// For agents with a discrete action space, in the order of ALL
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    fn direction(&self) -> Option<CurrentDirection> {
+        match self {
+            EnvAction::Noop => None,
+            EnvAction::Up => Some(CurrentDirection::Up),
+            EnvAction::Down => Some(CurrentDirection::Down),
+            EnvAction::Left => Some(CurrentDirection::Left),
+            EnvAction::Right => Some(CurrentDirection::Right),
+        }
+    }
+}
+
+/// The maze as CHANNELS layers of width x height (1.0 where something is), plus the SCALARS
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub width: usize,
+    pub height: usize,
+    /// Channel major, then rows, then columns
+    pub grid: Vec<f32>,
+    pub scalars: Vec<f32>,
+}
+
+impl Observation {
+    fn new(width: usize, height: usize) -> Self {
+        Self { width: width, height: height, grid: vec![0.0; CHANNELS * width * height], scalars: vec![0.0; SCALARS.len()] }
+    }
+
+    fn index(&self, channel: usize, tile: IVec2) -> Option<usize> {
+        if tile.x < 0 || tile.y < 0 || tile.x as usize >= self.width || tile.y as usize >= self.height { return None }
+        Some((channel * self.height + tile.y as usize) * self.width + tile.x as usize)
+    }
+
+    pub fn get(&self, channel: usize, tile: IVec2) -> f32 {
+        self.index(channel, tile).map(|i| self.grid[i]).unwrap_or(0.0)
+    }
+
+    /// Anything off the map is dropped
+    fn set(&mut self, channel: usize, tile: IVec2) {
+        if let Some(i) = self.index(channel, tile) { self.grid[i] = 1.0 }
+    }
+}
+
+/// Everything about a step that isn't in the observation
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StepInfo {
+    pub score: usize,
+    pub lives: u64,
+    pub level: usize,
+    /// Ticks since reset
+    pub ticks: u64,
+    /// A life was lost this step
+    pub died: bool,
+    /// Stopped by EnvConfig::max_ticks rather than a game over
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub observation: Observation,
+    pub reward: f32,
+    pub done: bool,
+    pub info: StepInfo,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EnvConfig {
+    /// Taken off the reward for each life lost
+    pub death_penalty: f32,
+    /// Ends the episode early, None plays until game over
+    pub max_ticks: Option<u64>,
+    /// Up to this many Noop steps (picked by the seed) are taken after reset, the game itself has no randomness
+    pub noop_max: usize,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        Self { death_penalty: 500.0, max_ticks: Some(27_000), noop_max: 30 }
+    }
+}
+
+/// One game without a window, stepped a tick at a time
+/// Ready screens (after a death or a cleared level) are played through inside a step
+pub struct PacmanEnv {
+    pub config: EnvConfig,
+    /// Built by reset, every game starts from a fresh App
+    app: Option<App>,
+    info: StepInfo,
+    done: bool,
+}
+
+impl PacmanEnv {
+    pub fn new(config: EnvConfig) -> Self {
+        Self { config: config, app: None, info: StepInfo::default(), done: true }
+    }
+
+    /// The gameplay plugins on top of the bare minimum of bevy, nothing is drawn
+    fn build(map: Option<&str>) -> App {
+        let mut app = App::new();
+
+        app
+            .add_plugin(TaskPoolPlugin::default())
+            .add_plugin(TypeRegistrationPlugin)
+            .add_plugin(FrameCountPlugin)
+            .add_plugin(TimePlugin)
+            .add_plugin(TransformPlugin)
+            .add_plugin(HierarchyPlugin)
+            .add_plugin(bevy::input::InputPlugin)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Image>()
+            .add_asset::<TextureAtlas>()
+            .insert_resource(TimeUpdateStrategy::ManualInstant(Instant::now()))
+            .add_system(Self::advance_clock.in_base_set(CoreSet::First).before(TimeSystem))
+            .init_resource::<GameAssets>()
+
+            // Only what's passed in counts, nothing is read from settings.toml or the working directory
+            .insert_resource(MapSource(map.unwrap_or(DEFAULT_MAP).to_string()))
+            .insert_resource(InputBindings::default())
+            .insert_resource(SeatBindings::default())
+
+            .add_plugin(Player)
+            .add_plugin(map::TileMap)
+            .add_plugin(FoodSystem)
+            .add_plugin(enemy::GhostPlugin)
+            .add_plugin(GameController)
+            .add_plugin(text::TextPlugin)
+            .add_plugin(input::InputPlugin)
+            .add_plugin(players::PlayersPlugin)
+
+        ;
+
+        // Environments run side by side on rayon, one thread each is plenty
+        app.edit_schedule(CoreSchedule::Main, |schedule| { schedule.set_executor_kind(ExecutorKind::SingleThreaded); });
+
+        app.setup();
+        app
+    }
+
+    /// Every update is exactly one TICK later than the last, however long it really took
+    /// (bevy's ManualDuration adds the TICK to the current time, so the delta would still be wall clock)
+    fn advance_clock(time: Res<Time>, mut strategy: ResMut<TimeUpdateStrategy>) {
+        *strategy = TimeUpdateStrategy::ManualInstant(time.last_update().unwrap_or(time.startup()) + TICK);
+    }
+
+    /// Starts a new one player game, `map` is the text of a .map file (None for the built in map)
+    pub fn reset(&mut self, seed: u64, map: Option<&str>) -> Observation {
+        self.info = StepInfo::default();
+        self.done = false;
+
+        // Straight past the title screen
+        let app = self.app.insert(Self::build(map));
+        app.update();
+        app.world.insert_resource(Players::new(1));
+        app.world.resource_mut::<NextState<GameState>>().set(GameState::Ready);
+        app.update();
+        self.skip_pauses();
+
+        let noops = Xorshift::new(seed).below(self.config.noop_max + 1);
+        for _ in 0..noops {
+            if self.step(EnvAction::Noop).done { break }
+        }
+
+        self.info.ticks = 0;
+        self.observe()
+    }
+
+    /// Plays one tick, once done every step is a no-op until reset
+    pub fn step(&mut self, action: EnvAction) -> Transition {
+        if self.done {
+            return Transition { observation: self.observe(), reward: 0.0, done: true, info: self.info }
+        }
+
+        if let Some(direction) = action.direction() {
+            let world = &mut self.app().world;
+            let mut query = world.query_filtered::<(&Seat, &mut MovementHelper), With<PlayerMovement>>();
+            for (seat, mut helper) in query.iter_mut(world) {
+                if seat.0 == 0 { helper.direction = Some(direction) }
+            }
+        }
+
+        let before = self.info;
+        self.app().update();
+        self.skip_pauses();
+        self.info.ticks += 1;
+
+        self.info.died = self.info.lives < before.lives || self.game_over();
+        let mut reward = self.info.score.saturating_sub(before.score) as f32;
+        if self.info.died { reward -= self.config.death_penalty }
+
+        self.info.truncated = self.config.max_ticks.map(|max| self.info.ticks >= max).unwrap_or(false);
+        self.done = self.game_over() || self.info.truncated;
+
+        Transition { observation: self.observe(), reward: reward, done: self.done, info: self.info }
+    }
+
+    /// Only reached once reset has built the App, a new env is done until then
+    fn app(&mut self) -> &mut App {
+        self.app.as_mut().expect("The env has to be reset before it's stepped")
+    }
+
+    fn state(&self) -> GameState {
+        self.app.as_ref().map_or(GameState::default(), |app| app.world.resource::<State<GameState>>().0)
+    }
+
+    fn game_over(&self) -> bool {
+        self.state() == GameState::EnteringInitials
+    }
+
+    /// Updates until play starts again (or the game is over)
+    fn skip_pauses(&mut self) {
+        for _ in 0..MAX_PAUSE_TICKS {
+            self.sync_info();
+            if matches!(self.state(), GameState::Playing | GameState::EnteringInitials) { return }
+            self.app().update();
+        }
+
+        warn!("Stuck in {:?} for {} ticks", self.state(), MAX_PAUSE_TICKS);
+    }
+
+    fn sync_info(&mut self) {
+        let Some(app) = self.app.as_mut() else { return };
+        let mut query = app.world.query_filtered::<(&Seat, &PlayerData, &GameData), With<PlayerMovement>>();
+        if let Some((_, data, game_data)) = query.iter(&app.world).find(|(seat, _, _)| seat.0 == 0) {
+            self.info.score = data.score;
+            self.info.lives = data.lives;
+            self.info.level = game_data.level;
+        }
+    }
+
+    pub fn observe(&mut self) -> Observation {
+        let Some(app) = self.app.as_mut() else { return Observation::new(0, 0) };
+        let world = &mut app.world;
+
+        let (Some(grid), Some(current_map)) = (world.get_resource::<MapGrid>().cloned(), world.get_resource::<CurrentMap>().cloned()) else {
+            return Observation::new(0, 0)
+        };
+        let mut observation = Observation::new(grid.width, grid.height);
+
+        for y in 0..grid.height as i32 {
+            for x in 0..grid.width as i32 {
+                let tile = IVec2::new(x, y);
+                if !grid.is_walkable(tile) { observation.set(WALL, tile) }
+            }
+        }
+
+        let mut food = world.query::<(&Food, &MapTile)>();
+        let (mut total, mut left) = (0, 0);
+        for (food, tile) in food.iter(world) {
+            if food.r#type != ConsumableType::Fruit { total += 1 }
+            if food.is_eaten { continue }
+
+            match food.r#type {
+                ConsumableType::PacDot => observation.set(DOT, tile.0),
+                ConsumableType::PowerPellet => observation.set(POWER_PELLET, tile.0),
+                ConsumableType::Fruit => observation.set(FRUIT, tile.0),
+            }
+            if food.r#type != ConsumableType::Fruit { left += 1 }
+        }
+
+        let mut pacman = world.query_filtered::<(&Seat, &TileMover, &PlayerData), With<PlayerMovement>>();
+        let mut energized = 0;
+        if let Some((_, mover, data)) = pacman.iter(world).find(|(seat, _, _)| seat.0 == 0) {
+            observation.set(PACMAN, mover.tile);
+            if let EntityState::Energized(time) = data.state { energized = time }
+
+            let heading = mover.heading.tile_step();
+            observation.scalars[4] = heading.x as f32;
+            observation.scalars[5] = heading.y as f32;
+        }
+
+        let mut ghosts = world.query::<(&Ghost, &Transform)>();
+        for (ghost, transform) in ghosts.iter(world) {
+            let channel = match ghost.state {
+                EntityState::Dead => EYES,
+                EntityState::Created | EntityState::Respawning => HOUSE,
+                _ if energized > 0 => FRIGHTENED,
+                _ => GHOST,
+            };
+            observation.set(channel, current_map.world_to_tile(transform.translation));
+        }
+
+        observation.scalars[0] = self.info.lives as f32;
+        observation.scalars[1] = self.info.level as f32;
+        observation.scalars[2] = energized as f32 / ENERGIZED_MAX_LENGTH as f32;
+        observation.scalars[3] = if total == 0 { 0.0 } else { left as f32 / total as f32 };
+
+        observation
+    }
+}
+
+/// Many environments stepped at once on the rayon thread pool
+/// Finished environments stay finished until they are reset
+pub struct VecEnv {
+    pub envs: Vec<PacmanEnv>,
+}
+
+impl VecEnv {
+    pub fn new(count: usize, config: EnvConfig) -> Self {
+        Self { envs: (0..count).into_par_iter().map(|_| PacmanEnv::new(config)).collect() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.envs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.envs.is_empty()
+    }
+
+    /// Environment i is seeded with seed + i
+    pub fn reset(&mut self, seed: u64, map: Option<&str>) -> Vec<Observation> {
+        self.envs.par_iter_mut().enumerate().map(|(i, env)| env.reset(seed.wrapping_add(i as u64), map)).collect()
+    }
+
+    /// One action per environment
+    pub fn step(&mut self, actions: &[EnvAction]) -> Vec<Transition> {
+        assert_eq!(actions.len(), self.envs.len(), "Need one action per environment");
+        self.envs.par_iter_mut().zip(actions.par_iter()).map(|(env, action)| env.step(*action)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Five dots between Pac-Man and Blinky, nowhere to run
+    const CORRIDOR: &str = "##########\n#S.....BP#\n##########\n";
+
+    fn env(max_ticks: Option<u64>) -> PacmanEnv {
+        PacmanEnv::new(EnvConfig { max_ticks: max_ticks, noop_max: 0, ..Default::default() })
+    }
+
+    /// Steps until done, there's a cap in case it never is
+    fn play(env: &mut PacmanEnv, action: EnvAction) -> Vec<Transition> {
+        let mut transitions = Vec::new();
+        for _ in 0..5000 {
+            let transition = env.step(action);
+            let done = transition.done;
+            transitions.push(transition);
+            if done { break }
+        }
+        transitions
+    }
+
+    #[test]
+    fn reset_starts_a_fresh_game() {
+        let mut env = env(None);
+        let first = env.reset(0, Some(CORRIDOR));
+
+        assert_eq!((first.width, first.height), (10, 3));
+        assert_eq!(first.get(PACMAN, IVec2::new(1, 1)), 1.0);
+        assert_eq!((2..7).map(|x| first.get(DOT, IVec2::new(x, 1))).sum::<f32>(), 5.0);
+        assert_eq!(first.scalars, vec![2.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+        for _ in 0..30 { env.step(EnvAction::Right); }
+        assert_ne!(env.observe(), first);
+
+        let again = env.reset(0, Some(CORRIDOR));
+        assert_eq!(again, first);
+        let transition = env.step(EnvAction::Noop);
+        assert_eq!((transition.info.score, transition.info.ticks, transition.info.lives), (0, 1, 2));
+    }
+
+    #[test]
+    fn a_seed_plays_out_the_same() {
+        let mut env = PacmanEnv::new(EnvConfig { noop_max: 30, ..Default::default() });
+        let mut episode = |seed| {
+            let first = env.reset(seed, Some(CORRIDOR));
+            let mut transitions = vec![Transition { observation: first, reward: 0.0, done: false, info: env.info }];
+            for tick in 0..400 {
+                transitions.push(env.step([EnvAction::Right, EnvAction::Left][tick / 40 % 2]));
+            }
+            transitions
+        };
+
+        let first = episode(7);
+        assert_eq!(episode(7), first);
+    }
+
+    #[test]
+    fn no_map_is_the_built_in_map() {
+        let mut env = env(None);
+        let built_in = env.reset(0, Some(DEFAULT_MAP));
+        assert_eq!(env.reset(0, None), built_in);
+    }
+
+    #[test]
+    fn rewards_add_up_to_the_score() {
+        let mut env = env(None);
+        env.reset(0, Some(CORRIDOR));
+
+        let transitions = play(&mut env, EnvAction::Right);
+        let last = transitions.last().unwrap().info;
+        let deaths = transitions.iter().filter(|transition| transition.info.died).count();
+
+        assert!(last.score > 0);
+        let total: f32 = transitions.iter().map(|transition| transition.reward).sum();
+        assert_eq!(total, last.score as f32 - deaths as f32 * env.config.death_penalty);
+
+        for pair in transitions.windows(2) {
+            assert!(!pair[0].done);
+            assert!(pair[1].info.score >= pair[0].info.score);
+            assert_eq!(pair[1].info.ticks, pair[0].info.ticks + 1);
+        }
+
+        // Finished until reset
+        let after = env.step(EnvAction::Left);
+        assert_eq!((after.reward, after.done, after.info), (0.0, true, last));
+    }
+
+    #[test]
+    fn the_last_death_ends_the_episode() {
+        let mut env = env(None);
+        env.reset(0, Some(CORRIDOR));
+
+        let transitions = play(&mut env, EnvAction::Noop);
+        let deaths: Vec<&Transition> = transitions.iter().filter(|transition| transition.info.died).collect();
+        let last = transitions.last().unwrap();
+
+        // Two lives in reserve, so the third death is game over
+        assert_eq!(deaths.len(), 3);
+        assert_eq!(deaths.iter().map(|death| death.info.lives).collect::<Vec<u64>>(), vec![1, 0, 0]);
+        assert!(deaths.iter().all(|death| death.reward == -env.config.death_penalty));
+        assert!(last.done && last.info.died && !last.info.truncated);
+    }
+
+    #[test]
+    fn max_ticks_truncates() {
+        let mut env = env(Some(10));
+        env.reset(0, Some(CORRIDOR));
+
+        let transitions = play(&mut env, EnvAction::Noop);
+        assert_eq!(transitions.len(), 10);
+        assert!(transitions[9].done && transitions[9].info.truncated && !transitions[9].info.died);
+    }
+
+    #[test]
+    fn vec_env_steps_each_env_on_its_own() {
+        let mut envs = VecEnv::new(3, EnvConfig { max_ticks: Some(40), noop_max: 0, ..Default::default() });
+        let observations = envs.reset(0, Some(CORRIDOR));
+        assert_eq!(observations.len(), 3);
+        assert!(observations.iter().all(|observation| observation.scalars[3] == 1.0));
+
+        // Only the first one goes for the dots, the last one is stopped early
+        envs.envs[2].config.max_ticks = Some(5);
+        let actions = [EnvAction::Right, EnvAction::Noop, EnvAction::Noop];
+
+        let mut last = Vec::new();
+        for _ in 0..40 {
+            last = envs.step(&actions);
+        }
+
+        assert!(last[0].info.score > 0 && last[0].observation.scalars[3] < 1.0);
+        assert_eq!((last[1].info.score, last[1].observation.scalars[3]), (0, 1.0));
+        assert_eq!(last[1].observation.get(PACMAN, IVec2::new(1, 1)), 1.0);
+
+        // Finished envs don't tick, the others carry on
+        assert_eq!((last[2].info.ticks, last[2].done, last[2].info.truncated), (5, true, true));
+        assert_eq!(last[1].info.ticks, 40);
+    }
+}
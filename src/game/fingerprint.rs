@@ -0,0 +1,208 @@
+use anyhow::Result;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{map::{CurrentMap, campaign::{Campaign, LevelSettings}, header::MapHeader}, player::{GameData, PlayerMovement}, util::{get_settings, hash_str}, STEP_SIZE, TILE_SIZE, GHOST_DEBUFF, ENERGIZED_GHOST_DEBUFF, ENERGIZED_MAX_LENGTH, PINKY_LEAVE_TIME, INKY_LEAVE_TIME, CLYDE_LEAVE_TIME};
+
+/// The [cheats] section of settings.toml, read once at startup
+/// Without the resource (the training env) no cheats are on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Resource)]
+pub struct Cheats {
+    pub disable_blinky: bool,
+    pub disable_pinky: bool,
+    pub disable_inky: bool,
+    pub disable_clyde: bool,
+}
+
+impl Cheats {
+    pub fn from_settings(settings: &Value) -> Self {
+        let cheats = &settings["cheats"];
+        let flag = |name: &str| cheats[name].as_bool().unwrap_or(false);
+
+        Self {
+            disable_blinky: flag("disable_blinky"),
+            disable_pinky: flag("disable_pinky"),
+            disable_inky: flag("disable_inky"),
+            disable_clyde: flag("disable_clyde"),
+        }
+    }
+
+    pub fn load() -> Self {
+        match get_settings() {
+            Ok(settings) => Self::from_settings(&settings),
+            Err(e) => { error!("Failed to read cheats from settings: {}", e); Self::default() },
+        }
+    }
+
+    /// Names of every cheat turned on
+    pub fn active(&self) -> Vec<String> {
+        let mut active = Vec::new();
+        if self.disable_blinky { active.push("disable_blinky".to_string()) }
+        if self.disable_pinky { active.push("disable_pinky".to_string()) }
+        if self.disable_inky { active.push("disable_inky".to_string()) }
+        if self.disable_clyde { active.push("disable_clyde".to_string()) }
+        active
+    }
+}
+
+/// Everything that decides how fast things move and how long they last
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpeedTable {
+    pub step_size: f32,
+    pub tile_size: f32,
+    pub ghost_debuff: f32,
+    pub energized_ghost_debuff: f32,
+    pub energized_length: u64,
+    pub pinky_leave_time: u64,
+    pub inky_leave_time: u64,
+    pub clyde_leave_time: u64,
+    /// The level's campaign settings and the map header's tunnel speed
+    pub pacman_speed: f32,
+    pub ghost_speed: f32,
+    pub frightened_time: u64,
+    pub tunnel_speed: f32,
+}
+
+impl SpeedTable {
+    /// The speed table this build is running with, on a level with these settings
+    pub fn current(level: &LevelSettings, header: &MapHeader) -> Self {
+        Self {
+            step_size: STEP_SIZE,
+            tile_size: TILE_SIZE,
+            ghost_debuff: GHOST_DEBUFF,
+            energized_ghost_debuff: ENERGIZED_GHOST_DEBUFF,
+            energized_length: ENERGIZED_MAX_LENGTH,
+            pinky_leave_time: PINKY_LEAVE_TIME,
+            inky_leave_time: INKY_LEAVE_TIME,
+            clyde_leave_time: CLYDE_LEAVE_TIME,
+            pacman_speed: level.pacman_speed,
+            ghost_speed: level.ghost_speed,
+            frightened_time: level.frightened_time,
+            tunnel_speed: header.tunnel_speed,
+        }
+    }
+
+    /// Nothing is sped up or slowed down by the campaign or the map
+    /// The build's own constants aren't checked here, a build with edited ones gives a different hash
+    pub fn is_standard(&self) -> bool {
+        let level = LevelSettings::default();
+
+        self.pacman_speed == level.pacman_speed
+            && self.ghost_speed == level.ghost_speed
+            && self.frightened_time == level.frightened_time
+            && self.tunnel_speed == MapHeader::default().tunnel_speed
+    }
+}
+
+/// Scores saved before games were fingerprinted count as ranked
+pub fn ranked_default() -> bool { true }
+
+/// What actually gets hashed, field order is the canonical order
+#[derive(Serialize)]
+struct CanonicalSettings<'a> {
+    cheats: &'a Cheats,
+    speeds: &'a SpeedTable,
+    map_hash: &'a str,
+}
+
+/// A hash of every gameplay relevant setting, the active map and the cheats in use
+/// Attached to score submissions so games can be compared fairly, there is no replay recording to attach it to yet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Resource)]
+pub struct SettingsFingerprint {
+    pub hash: String,
+    /// False if any cheat is on or the campaign or the map changes the speeds
+    pub ranked: bool,
+    pub cheats: Vec<String>,
+}
+
+impl SettingsFingerprint {
+    pub fn new(cheats: &Cheats, speeds: &SpeedTable, map_hash: &str) -> Result<Self> {
+        let canonical = serde_json::to_string(&CanonicalSettings { cheats: cheats, speeds: speeds, map_hash: map_hash })?;
+
+        Ok(Self {
+            hash: hash_str(&canonical),
+            ranked: cheats.active().is_empty() && speeds.is_standard(),
+            cheats: cheats.active(),
+        })
+    }
+
+    /// Recomputes the fingerprint whenever the map, the campaign or the level changes
+    pub fn update(
+        mut commands: Commands,
+        current_map: Option<Res<CurrentMap>>,
+        campaign: Option<Res<Campaign>>,
+        cheats: Option<Res<Cheats>>,
+        game: Query<&GameData, With<PlayerMovement>>,
+        mut last_level: Local<Option<usize>>,
+    ) {
+        let Some(current_map) = current_map else { return };
+
+        let level = game.iter().map(|game_data| game_data.level).next().unwrap_or(0);
+        let campaign_changed = campaign.as_ref().map_or(false, |campaign| campaign.is_changed());
+        if !current_map.is_changed() && !campaign_changed && *last_level == Some(level) { return }
+        *last_level = Some(level);
+
+        let settings = campaign.map(|campaign| campaign.level(level)).unwrap_or_default();
+        let speeds = SpeedTable::current(&settings, &current_map.header);
+
+        match Self::new(&cheats.as_deref().copied().unwrap_or_default(), &speeds, &current_map.hash) {
+            Ok(fingerprint) => {
+                if !fingerprint.ranked {
+                    warn!("This game is unranked! Cheats: {:?}, speeds: {:?}", fingerprint.cheats, speeds);
+                }
+                info!("Settings fingerprint: {}", fingerprint.hash);
+                commands.insert_resource(fingerprint);
+            },
+            Err(e) => error!("Failed to fingerprint settings: {}", e),
+        }
+    }
+}
+
+pub struct FingerprintPlugin;
+
+impl Plugin for FingerprintPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(SettingsFingerprint::update);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(tunnel_speed: f32) -> MapHeader {
+        MapHeader { tunnel_speed: tunnel_speed, ..Default::default() }
+    }
+
+    #[test]
+    fn speeds_follow_the_level_and_the_map() {
+        let standard = MapHeader::default();
+        let slow = LevelSettings { pacman_speed: 0.9, ghost_speed: 0.8, frightened_time: 60, ..Default::default() };
+        let speeds = SpeedTable::current(&slow, &header(0.25));
+
+        assert_eq!((speeds.pacman_speed, speeds.ghost_speed, speeds.frightened_time, speeds.tunnel_speed), (0.9, 0.8, 60, 0.25));
+        assert!(SpeedTable::current(&LevelSettings::default(), &standard).is_standard());
+        assert!(!speeds.is_standard());
+        assert!(!SpeedTable::current(&LevelSettings { pacman_speed: 1.1, ..Default::default() }, &standard).is_standard());
+        assert!(!SpeedTable::current(&LevelSettings::default(), &header(standard.tunnel_speed / 2.0)).is_standard());
+    }
+
+    #[test]
+    fn changed_speeds_unrank_the_game() {
+        let standard = SpeedTable::current(&LevelSettings::default(), &MapHeader::default());
+        let slow = SpeedTable::current(&LevelSettings { ghost_speed: 0.5, ..Default::default() }, &MapHeader::default());
+        let fingerprint = |cheats: &Cheats, speeds: &SpeedTable| SettingsFingerprint::new(cheats, speeds, "maze").unwrap();
+
+        let ranked = fingerprint(&Cheats::default(), &standard);
+        assert!(ranked.ranked);
+        assert_eq!(ranked, fingerprint(&Cheats::default(), &standard));
+
+        let unranked = fingerprint(&Cheats::default(), &slow);
+        assert!(!unranked.ranked);
+        assert_ne!(unranked.hash, ranked.hash);
+
+        let cheated = fingerprint(&Cheats { disable_inky: true, ..Default::default() }, &standard);
+        assert_eq!((cheated.ranked, cheated.cheats), (false, vec!["disable_inky".to_string()]));
+    }
+}
@@ -0,0 +1,393 @@
+use bevy::{prelude::*, ecs::system::SystemParam};
+
+use super::{player::{PlayerData, GameData, PlayerMovement, Seat}, map::{CurrentMap, campaign::Campaign}, score::HighScoreTable, text::{BitmapText, SetBitmapText, BitmapTextBundle, TextAlign}, assets::GameAssets, players::{Players, MAX_PACMEN, SEAT_COLOURS}, GameState, TILE_SIZE};
+
+const HUD_Z: f32 = 950.0;
+
+/// One glyph takes up one tile, like the arcade
+const GLYPH_SCALE: f32 = TILE_SIZE * 10.0 / 8.0;
+const GLYPH_SIZE: f32 = TILE_SIZE * 10.0;
+
+const SCORE_DIGITS: usize = 7;
+const MAX_LIFE_ICONS: usize = 5;
+const FRUIT_HISTORY: usize = 7;
+
+/// Index of the left facing Pac-Man in sprites.png
+const LIFE_ICON_INDEX: usize = 2;
+
+/// Marks everything spawned for the HUD
+#[derive(Component)]
+pub struct Hud;
+
+/// Score holds the player index
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HudNumber {
+    Score(usize), HighScore
+}
+
+/// Only shown in a two player game
+#[derive(Component)]
+pub struct SecondPlayer;
+
+/// Shown while in GameState::Ready
+#[derive(Component)]
+pub struct ReadyBanner;
+
+/// PLAYER ONE / PLAYER TWO, shown with READY! in a two player game
+#[derive(Component)]
+pub struct PlayerBanner;
+
+#[derive(Component)]
+pub struct LifeIcon(pub usize);
+
+/// Slot 0 is the most recent level
+#[derive(Component)]
+pub struct FruitIcon(pub usize);
+
+/// A Pac-Man's score and lives in a simultaneous game, these replace the life and fruit icons
+#[derive(Component)]
+pub struct SeatStatus(pub usize);
+
+type NewNumbers = Or<(Added<HudNumber>, Added<SeatStatus>)>;
+type NewBanners = Or<(Added<ReadyBanner>, Added<PlayerBanner>)>;
+
+/// Where the numbers on the HUD come from
+#[derive(SystemParam)]
+pub struct ScoreSources<'w, 's> {
+    player: Query<'w, 's, (&'static Seat, &'static PlayerData), With<PlayerMovement>>,
+    table: Res<'w, HighScoreTable>,
+    players: Res<'w, Players>,
+    current_map: Option<Res<'w, CurrentMap>>,
+}
+
+/// The fruit row of general_sprites.png in order, as named in a campaign
+pub const FRUIT_NAMES: [&str; 8] = ["cherry", "strawberry", "orange", "apple", "melon", "galaxian", "bell", "key"];
+
+/// Which fruit a level shows (index into the fruit row of general_sprites.png)
+/// Level is 1 based
+pub fn fruit_for_level(level: usize) -> usize {
+    match level {
+        0 | 1 => 0,     // Cherry
+        2 => 1,         // Strawberry
+        3 | 4 => 2,     // Orange
+        5 | 6 => 3,     // Apple
+        7 | 8 => 4,     // Melon
+        9 | 10 => 5,    // Galaxian
+        11 | 12 => 6,   // Bell
+        _ => 7,         // Key
+    }
+}
+
+/// The arcade shows "00" before anything is scored
+fn score_text(score: usize) -> String {
+    if score == 0 { "00".to_string() } else { score.to_string() }
+}
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(Hud::spawn)
+            .add_system(Hud::update_scores.after(Hud::spawn))
+            .add_system(Hud::update_lives.after(Hud::spawn))
+            .add_system(Hud::update_fruit.after(Hud::spawn))
+            .add_system(Hud::ready_banner.after(Hud::spawn))
+            .add_system(Hud::second_player.after(Hud::spawn))
+
+        ;
+    }
+}
+
+impl Hud {
+    /// (Re)builds the HUD around the map whenever the map changes
+    pub fn spawn(
+        mut commands: Commands,
+        current_map: Option<Res<CurrentMap>>,
+        old_hud: Query<Entity, With<Hud>>,
+        assets: Res<GameAssets>,
+    ) {
+        let Some(current_map) = current_map else { return };
+        if !current_map.is_changed() { return }
+
+        for entity in &old_hud {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        let width = current_map.width as f32;
+        let height = current_map.height as f32;
+
+        // Two rows above the maze for the scores, two below for lives and fruit
+        let label_row = current_map.tile_pos(0.0, -2.0).y;
+        let number_row = current_map.tile_pos(0.0, -1.0).y;
+        let bottom_row = current_map.tile_pos(0.0, height + 0.5).y;
+        let left = current_map.tile_pos(0.0, 0.0).x;
+        let right = current_map.tile_pos(width - 1.0, 0.0).x;
+        let centre = (left + right) / 2.0;
+
+        let text = |text: BitmapText, x: f32, y: f32| BitmapTextBundle::new(text, Vec3 { x: x, y: y, z: HUD_Z }, GLYPH_SCALE);
+
+        commands.spawn(text(BitmapText::new("1UP"), left + GLYPH_SIZE * 1.5, label_row)).insert(Hud);
+        commands.spawn(text(BitmapText::new("HIGH SCORE").with_align(TextAlign::Centre), centre, label_row)).insert(Hud);
+        commands.spawn(text(BitmapText::new("2UP").with_align(TextAlign::Right), right - GLYPH_SIZE * 1.5, label_row)).insert(Hud).insert(SecondPlayer);
+
+        commands.spawn(text(BitmapText::new("").with_align(TextAlign::Right).with_width(SCORE_DIGITS), left + GLYPH_SIZE * (SCORE_DIGITS as f32 - 0.5), number_row))
+            .insert(Hud).insert(HudNumber::Score(0));
+        commands.spawn(text(BitmapText::new("").with_align(TextAlign::Right).with_width(SCORE_DIGITS), centre + GLYPH_SIZE * 3.0, number_row))
+            .insert(Hud).insert(HudNumber::HighScore);
+        commands.spawn(text(BitmapText::new("").with_align(TextAlign::Right).with_width(SCORE_DIGITS), right - GLYPH_SIZE * 0.5, number_row))
+            .insert(Hud).insert(HudNumber::Score(1)).insert(SecondPlayer);
+
+        // The arcade puts READY! on the row below the ghost house and PLAYER ONE above it, the middle of the maze is close enough
+        commands.spawn(text(BitmapText::new("READY!").with_colour(Color::YELLOW).with_align(TextAlign::Centre), centre, current_map.tile_pos(0.0, (height / 2.0).floor() + 3.0).y))
+            .insert(Hud).insert(ReadyBanner);
+        commands.spawn(text(BitmapText::new("").with_colour(Color::CYAN).with_align(TextAlign::Centre), centre, current_map.tile_pos(0.0, (height / 2.0).floor() - 3.0).y))
+            .insert(Hud).insert(PlayerBanner);
+
+        for slot in 0..MAX_LIFE_ICONS {
+            commands.spawn(SpriteSheetBundle {
+                texture_atlas: assets.player.clone(),
+                sprite: TextureAtlasSprite::new(LIFE_ICON_INDEX),
+                visibility: Visibility::Hidden,
+                transform: Transform {
+                    translation: Vec3 { x: left + GLYPH_SIZE * (1.0 + slot as f32 * 2.0), y: bottom_row, z: HUD_Z },
+                    scale: Vec3::splat(TILE_SIZE),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }).insert(Hud).insert(LifeIcon(slot)).insert(Name::new("HUD Life"));
+        }
+
+        // The bottom row split evenly between every possible Pac-Man
+        let column = (current_map.width / MAX_PACMEN).max(1);
+        for (seat, colour) in SEAT_COLOURS.into_iter().enumerate() {
+            let x = left + GLYPH_SIZE * ((column * (seat + 1)) as f32 - 1.0);
+            commands.spawn(text(BitmapText::new("").with_colour(colour).with_align(TextAlign::Right).with_width(column.min(SCORE_DIGITS)), x, bottom_row))
+                .insert(Hud).insert(SeatStatus(seat)).insert(Name::new("HUD Seat"));
+        }
+
+        for slot in 0..FRUIT_HISTORY {
+            commands.spawn(SpriteSheetBundle {
+                texture_atlas: assets.fruit.clone(),
+                visibility: Visibility::Hidden,
+                transform: Transform {
+                    translation: Vec3 { x: right - GLYPH_SIZE * (0.5 + slot as f32 * 2.0), y: bottom_row, z: HUD_Z },
+                    scale: Vec3::splat(GLYPH_SCALE),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }).insert(Hud).insert(FruitIcon(slot)).insert(Name::new("HUD Fruit"));
+        }
+
+        info!("HUD ready for a {}x{} map", current_map.width, current_map.height);
+    }
+
+    pub fn update_scores(
+        sources: ScoreSources,
+        changed_player: Query<(), (With<PlayerMovement>, Changed<PlayerData>)>,
+        new_numbers: Query<(), NewNumbers>,
+        mut numbers: Query<(&HudNumber, &mut BitmapText), Without<SeatStatus>>,
+        mut statuses: Query<(&SeatStatus, &mut BitmapText, &mut Visibility), Without<HudNumber>>,
+    ) {
+        let ScoreSources { player, table, players, current_map } = sources;
+        if changed_player.is_empty() && new_numbers.is_empty() && !table.is_changed() && !players.is_changed() { return }
+
+        let best = current_map.and_then(|map| table.best(&map.hash).map(|entry| entry.score)).unwrap_or(0);
+        let seat = |index: usize| player.iter().find(|(seat, _)| seat.0 == index).map(|(_, data)| data);
+
+        // Every Pac-Man has its own score in a simultaneous game, a ghost player's is kept in their slot
+        // otherwise the player entity has the current player's score and everyone else's is in their slot
+        let slot_score = |index: usize| players.slots.get(index).map(|slot| slot.score).unwrap_or(0);
+        let score = |index: usize| match (players.is_simultaneous(), index == players.current) {
+            (true, _) => seat(index).map(|data| data.score).unwrap_or_else(|| slot_score(index)),
+            (false, true) => seat(0).map(|data| data.score).unwrap_or(0),
+            (false, false) => slot_score(index),
+        };
+        let top = (0..players.count()).map(score).max().unwrap_or(0);
+
+        for (number, mut text) in &mut numbers {
+            match number {
+                HudNumber::Score(index) => text.set(score_text(score(*index))),
+                HudNumber::HighScore => text.set(score_text(best.max(top))),
+            }
+        }
+
+        for (status, mut text, mut visability) in &mut statuses {
+            let Some(data) = seat(status.0).filter(|_| players.is_simultaneous()) else {
+                *visability = Visibility::Hidden;
+                continue
+            };
+
+            text.set(format!("{}X{}", score_text(data.score), if data.out { 0 } else { data.lives + 1 }));
+            *visability = Visibility::Visible;
+        }
+    }
+
+    /// Player one's (or the current player's) spare lives, hidden in a simultaneous game
+    pub fn update_lives(
+        player: Query<(&Seat, &PlayerData), With<PlayerMovement>>,
+        changed_player: Query<(), (With<PlayerMovement>, Changed<PlayerData>)>,
+        new_icons: Query<(), Added<LifeIcon>>,
+        players: Res<Players>,
+        mut icons: Query<(&LifeIcon, &mut Visibility)>,
+    ) {
+        if changed_player.is_empty() && new_icons.is_empty() && !players.is_changed() { return }
+        let Some((_, data)) = player.iter().find(|(seat, _)| seat.0 == 0) else { return };
+
+        for (icon, mut visability) in &mut icons {
+            *visability = if (icon.0 as u64) < data.lives && !players.is_simultaneous() { Visibility::Visible } else { Visibility::Hidden };
+        }
+    }
+
+    pub fn update_fruit(
+        game: Query<(&Seat, &GameData), With<PlayerMovement>>,
+        changed_game: Query<(), (With<PlayerMovement>, Changed<GameData>)>,
+        new_icons: Query<(), Added<FruitIcon>>,
+        players: Res<Players>,
+        campaign: Option<Res<Campaign>>,
+        mut icons: Query<(&FruitIcon, &mut TextureAtlasSprite, &mut Visibility)>,
+    ) {
+        if changed_game.is_empty() && new_icons.is_empty() && !players.is_changed() { return }
+        let Some((_, game_data)) = game.iter().find(|(seat, _)| seat.0 == 0) else { return };
+
+        // GameData.level starts at 0
+        let level = game_data.level + 1;
+
+        for (icon, mut sprite, mut visability) in &mut icons {
+            if icon.0 < level && !players.is_simultaneous() {
+                sprite.index = match &campaign {
+                    Some(campaign) => campaign.fruit(level - icon.0 - 1),
+                    None => fruit_for_level(level - icon.0),
+                };
+                *visability = Visibility::Visible;
+            } else {
+                *visability = Visibility::Hidden;
+            }
+        }
+    }
+
+    /// Shows READY! (and whose turn it is) while in GameState::Ready
+    pub fn ready_banner(
+        state: Res<State<GameState>>,
+        players: Res<Players>,
+        new_banners: Query<(), NewBanners>,
+        mut ready: Query<&mut Visibility, (With<ReadyBanner>, Without<PlayerBanner>)>,
+        mut banner: Query<(&mut BitmapText, &mut Visibility), With<PlayerBanner>>,
+    ) {
+        if !state.is_changed() && !players.is_changed() && new_banners.is_empty() { return }
+
+        let is_ready = state.0 == GameState::Ready;
+
+        for mut visability in &mut ready {
+            *visability = if is_ready { Visibility::Visible } else { Visibility::Hidden };
+        }
+
+        for (mut text, mut visability) in &mut banner {
+            text.set(Players::name(players.current));
+            *visability = if is_ready && players.count() > 1 && !players.is_simultaneous() { Visibility::Visible } else { Visibility::Hidden };
+        }
+    }
+
+    pub fn second_player(
+        players: Res<Players>,
+        new_hud: Query<(), Added<SecondPlayer>>,
+        mut hud: Query<&mut Visibility, With<SecondPlayer>>,
+    ) {
+        if !players.is_changed() && new_hud.is_empty() { return }
+
+        for mut visability in &mut hud {
+            *visability = if players.count() > 1 { Visibility::Visible } else { Visibility::Hidden };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hud(map: &str) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_plugin(AssetPlugin::default()).add_asset::<Image>().add_asset::<TextureAtlas>()
+            .add_state::<GameState>()
+            .init_resource::<GameAssets>()
+            .init_resource::<HighScoreTable>()
+            .init_resource::<Players>()
+            .insert_resource(CurrentMap::new(map).unwrap())
+            .add_plugin(HudPlugin);
+
+        app.world.spawn((PlayerMovement, Seat(0), PlayerData::default(), GameData { level: 0, transitioning: false }));
+
+        // The HUD is spawned by the first update and filled in by the second
+        app.update();
+        app.update();
+        app
+    }
+
+    fn text_at(app: &mut App, label: &str) -> Vec3 {
+        let mut texts = app.world.query::<(&BitmapText, &Transform)>();
+        texts.iter(&app.world).find(|(text, _)| text.text == label).map(|(_, transform)| transform.translation).unwrap()
+    }
+
+    fn icons_at<T: Component>(app: &mut App) -> Vec<Vec3> {
+        let mut icons = app.world.query_filtered::<&Transform, With<T>>();
+        icons.iter(&app.world).map(|transform| transform.translation).collect()
+    }
+
+    /// The HUD sits in the two rows above and below the maze, inside its width
+    fn check_layout(map: &str) {
+        let mut app = hud(map);
+        let current_map = app.world.resource::<CurrentMap>().clone();
+        let (left, right) = (current_map.tile_pos(0.0, 0.0).x, current_map.tile_pos(current_map.width as f32 - 1.0, 0.0).x);
+        let bottom = current_map.tile_pos(0.0, current_map.height as f32 + 0.5).y;
+
+        let one_up = text_at(&mut app, "1UP");
+        assert_eq!(one_up.y, current_map.tile_pos(0.0, -2.0).y);
+        assert_eq!(one_up.x, left + GLYPH_SIZE * 1.5);
+
+        let high_score = text_at(&mut app, "HIGH SCORE");
+        assert_eq!(high_score.y, one_up.y);
+        assert_eq!(high_score.x, (left + right) / 2.0);
+        // The map is centred on the screen, give or take its rounded origin
+        assert!(high_score.x.abs() <= 1.0);
+
+        let lives = icons_at::<LifeIcon>(&mut app);
+        assert_eq!(lives.len(), MAX_LIFE_ICONS);
+        assert!(lives.iter().all(|life| life.y == bottom && life.x >= left));
+
+        let fruit = icons_at::<FruitIcon>(&mut app);
+        assert_eq!(fruit.len(), FRUIT_HISTORY);
+        assert!(fruit.iter().all(|fruit| fruit.y == bottom && fruit.x <= right));
+        assert_eq!(fruit.iter().map(|fruit| fruit.x).reduce(f32::max), Some(right - GLYPH_SIZE * 0.5));
+    }
+
+    #[test]
+    fn lays_out_around_an_odd_map() {
+        check_layout("#########\n#S.....B#\n#.#####.#\n#.......#\n#########\n");
+    }
+
+    #[test]
+    fn lays_out_around_an_even_map() {
+        check_layout("##########\n#S......B#\n#.######.#\n#.######.#\n#........#\n##########\n");
+    }
+
+    #[test]
+    fn scores_update_when_player_data_does() {
+        let mut app = hud("#####\n#S..#\n#####\n");
+        let score = |app: &mut App| {
+            let mut numbers = app.world.query::<(&HudNumber, &BitmapText)>();
+            numbers.iter(&app.world).find(|(number, _)| **number == HudNumber::Score(0)).map(|(_, text)| text.text.clone()).unwrap()
+        };
+        assert_eq!(score(&mut app), "00");
+
+        // Left alone while nothing changes
+        let mut numbers = app.world.query::<(&HudNumber, &mut BitmapText)>();
+        for (_, mut text) in numbers.iter_mut(&mut app.world) { text.text = "STALE".to_string() }
+        app.update();
+        assert_eq!(score(&mut app), "STALE");
+
+        let mut data = app.world.query::<&mut PlayerData>();
+        data.single_mut(&mut app.world).score = 120;
+        app.update();
+        assert_eq!(score(&mut app), "120");
+    }
+}
@@ -0,0 +1,807 @@
+use std::{collections::{HashMap, HashSet}, fs, path::Path};
+
+use anyhow::Result;
+use bevy::{prelude::*, input::InputSystem, ecs::system::SystemParam};
+use serde_json::Value;
+
+use super::{util::{get_settings, DEFAULT_SETTINGS}, text::{BitmapText, SetBitmapText, BitmapTextBundle, TextAlign}, players::{Players, MAX_PACMEN}, GameState, TILE_SIZE};
+
+const SETTINGS_FILE: &str = "./settings.toml";
+
+/// Stick movement under this is ignored
+const DEFAULT_DEADZONE: f32 = 0.5;
+
+/// Keys that can be bound, named as in KeyCode
+const KEYS: &[KeyCode] = &[
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G, KeyCode::H, KeyCode::I,
+    KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+    KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9, KeyCode::Key0,
+    KeyCode::Up, KeyCode::Down, KeyCode::Left, KeyCode::Right,
+    KeyCode::Escape, KeyCode::Return, KeyCode::Space, KeyCode::Back, KeyCode::Tab,
+    KeyCode::LShift, KeyCode::RShift, KeyCode::LControl, KeyCode::RControl, KeyCode::LAlt, KeyCode::RAlt,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9, KeyCode::NumpadEnter,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Semicolon, KeyCode::Apostrophe,
+    KeyCode::Minus, KeyCode::Equals, KeyCode::LBracket, KeyCode::RBracket, KeyCode::Backslash, KeyCode::Grave,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+];
+
+const BUTTONS: &[GamepadButtonType] = &[
+    GamepadButtonType::South, GamepadButtonType::East, GamepadButtonType::North, GamepadButtonType::West,
+    GamepadButtonType::C, GamepadButtonType::Z,
+    GamepadButtonType::LeftTrigger, GamepadButtonType::LeftTrigger2, GamepadButtonType::RightTrigger, GamepadButtonType::RightTrigger2,
+    GamepadButtonType::Select, GamepadButtonType::Start, GamepadButtonType::Mode,
+    GamepadButtonType::LeftThumb, GamepadButtonType::RightThumb,
+    GamepadButtonType::DPadUp, GamepadButtonType::DPadDown, GamepadButtonType::DPadLeft, GamepadButtonType::DPadRight,
+];
+
+const AXES: &[GamepadAxisType] = &[
+    GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY, GamepadAxisType::LeftZ,
+    GamepadAxisType::RightStickX, GamepadAxisType::RightStickY, GamepadAxisType::RightZ,
+];
+
+/// Everything the game reads input as, the keys and buttons behind them come from InputBindings
+/// Maps and Editor open the map browser and the map editor from the title, TestPlay, Undo, Redo and Save are the editor's own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Up, Down, Left, Right, Pause, Confirm, Back,
+    Maps, Editor, TestPlay, Undo, Redo, Save,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::Up, Action::Down, Action::Left, Action::Right, Action::Pause, Action::Confirm, Action::Back,
+        Action::Maps, Action::Editor, Action::TestPlay, Action::Undo, Action::Redo, Action::Save,
+    ];
+
+    /// The only actions each Pac-Man has its own bindings for
+    pub const DIRECTIONS: [Action; 4] = [Action::Up, Action::Down, Action::Left, Action::Right];
+
+    /// Name in the [input] section of settings.toml
+    pub fn setting(&self) -> &'static str {
+        match self {
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Pause => "pause",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+            Action::Maps => "maps",
+            Action::Editor => "editor",
+            Action::TestPlay => "test_play",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Save => "save",
+        }
+    }
+}
+
+/// One key, button or stick direction
+/// In settings.toml keys are written as their KeyCode ("W", "Return"), with "Ctrl+" and "Shift+" in front
+/// for a chord ("Ctrl+Shift+Z"), and gamepad inputs with a "Gamepad:" prefix ("Gamepad:South", "Gamepad:LeftStickX-")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    /// Not while Ctrl or Shift is held, unless it's one of them
+    Key(KeyCode),
+    /// A key with exactly these modifiers held
+    Chord { ctrl: bool, shift: bool, key: KeyCode },
+    Button(GamepadButtonType),
+    Axis { axis: GamepadAxisType, positive: bool },
+}
+
+const CTRL_KEYS: [KeyCode; 2] = [KeyCode::LControl, KeyCode::RControl];
+const SHIFT_KEYS: [KeyCode; 2] = [KeyCode::LShift, KeyCode::RShift];
+
+impl Binding {
+    pub fn parse(name: &str) -> Option<Self> {
+        let Some(gamepad) = name.strip_prefix("Gamepad:") else {
+            let (mut ctrl, mut shift, mut key) = (false, false, name);
+            loop {
+                if let Some(rest) = key.strip_prefix("Ctrl+") { ctrl = true; key = rest; continue }
+                if let Some(rest) = key.strip_prefix("Shift+") { shift = true; key = rest; continue }
+                break
+            }
+
+            let key = *KEYS.iter().find(|k| format!("{:?}", k) == key)?;
+            return Some(if ctrl || shift { Binding::Chord { ctrl: ctrl, shift: shift, key: key } } else { Binding::Key(key) })
+        };
+
+        if let Some(button) = BUTTONS.iter().find(|button| format!("{:?}", button) == gamepad) {
+            return Some(Binding::Button(*button))
+        }
+
+        let (axis, positive) = match gamepad.strip_suffix('+') {
+            Some(axis) => (axis, true),
+            None => (gamepad.strip_suffix('-')?, false),
+        };
+        AXES.iter().find(|a| format!("{:?}", a) == axis).map(|axis| Binding::Axis { axis: *axis, positive: positive })
+    }
+
+    /// As written in settings.toml
+    pub fn to_setting(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Chord { ctrl, shift, key } => format!("{}{}{:?}", if *ctrl { "Ctrl+" } else { "" }, if *shift { "Shift+" } else { "" }, key),
+            Binding::Button(button) => format!("Gamepad:{:?}", button),
+            Binding::Axis { axis, positive } => format!("Gamepad:{:?}{}", axis, if *positive { "+" } else { "-" }),
+        }
+    }
+
+    /// Shown in the options menu, the font has no + or :
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key).to_uppercase(),
+            Binding::Chord { ctrl, shift, key } => format!("{}{}{:?}", if *ctrl { "CTRL " } else { "" }, if *shift { "SHIFT " } else { "" }, key).to_uppercase(),
+            Binding::Button(button) => format!("PAD {:?}", button).to_uppercase(),
+            Binding::Axis { axis, positive } => format!("PAD {:?}{}", axis, if *positive { " POS" } else { " NEG" }).to_uppercase(),
+        }
+    }
+
+    fn is_gamepad(&self) -> bool {
+        !matches!(self, Binding::Key(_) | Binding::Chord { .. })
+    }
+
+    fn active(&self, devices: &Devices) -> bool {
+        match self {
+            // With Ctrl or Shift held it's a chord, so Ctrl+S saves without also going down
+            Binding::Key(key) if is_modifier(key) => devices.keyboard.pressed(*key),
+            Binding::Key(key) => devices.keyboard.pressed(*key) && !devices.keyboard.any_pressed(CTRL_KEYS) && !devices.keyboard.any_pressed(SHIFT_KEYS),
+            Binding::Chord { ctrl, shift, key } => {
+                devices.keyboard.pressed(*key) && devices.keyboard.any_pressed(CTRL_KEYS) == *ctrl && devices.keyboard.any_pressed(SHIFT_KEYS) == *shift
+            },
+            Binding::Button(button) => devices.pads.iter().any(|gamepad| devices.buttons.pressed(GamepadButton::new(*gamepad, *button))),
+            Binding::Axis { axis, positive } => devices.pads.iter().any(|gamepad| {
+                let value = devices.axes.get(GamepadAxis::new(*gamepad, *axis)).unwrap_or(0.0);
+                if *positive { value > devices.deadzone } else { value < -devices.deadzone }
+            }),
+        }
+    }
+
+    /// Whatever was just pressed, for rebinding
+    /// Ctrl and Shift wait for the key they're held with, they can still be bound on their own in settings.toml
+    fn captured(devices: &Devices) -> Option<Self> {
+        if let Some(key) = devices.keyboard.get_just_pressed().find(|key| KEYS.contains(key) && !is_modifier(key)) {
+            let (ctrl, shift) = (devices.keyboard.any_pressed(CTRL_KEYS), devices.keyboard.any_pressed(SHIFT_KEYS));
+            return Some(if ctrl || shift { Binding::Chord { ctrl: ctrl, shift: shift, key: *key } } else { Binding::Key(*key) })
+        }
+
+        if let Some(button) = devices.buttons.get_just_pressed().find(|button| BUTTONS.contains(&button.button_type)) {
+            return Some(Binding::Button(button.button_type))
+        }
+
+        for gamepad in &devices.pads {
+            for axis in AXES {
+                let value = devices.axes.get(GamepadAxis::new(*gamepad, *axis)).unwrap_or(0.0);
+                if value.abs() > devices.deadzone {
+                    return Some(Binding::Axis { axis: *axis, positive: value > 0.0 })
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn is_modifier(key: &KeyCode) -> bool {
+    CTRL_KEYS.contains(key) || SHIFT_KEYS.contains(key)
+}
+
+/// Every input resource, for systems that build Devices on top of a lot else
+#[derive(SystemParam)]
+pub struct DeviceInputs<'w> {
+    keyboard: Res<'w, Input<KeyCode>>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    gamepads: Res<'w, Gamepads>,
+}
+
+/// The input resources bindings are checked against
+/// Gamepad bindings only count on the pads listed
+struct Devices<'a> {
+    keyboard: &'a Input<KeyCode>,
+    buttons: &'a Input<GamepadButton>,
+    axes: &'a Axis<GamepadAxis>,
+    pads: Vec<Gamepad>,
+    deadzone: f32,
+}
+
+/// Which keys and buttons trigger each action, from the [input] section of settings.toml
+#[derive(Debug, Clone, Resource)]
+pub struct InputBindings {
+    pub bindings: HashMap<Action, Vec<Binding>>,
+    /// 0 - 1, applies to the sticks (and pads that report the D-pad as a stick)
+    pub deadzone: f32,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        let parse = |names: &[&str]| names.iter().filter_map(|name| Binding::parse(name)).collect::<Vec<Binding>>();
+
+        let bindings = HashMap::from([
+            (Action::Up, parse(&["W", "Up", "Gamepad:DPadUp", "Gamepad:LeftStickY+"])),
+            (Action::Down, parse(&["S", "Down", "Gamepad:DPadDown", "Gamepad:LeftStickY-"])),
+            (Action::Left, parse(&["A", "Left", "Gamepad:DPadLeft", "Gamepad:LeftStickX-"])),
+            (Action::Right, parse(&["D", "Right", "Gamepad:DPadRight", "Gamepad:LeftStickX+"])),
+            (Action::Pause, parse(&["Escape", "Gamepad:Start"])),
+            (Action::Confirm, parse(&["Return", "Gamepad:South"])),
+            (Action::Back, parse(&["Back", "Gamepad:East"])),
+            (Action::Maps, parse(&["M"])),
+            (Action::Editor, parse(&["E"])),
+            (Action::TestPlay, parse(&["F5"])),
+            (Action::Undo, parse(&["Ctrl+Z"])),
+            (Action::Redo, parse(&["Ctrl+Y", "Ctrl+Shift+Z"])),
+            (Action::Save, parse(&["Ctrl+S"])),
+        ]);
+
+        Self { bindings: bindings, deadzone: DEFAULT_DEADZONE }
+    }
+}
+
+impl InputBindings {
+    /// Missing actions keep their default bindings, unknown names are skipped
+    pub fn from_settings(settings: &Value) -> Self {
+        let mut input = Self::default();
+        let section = &settings["input"];
+
+        if let Some(deadzone) = section["deadzone"].as_f64() {
+            input.deadzone = (deadzone as f32).clamp(0.0, 1.0);
+        }
+
+        for action in Action::ALL {
+            let Some(names) = section[action.setting()].as_array() else { continue };
+
+            let mut bindings = Vec::new();
+            for name in names.iter().filter_map(|name| name.as_str()) {
+                match Binding::parse(name) {
+                    Some(binding) => bindings.push(binding),
+                    None => warn!("Unknown input {:?} for {}", name, action.setting()),
+                }
+            }
+
+            input.bindings.insert(action, bindings);
+        }
+
+        input
+    }
+
+    pub fn load() -> Self {
+        match get_settings() {
+            Ok(settings) => Self::from_settings(&settings),
+            Err(e) => { error!("Failed to read input settings: {}", e); Self::default() },
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map(|bindings| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    /// Bindings used by more than one action
+    pub fn conflicts(&self) -> Vec<(Binding, Vec<Action>)> {
+        let mut conflicts = Vec::new();
+        let mut seen = HashSet::new();
+
+        for action in Action::ALL {
+            for binding in self.get(action) {
+                if !seen.insert(*binding) { continue }
+
+                let actions: Vec<Action> = Action::ALL.into_iter().filter(|other| self.get(*other).contains(binding)).collect();
+                if actions.len() > 1 {
+                    conflicts.push((*binding, actions));
+                }
+            }
+        }
+
+        conflicts
+    }
+
+    /// Replaces the action's binding for the same device (keyboard or gamepad)
+    /// Fails with the action that already uses it
+    pub fn rebind(&mut self, action: Action, binding: Binding) -> Result<(), Action> {
+        if let Some(other) = Action::ALL.into_iter().find(|other| *other != action && self.get(*other).contains(&binding)) {
+            return Err(other)
+        }
+
+        let bindings = self.bindings.entry(action).or_default();
+        match bindings.iter_mut().find(|existing| existing.is_gamepad() == binding.is_gamepad()) {
+            Some(existing) => *existing = binding,
+            None => bindings.push(binding),
+        }
+
+        Ok(())
+    }
+
+    /// Writes the bindings into the [input] section of a settings.toml
+    /// Only the keys set here are replaced, comments, unknown keys and the other sections are kept as they are
+    fn write_into(&self, settings: &str) -> Result<String> {
+        let mut document: toml_edit::Document = settings.parse()?;
+
+        let input = document.entry("input").or_insert(toml_edit::table());
+        let Some(input) = input.as_table_mut() else { anyhow::bail!("[input] in settings.toml isn't a table") };
+
+        // Indexing keeps an existing key (and the comments above it), only its value changes
+        input["deadzone"] = toml_edit::value(self.deadzone as f64);
+        for action in Action::ALL {
+            let names: toml_edit::Array = self.get(action).iter().map(|binding| binding.to_setting()).collect();
+            input[action.setting()] = toml_edit::value(names);
+        }
+
+        Ok(document.to_string())
+    }
+
+    /// Rewrites the bindings in settings.toml, everything else in the file is kept
+    pub fn save(&self) -> Result<()> {
+        let settings = if Path::new(SETTINGS_FILE).exists() { fs::read_to_string(SETTINGS_FILE)? } else { DEFAULT_SETTINGS.to_string() };
+
+        fs::write(SETTINGS_FILE, self.write_into(&settings)?)?;
+
+        Ok(())
+    }
+}
+
+/// The state of every action this frame
+#[derive(Debug, Clone, Default, Resource)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn update(
+        keyboard: Res<Input<KeyCode>>,
+        buttons: Res<Input<GamepadButton>>,
+        axes: Res<Axis<GamepadAxis>>,
+        gamepads: Res<Gamepads>,
+        bindings: Res<InputBindings>,
+        mut state: ResMut<Self>,
+    ) {
+        let devices = Devices { keyboard: &keyboard, buttons: &buttons, axes: &axes, pads: gamepads.iter().collect(), deadzone: bindings.deadzone };
+
+        let pressed: HashSet<Action> = Action::ALL.into_iter()
+            .filter(|action| bindings.get(*action).iter().any(|binding| binding.active(&devices)))
+            .collect();
+
+        state.just_pressed = pressed.difference(&state.pressed).copied().collect();
+        state.pressed = pressed;
+    }
+}
+
+/// (seat, action), the seat is None for the shared bindings
+pub type BindingUse = (Option<usize>, Action);
+
+/// Direction bindings for each Pac-Man in a simultaneous game, from [input.player1] .. [input.player4]
+/// Gamepad inputs only count on that player's pad, the first pad connected is player one's
+#[derive(Debug, Clone, Resource)]
+pub struct SeatBindings {
+    pub seats: Vec<HashMap<Action, Vec<Binding>>>,
+}
+
+impl Default for SeatBindings {
+    fn default() -> Self {
+        let keys: [[&str; 4]; MAX_PACMEN] = [
+            ["W", "S", "A", "D"],
+            ["Up", "Down", "Left", "Right"],
+            ["I", "K", "J", "L"],
+            ["Numpad8", "Numpad5", "Numpad4", "Numpad6"],
+        ];
+        let pad = ["Gamepad:DPadUp", "Gamepad:DPadDown", "Gamepad:DPadLeft", "Gamepad:DPadRight"];
+        let stick = ["Gamepad:LeftStickY+", "Gamepad:LeftStickY-", "Gamepad:LeftStickX-", "Gamepad:LeftStickX+"];
+
+        let seats = keys.iter().map(|keys| {
+            Action::DIRECTIONS.iter().enumerate()
+                .map(|(i, action)| (*action, [keys[i], pad[i], stick[i]].iter().filter_map(|name| Binding::parse(name)).collect()))
+                .collect()
+        }).collect();
+
+        Self { seats: seats }
+    }
+}
+
+impl SeatBindings {
+    /// Like InputBindings, missing directions keep their defaults
+    pub fn from_settings(settings: &Value) -> Self {
+        let mut input = Self::default();
+
+        for (seat, bindings) in input.seats.iter_mut().enumerate() {
+            let section = &settings["input"][format!("player{}", seat + 1)];
+
+            for action in Action::DIRECTIONS {
+                let Some(names) = section[action.setting()].as_array() else { continue };
+
+                let mut parsed = Vec::new();
+                for name in names.iter().filter_map(|name| name.as_str()) {
+                    match Binding::parse(name) {
+                        Some(binding) => parsed.push(binding),
+                        None => warn!("Unknown input {:?} for player{} {}", name, seat + 1, action.setting()),
+                    }
+                }
+
+                bindings.insert(action, parsed);
+            }
+        }
+
+        input
+    }
+
+    pub fn load() -> Self {
+        match get_settings() {
+            Ok(settings) => Self::from_settings(&settings),
+            Err(e) => { error!("Failed to read input settings: {}", e); Self::default() },
+        }
+    }
+
+    pub fn get(&self, seat: usize, action: Action) -> &[Binding] {
+        self.seats.get(seat).and_then(|bindings| bindings.get(&action)).map(|bindings| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    /// Bindings a seat shares with another seat, or with a different action in the shared bindings
+    /// A seat's gamepad inputs only clash within that seat since every player has their own pad
+    pub fn conflicts(&self, shared: &InputBindings) -> Vec<(Binding, Vec<BindingUse>)> {
+        let mut conflicts = Vec::new();
+        let mut seen = HashSet::new();
+
+        for seat in 0..self.seats.len() {
+            for action in Action::DIRECTIONS {
+                for binding in self.get(seat, action) {
+                    if !seen.insert(*binding) { continue }
+
+                    let mut uses: Vec<BindingUse> = (0..self.seats.len())
+                        .flat_map(|other| Action::DIRECTIONS.into_iter().map(move |action| (other, action)))
+                        .filter(|(other, action)| self.get(*other, *action).contains(binding))
+                        .map(|(other, action)| (Some(other), action))
+                        .collect();
+
+                    // The same direction in the shared bindings is what the seat would do anyway
+                    let shared_uses: Vec<BindingUse> = Action::ALL.into_iter()
+                        .filter(|other| shared.get(*other).contains(binding) && !uses.iter().any(|(_, action)| action == other))
+                        .map(|other| (None, other))
+                        .collect();
+
+                    if binding.is_gamepad() && shared_uses.is_empty() {
+                        let all = uses.clone();
+                        uses.retain(|(seat, _)| all.iter().filter(|(other, _)| other == seat).count() > 1);
+                    }
+
+                    if uses.len() + shared_uses.len() > 1 {
+                        uses.extend(shared_uses);
+                        conflicts.push((*binding, uses));
+                    }
+                }
+            }
+        }
+
+        conflicts
+    }
+}
+
+/// "P2 UP" for a seat's binding, "PAUSE" for a shared one
+pub fn use_label((seat, action): BindingUse) -> String {
+    match seat {
+        Some(seat) => format!("P{} {}", seat + 1, action.setting().to_uppercase()),
+        None => action.setting().to_uppercase(),
+    }
+}
+
+/// The directions each Pac-Man's player is holding this frame
+#[derive(Debug, Clone, Default, Resource)]
+pub struct SeatActions {
+    pressed: Vec<HashSet<Action>>,
+}
+
+impl SeatActions {
+    pub fn pressed(&self, seat: usize, action: Action) -> bool {
+        self.pressed.get(seat).map(|pressed| pressed.contains(&action)).unwrap_or(false)
+    }
+
+    pub fn update(
+        keyboard: Res<Input<KeyCode>>,
+        buttons: Res<Input<GamepadButton>>,
+        axes: Res<Axis<GamepadAxis>>,
+        gamepads: Res<Gamepads>,
+        bindings: Res<InputBindings>,
+        seats: Res<SeatBindings>,
+        mut state: ResMut<Self>,
+    ) {
+        // Oldest pad first, so a player keeps their pad when another is plugged in
+        let mut pads: Vec<Gamepad> = gamepads.iter().collect();
+        pads.sort_by_key(|pad| pad.id);
+
+        state.pressed = (0..seats.seats.len()).map(|seat| {
+            let devices = Devices { keyboard: &keyboard, buttons: &buttons, axes: &axes, pads: pads.get(seat).copied().into_iter().collect(), deadzone: bindings.deadzone };
+
+            Action::DIRECTIONS.into_iter()
+                .filter(|action| seats.get(seat, *action).iter().any(|binding| binding.active(&devices)))
+                .collect()
+        }).collect();
+    }
+}
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        // Bindings already in the App (the env's defaults) are kept, otherwise they come from settings.toml
+        let bindings = app.world.remove_resource::<InputBindings>().unwrap_or_else(InputBindings::load);
+        for (binding, actions) in bindings.conflicts() {
+            warn!("{} is bound to more than one action: {:?}", binding.to_setting(), actions);
+        }
+
+        let seats = app.world.remove_resource::<SeatBindings>().unwrap_or_else(SeatBindings::load);
+        for (binding, uses) in seats.conflicts(&bindings) {
+            warn!("{} is bound to more than one player's action: {}", binding.to_setting(), uses.into_iter().map(use_label).collect::<Vec<_>>().join(", "));
+        }
+
+        app
+            .insert_resource(bindings)
+            .insert_resource(seats)
+            .init_resource::<ActionState>()
+            .init_resource::<SeatActions>()
+            .add_system(ActionState::update.in_base_set(CoreSet::PreUpdate).after(InputSystem))
+            .add_system(SeatActions::update.in_base_set(CoreSet::PreUpdate).after(InputSystem))
+            .add_system(OptionsMenu::open.in_set(OnUpdate(GameState::Playing)))
+            .add_system(OptionsMenu::begin.in_schedule(OnEnter(GameState::Options)))
+            .add_system(OptionsMenu::input.in_set(OnUpdate(GameState::Options)))
+            .add_system(OptionsMenu::display.in_set(OnUpdate(GameState::Options)).after(OptionsMenu::input))
+            .add_system(OptionsMenu::finish.in_schedule(OnExit(GameState::Options)))
+
+        ;
+    }
+}
+
+/// The pause screen, lists every action and lets it be rebound
+#[derive(Debug, Clone, Default, Resource)]
+pub struct OptionsMenu {
+    pub selected: usize,
+    /// Waiting for a key or button to bind to the selected action
+    pub waiting: bool,
+    pub message: Option<(String, Color)>,
+    /// Bindings were changed and need saving
+    pub dirty: bool,
+}
+
+#[derive(Component)]
+pub struct OptionsScreen;
+
+#[derive(Component)]
+pub struct OptionsRow(pub usize);
+
+#[derive(Component)]
+pub struct OptionsMessage;
+
+/// Action names are padded to this many glyphs
+const ACTION_COLUMN: usize = 10;
+const ROW_GLYPHS: usize = 34;
+
+impl OptionsMenu {
+    pub fn open(actions: Res<ActionState>, players: Res<Players>, mut next_state: ResMut<NextState<GameState>>) {
+        // The demo ends instead
+        if players.demo { return }
+
+        if actions.just_pressed(Action::Pause) {
+            debug!("Pausing");
+            next_state.set(GameState::Options);
+        }
+    }
+
+    pub fn begin(mut commands: Commands) {
+        commands.insert_resource(Self::default());
+
+        let scale = TILE_SIZE;
+        let line = 8.0 * scale * 1.5;
+        let text = |text: BitmapText, y: f32| BitmapTextBundle::new(text, Vec3::new(0.0, y, 950.0), scale);
+
+        commands.spawn(text(BitmapText::new("PAUSED").with_colour(Color::YELLOW).with_align(TextAlign::Centre), line * 6.0)).insert(OptionsScreen);
+
+        for (i, _) in Action::ALL.iter().enumerate() {
+            commands.spawn(text(BitmapText::new("").with_align(TextAlign::Centre).with_width(ROW_GLYPHS), line * (4.0 - i as f32)))
+                .insert(OptionsScreen)
+                .insert(OptionsRow(i));
+        }
+
+        commands.spawn(text(BitmapText::new("").with_align(TextAlign::Centre), line * (3.0 - Action::ALL.len() as f32))).insert(OptionsScreen).insert(OptionsMessage);
+    }
+
+    /// Up/Down picks an action, Confirm rebinds it, Pause or Back go back to the game
+    pub fn input(
+        inputs: DeviceInputs,
+        actions: Res<ActionState>,
+        mut bindings: ResMut<InputBindings>,
+        menu: Option<ResMut<Self>>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        let Some(mut menu) = menu else { return };
+        let action = Action::ALL[menu.selected];
+
+        if menu.waiting {
+            let devices = Devices { keyboard: &inputs.keyboard, buttons: &inputs.buttons, axes: &inputs.axes, pads: inputs.gamepads.iter().collect(), deadzone: bindings.deadzone };
+            let Some(binding) = Binding::captured(&devices) else { return };
+
+            menu.waiting = false;
+            menu.message = match bindings.rebind(action, binding) {
+                Ok(()) => { menu.dirty = true; Some((format!("{} SET", binding.label()), Color::WHITE)) },
+                Err(other) => Some((format!("{} USED BY {}", binding.label(), other.setting().to_uppercase()), Color::RED)),
+            };
+            return
+        }
+
+        if actions.just_pressed(Action::Up) {
+            menu.selected = (menu.selected + Action::ALL.len() - 1) % Action::ALL.len();
+        }
+
+        if actions.just_pressed(Action::Down) {
+            menu.selected = (menu.selected + 1) % Action::ALL.len();
+        }
+
+        if actions.just_pressed(Action::Confirm) {
+            menu.waiting = true;
+            menu.message = Some(("PRESS A KEY OR BUTTON".to_string(), Color::YELLOW));
+        }
+
+        if actions.just_pressed(Action::Pause) || actions.just_pressed(Action::Back) {
+            next_state.set(GameState::Playing);
+        }
+    }
+
+    /// Actions that clash are red, a clash between the players' own bindings is shown when there's nothing else to say
+    pub fn display(
+        menu: Option<Res<Self>>,
+        bindings: Res<InputBindings>,
+        seats: Res<SeatBindings>,
+        mut rows: Query<(&OptionsRow, &mut BitmapText), Without<OptionsMessage>>,
+        mut message: Query<&mut BitmapText, With<OptionsMessage>>,
+    ) {
+        let Some(menu) = menu else { return };
+        if !menu.is_changed() && !bindings.is_changed() { return }
+
+        let seat_conflicts = seats.conflicts(&bindings);
+        let conflicted: Vec<Action> = bindings.conflicts().into_iter().flat_map(|(_, actions)| actions)
+            .chain(seat_conflicts.iter().flat_map(|(_, uses)| uses.iter().filter(|(seat, _)| seat.is_none()).map(|(_, action)| *action)))
+            .collect();
+
+        for (row, mut text) in &mut rows {
+            let action = Action::ALL[row.0];
+            let labels: Vec<String> = bindings.get(action).iter().map(|binding| binding.label()).collect();
+
+            text.set(format!("{:<width$}{}", action.setting().to_uppercase(), labels.join(" "), width = ACTION_COLUMN));
+            text.set_colour(if conflicted.contains(&action) { Color::RED } else if row.0 == menu.selected { Color::YELLOW } else { Color::WHITE });
+        }
+
+        for mut text in &mut message {
+            let clash = seat_conflicts.first().map(|(binding, uses)| {
+                (format!("{} - {}", binding.label(), uses.iter().copied().map(use_label).collect::<Vec<_>>().join(" ")), Color::RED)
+            });
+            let (message, colour) = menu.message.clone().or(clash).unwrap_or((String::new(), Color::WHITE));
+            text.set(message);
+            text.set_colour(colour);
+        }
+    }
+
+    pub fn finish(mut commands: Commands, screen: Query<Entity, With<OptionsScreen>>, menu: Option<Res<Self>>, bindings: Res<InputBindings>) {
+        for entity in &screen {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        commands.remove_resource::<Self>();
+
+        if menu.map(|menu| menu.dirty).unwrap_or(false) {
+            match bindings.save() {
+                Ok(()) => info!("Saved input bindings"),
+                Err(e) => error!("Failed to save input bindings: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> Binding {
+        Binding::parse(name).unwrap()
+    }
+
+    /// Whether the binding is held down with only `keys` pressed
+    fn active(binding: &Binding, keys: &[KeyCode]) -> bool {
+        let mut keyboard = Input::<KeyCode>::default();
+        for key in keys { keyboard.press(*key) }
+
+        let (buttons, axes) = (Input::<GamepadButton>::default(), Axis::<GamepadAxis>::default());
+        binding.active(&Devices { keyboard: &keyboard, buttons: &buttons, axes: &axes, pads: Vec::new(), deadzone: DEFAULT_DEADZONE })
+    }
+
+    #[test]
+    fn shipped_bindings_dont_clash() {
+        let settings: Value = toml::from_str(DEFAULT_SETTINGS).unwrap();
+        let shared = InputBindings::from_settings(&settings);
+
+        assert!(shared.conflicts().is_empty());
+        assert!(SeatBindings::from_settings(&settings).conflicts(&shared).is_empty());
+    }
+
+    #[test]
+    fn saving_keeps_the_rest_of_settings() {
+        let mut bindings = InputBindings::from_settings(&toml::from_str(DEFAULT_SETTINGS).unwrap());
+        bindings.rebind(Action::Up, key("Numpad9")).unwrap();
+        bindings.deadzone = 0.25;
+
+        let saved = bindings.write_into(DEFAULT_SETTINGS).unwrap();
+
+        for comment in DEFAULT_SETTINGS.lines().filter(|line| line.trim_start().starts_with('#')) {
+            assert!(saved.contains(comment), "lost {:?}", comment);
+        }
+
+        let settings: Value = toml::from_str(&saved).unwrap();
+        let loaded = InputBindings::from_settings(&settings);
+        assert_eq!(loaded.get(Action::Up), bindings.get(Action::Up));
+        assert_eq!(loaded.deadzone, 0.25);
+        assert_eq!(settings["input"]["player2"]["up"], toml::from_str::<Value>(DEFAULT_SETTINGS).unwrap()["input"]["player2"]["up"]);
+        assert_eq!(settings["autopilot"]["difficulty"], "normal");
+    }
+
+    #[test]
+    fn ctrl_and_shift_turn_keys_into_chords() {
+        let bindings = InputBindings::default();
+        let triggered = |keys: &[KeyCode]| Action::ALL.into_iter()
+            .filter(|action| bindings.get(*action).iter().any(|binding| active(binding, keys)))
+            .collect::<Vec<Action>>();
+
+        assert_eq!(triggered(&[KeyCode::S]), vec![Action::Down]);
+        assert_eq!(triggered(&[KeyCode::LControl, KeyCode::S]), vec![Action::Save]);
+        assert_eq!(triggered(&[KeyCode::RControl, KeyCode::Z]), vec![Action::Undo]);
+        assert_eq!(triggered(&[KeyCode::LControl, KeyCode::LShift, KeyCode::Z]), vec![Action::Redo]);
+        assert_eq!(triggered(&[KeyCode::RShift, KeyCode::W]), vec![]);
+
+        // A modifier bound on its own still works
+        assert!(active(&Binding::Key(KeyCode::LControl), &[KeyCode::LControl]));
+    }
+
+    #[test]
+    fn seats_sharing_a_key_clash() {
+        let mut seats = SeatBindings::default();
+        seats.seats[1].insert(Action::Left, vec![key("A")]);
+
+        let conflicts = seats.conflicts(&InputBindings::default());
+        assert_eq!(conflicts, vec![(key("A"), vec![(Some(0), Action::Left), (Some(1), Action::Left)])]);
+    }
+
+    #[test]
+    fn seat_keys_clash_with_other_shared_actions() {
+        let mut seats = SeatBindings::default();
+        seats.seats[2].insert(Action::Up, vec![key("Escape")]);
+        // W is the shared Up as well, that's fine
+        seats.seats[0].insert(Action::Up, vec![key("W")]);
+
+        let conflicts = seats.conflicts(&InputBindings::default());
+        assert_eq!(conflicts, vec![(key("Escape"), vec![(Some(2), Action::Up), (None, Action::Pause)])]);
+    }
+
+    #[test]
+    fn clashes_are_labelled_by_seat() {
+        let mut seats = SeatBindings::default();
+        seats.seats[1].insert(Action::Left, vec![key("Escape")]);
+
+        let (_, uses) = &seats.conflicts(&InputBindings::default())[0];
+        assert_eq!(uses.iter().copied().map(use_label).collect::<Vec<String>>(), vec!["P2 LEFT", "PAUSE"]);
+    }
+
+    #[test]
+    fn gamepads_only_clash_within_a_seat() {
+        let mut seats = SeatBindings::default();
+        assert!(seats.conflicts(&InputBindings::default()).is_empty());
+
+        seats.seats[3].insert(Action::Down, vec![key("Gamepad:DPadUp")]);
+        let conflicts = seats.conflicts(&InputBindings::default());
+        assert_eq!(conflicts, vec![(key("Gamepad:DPadUp"), vec![(Some(3), Action::Up), (Some(3), Action::Down)])]);
+    }
+}
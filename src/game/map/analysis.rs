@@ -0,0 +1,248 @@
+use std::{collections::VecDeque, path::Path};
+
+use anyhow::{Result, bail};
+use bevy::{prelude::*, utils::HashMap};
+use serde::Serialize;
+
+use super::{grid::MapGrid, header::MapHeader, tiled::read_map};
+
+/// How far Pac-Man has to walk from the spawn to a power pellet, None if he can't get there
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PelletDistance {
+    pub tile: IVec2,
+    pub distance: Option<usize>,
+}
+
+/// What `pacman analyze` reports about a map, tiles are (x, y) from the top left
+/// Only the part Pac-Man can walk to from the spawn counts as the maze, so the ghost house is left out
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MapReport {
+    pub title: Option<String>,
+    pub width: usize,
+    pub height: usize,
+    pub spawn: Option<IVec2>,
+    /// Tiles Pac-Man can walk to from the spawn
+    pub reachable: usize,
+    /// Dots and power pellets Pac-Man can't get to, the level can never be finished
+    pub unreachable_food: Vec<IVec2>,
+    /// Only one way in or out, ghosts can't turn back so they get stuck
+    pub dead_ends: Vec<IVec2>,
+    /// Top left of every 2x2 open block, ghosts zig zag through corridors wider than a tile
+    pub wide_corridors: Vec<IVec2>,
+    /// Tiles with three or more ways out
+    pub intersections: usize,
+    pub pellets: Vec<PelletDistance>,
+    /// Tiles on the left half whose walls don't match the right half
+    pub asymmetric: Vec<IVec2>,
+}
+
+impl MapReport {
+    /// Nothing that stops the map being played properly, symmetry is only a matter of taste
+    pub fn is_playable(&self) -> bool {
+        self.spawn.is_some() && self.unreachable_food.is_empty() && self.dead_ends.is_empty() && self.wide_corridors.is_empty()
+    }
+
+    /// For people, the JSON has the same things in it
+    pub fn text(&self) -> String {
+        let tiles = |tiles: &[IVec2]| match tiles.is_empty() {
+            true => "none".to_string(),
+            false => format!("{} at {}", tiles.len(), tiles.iter().map(|tile| format!("({}, {})", tile.x, tile.y)).collect::<Vec<_>>().join(" ")),
+        };
+
+        let mut text = format!("{} ({}x{})\n", self.title.as_deref().unwrap_or("Untitled"), self.width, self.height);
+
+        match self.spawn {
+            Some(spawn) => text += &format!("Spawn: ({}, {})\n", spawn.x, spawn.y),
+            None => text += "Spawn: none\n",
+        }
+        text += &format!("Reachable tiles: {}\n", self.reachable);
+        text += &format!("Intersections: {}\n", self.intersections);
+        text += &format!("Unreachable food: {}\n", tiles(&self.unreachable_food));
+        text += &format!("Dead ends: {}\n", tiles(&self.dead_ends));
+        text += &format!("Wide corridors: {}\n", tiles(&self.wide_corridors));
+        text += &format!("Symmetric: {}\n", if self.asymmetric.is_empty() { "yes".to_string() } else { format!("no, {}", tiles(&self.asymmetric)) });
+
+        text += "Power pellets:";
+        if self.pellets.is_empty() { text += " none" }
+        for pellet in &self.pellets {
+            match pellet.distance {
+                Some(distance) => text += &format!("\n  ({}, {}) {} tiles from the spawn", pellet.tile.x, pellet.tile.y, distance),
+                None => text += &format!("\n  ({}, {}) unreachable", pellet.tile.x, pellet.tile.y),
+            }
+        }
+        text.push('\n');
+
+        text += if self.is_playable() { "OK\n" } else { "PROBLEMS FOUND\n" };
+        text
+    }
+}
+
+/// Where Pac-Man can go from a tile in one step, teleports count as leading to their pair
+fn neighbours(grid: &MapGrid, tile: IVec2) -> Vec<IVec2> {
+    let mut next = Vec::new();
+
+    for step in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+        let to = tile + step;
+        if grid.is_walkable(to) {
+            next.push(to);
+        } else if !grid.in_bounds(to) {
+            if let Some(destination) = grid.teleport_destination(tile) { next.push(destination) }
+        }
+    }
+
+    next.dedup();
+    next
+}
+
+/// Every tile in reading order
+fn tiles(grid: &MapGrid) -> impl Iterator<Item = (IVec2, char)> + '_ {
+    (0..grid.height).flat_map(move |y| (0..grid.width).map(move |x| (x as isize, y as isize)))
+        .filter_map(|(x, y)| grid.get(x, y).map(|c| (IVec2::new(x as i32, y as i32), c)))
+}
+
+/// Walls and gates are what has to match when mirrored, the rest is open
+fn is_solid(grid: &MapGrid, x: isize, y: isize) -> bool {
+    matches!(grid.get(x, y), Some('#') | Some('G'))
+}
+
+/// Why a map can't be played at all, empty if it can be
+/// Every way a map comes in (the loaders, campaign.toml, the editor and the map browser) checks it with this
+pub fn blocking_problems(map: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let body = match (MapHeader::parse(map), MapHeader::split(map)) {
+        (Ok((_, body)), _) => body,
+        (Err(_), Ok((_, body))) => { problems.push("BAD HEADER".to_string()); body },
+        (Err(_), Err(_)) => { problems.push("HEADER NEVER CLOSED".to_string()); return problems },
+    };
+
+    if !body.contains('S') { problems.push("NO SPAWN".to_string()) }
+    if !body.contains('.') { problems.push("NO FOOD".to_string()) }
+
+    problems
+}
+
+/// Fails with every blocking problem the map has
+pub fn validate(map: &str) -> Result<()> {
+    let problems = blocking_problems(map);
+    if !problems.is_empty() { bail!("{}", problems.join(", ")) }
+    Ok(())
+}
+
+/// Checks a map the same way it's built for play
+pub fn analyze(map: &str) -> Result<MapReport> {
+    let (header, body) = MapHeader::parse(map)?;
+    let grid = MapGrid::new(body);
+
+    let spawn = tiles(&grid).find(|(_, c)| *c == 'S').map(|(tile, _)| tile);
+
+    // Steps from the spawn to every tile Pac-Man can get to
+    let mut distances = HashMap::new();
+    if let Some(spawn) = spawn {
+        let mut queue = VecDeque::from([spawn]);
+        distances.insert(spawn, 0);
+
+        while let Some(tile) = queue.pop_front() {
+            let distance = distances[&tile];
+            for next in neighbours(&grid, tile) {
+                if distances.contains_key(&next) { continue }
+
+                distances.insert(next, distance + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let reached = |tile: IVec2| distances.contains_key(&tile);
+    let mut walkable: Vec<IVec2> = distances.keys().copied().collect();
+    walkable.sort_by_key(|tile| (tile.y, tile.x));
+
+    let unreachable_food = tiles(&grid).filter(|(tile, c)| matches!(c, '.' | '@') && !reached(*tile)).map(|(tile, _)| tile).collect();
+    let dead_ends = walkable.iter().copied().filter(|tile| neighbours(&grid, *tile).len() < 2).collect();
+    let intersections = walkable.iter().filter(|tile| neighbours(&grid, **tile).len() >= 3).count();
+
+    let wide_corridors = walkable.iter().copied()
+        .filter(|tile| [IVec2::X, IVec2::Y, IVec2::ONE].iter().all(|step| reached(*tile + *step)))
+        .collect();
+
+    let pellets = tiles(&grid).filter(|(_, c)| *c == '@')
+        .map(|(tile, _)| PelletDistance { tile: tile, distance: distances.get(&tile).copied() })
+        .collect();
+
+    let mut asymmetric = Vec::new();
+    for y in 0..grid.height as isize {
+        for x in 0..grid.width as isize / 2 {
+            if is_solid(&grid, x, y) != is_solid(&grid, grid.width as isize - 1 - x, y) {
+                asymmetric.push(IVec2::new(x as i32, y as i32));
+            }
+        }
+    }
+
+    Ok(MapReport {
+        title: header.title,
+        width: grid.width,
+        height: grid.height,
+        spawn: spawn,
+        reachable: walkable.len(),
+        unreachable_food: unreachable_food,
+        dead_ends: dead_ends,
+        wide_corridors: wide_corridors,
+        intersections: intersections,
+        pellets: pellets,
+        asymmetric: asymmetric,
+    })
+}
+
+/// `pacman analyze <file.map> [--json]`, Tiled maps work too, fails if the map has problems so it can be used in scripts
+pub fn run(args: &[String]) -> Result<()> {
+    let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) else { bail!("Usage: pacman analyze <file.map> [--json]") };
+
+    let report = analyze(&read_map(Path::new(path))?)?;
+
+    if args.iter().any(|arg| arg == "--json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.text());
+    }
+
+    if !report.is_playable() { bail!("{} has problems", path) }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A loop around a walled in dot, with one gap in the bottom left wall that isn't mirrored
+    const TINY: &str = "---\ntitle = \"Tiny\"\n---\n#######\n#S...@#\n#.###.#\n#.#.#.#\n#.###.#\n#.....#\n ######\n";
+
+    #[test]
+    fn reports_on_a_tiny_maze() {
+        let report = analyze(TINY).unwrap();
+
+        assert_eq!(report, MapReport {
+            title: Some("Tiny".to_string()),
+            width: 7,
+            height: 7,
+            spawn: Some(IVec2::new(1, 1)),
+            reachable: 16,
+            unreachable_food: vec![IVec2::new(3, 3)],
+            dead_ends: vec![],
+            wide_corridors: vec![],
+            intersections: 0,
+            pellets: vec![PelletDistance { tile: IVec2::new(5, 1), distance: Some(4) }],
+            asymmetric: vec![IVec2::new(0, 6)],
+        });
+
+        assert!(!report.is_playable());
+        assert!(report.text().contains("Symmetric: no, 1 at (0, 6)\n"));
+        assert!(report.text().ends_with("PROBLEMS FOUND\n"));
+    }
+
+    #[test]
+    fn blocking_problems_are_all_listed() {
+        assert!(validate(TINY).is_ok());
+        assert_eq!(blocking_problems("---\ntitle = \n---\n###\n"), ["BAD HEADER", "NO SPAWN", "NO FOOD"]);
+        assert_eq!(blocking_problems("---\n#S.#\n"), ["HEADER NEVER CLOSED"]);
+    }
+}
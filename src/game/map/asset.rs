@@ -0,0 +1,157 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use anyhow::Result;
+use bevy::{prelude::*, asset::{AssetLoader, FileAssetIo, LoadContext, LoadedAsset}, reflect::TypeUuid, utils::{BoxedFuture, HashMap}};
+
+use super::{analysis::validate, tiled::{self, TILED_ASSET_EXTENSIONS}};
+
+/// Played when there's no campaign and no map given on the command line, in the assets folder
+pub const ASSET_MAP: &str = "clevel.map";
+
+/// Where clevel.map used to be kept, next to settings.toml
+/// Still played (and edited) when there's nothing at ASSET_MAP
+pub const LEGACY_ASSET_MAP: &str = "./clevel.map";
+
+/// The text of a .map file
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "9b7f2c9e-ff73-42e3-9bb2-72d8f38869a8"]
+pub struct MapAsset {
+    pub text: String,
+}
+
+/// Loads .map files, ones that can't be played are turned away so a bad save keeps the old map up
+#[derive(Debug, Default)]
+pub struct MapLoader;
+
+impl MapLoader {
+    fn parse(bytes: &[u8]) -> Result<MapAsset> {
+        let text = String::from_utf8(bytes.to_vec())?;
+        validate(&text)?;
+
+        Ok(MapAsset { text: text })
+    }
+}
+
+impl AssetLoader for MapLoader {
+    fn load<'a>(&'a self, bytes: &'a [u8], load_context: &'a mut LoadContext) -> BoxedFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let map = Self::parse(bytes).map_err(|e| { error!("Can't play {}: {}", load_context.path().display(), e); e })?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map"]
+    }
+}
+
+/// Loads Tiled maps (.tmx or .tmj) as .map text, turning away ones that can't be played the same way
+#[derive(Debug, Default)]
+pub struct TiledLoader;
+
+impl TiledLoader {
+    async fn convert(bytes: &[u8], load_context: &LoadContext<'_>) -> Result<MapAsset> {
+        // Tilesets kept in their own files are next to the map
+        let folder = load_context.path().parent().unwrap_or(Path::new("")).to_path_buf();
+
+        let mut tilesets = HashMap::new();
+        for source in tiled::external_tilesets(bytes)? {
+            let tileset = load_context.read_asset_bytes(folder.join(&source)).await?;
+            tilesets.insert(source, tileset);
+        }
+
+        MapLoader::parse(tiled::convert(bytes, &tilesets)?.as_bytes())
+    }
+}
+
+impl AssetLoader for TiledLoader {
+    fn load<'a>(&'a self, bytes: &'a [u8], load_context: &'a mut LoadContext) -> BoxedFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let map = Self::convert(bytes, load_context).await.map_err(|e| { error!("Can't play {}: {}", load_context.path().display(), e); e })?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &TILED_ASSET_EXTENSIONS
+    }
+}
+
+/// A map file given with `--map <file>`, played instead of the campaign
+#[derive(Debug, Clone, Resource)]
+pub struct MapPath(pub PathBuf);
+
+impl MapPath {
+    /// The path is taken from where the game was started, not the assets folder
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let index = args.iter().position(|arg| arg == "--map")?;
+        let path = PathBuf::from(args.get(index + 1)?);
+
+        // Absolute so the asset server doesn't look for it in the assets folder
+        Some(Self(fs::canonicalize(&path).unwrap_or(path)))
+    }
+}
+
+/// clevel.map from the old place, only if there isn't one in the assets folder
+/// Absolute like MapPath so the asset server doesn't look for it in the assets folder
+pub fn legacy_map() -> Option<PathBuf> {
+    let path = Path::new(LEGACY_ASSET_MAP);
+    if FileAssetIo::get_base_path().join("assets").join(ASSET_MAP).exists() || !path.exists() { return None }
+
+    Some(fs::canonicalize(path).unwrap_or(path.to_path_buf()))
+}
+
+/// The asset server can load it (and reload it when it's saved), Tiled's .json maps have to be read with read_map
+pub fn is_map_asset(path: &Path) -> bool {
+    path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| {
+        let extension = extension.to_lowercase();
+        extension == "map" || TILED_ASSET_EXTENSIONS.contains(&extension.as_str())
+    })
+}
+
+/// The file the current map came from, it's rebuilt whenever the file changes
+#[derive(Debug, Clone, Resource)]
+pub struct MapFile(pub Handle<MapAsset>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::DEFAULT_MAP;
+
+    #[test]
+    fn loads_playable_maps() {
+        assert_eq!(MapLoader::parse(DEFAULT_MAP.as_bytes()).unwrap().text, DEFAULT_MAP);
+
+        let with_header = "---\ntitle = \"Tiny\"\n---\n#####\n#S..#\n#####\n";
+        assert_eq!(MapLoader::parse(with_header.as_bytes()).unwrap().text, with_header);
+    }
+
+    #[test]
+    fn turns_away_maps_that_cant_be_played() {
+        let error = MapLoader::parse(b"#####\n#   #\n#####\n").unwrap_err().to_string();
+        assert!(error.contains("NO SPAWN") && error.contains("NO FOOD"), "{}", error);
+
+        assert!(MapLoader::parse(b"---\ntitle = \n---\n#S.#\n").is_err());
+        assert!(MapLoader::parse(&[0xff, 0xfe, b'S', b'.']).is_err());
+    }
+
+    #[test]
+    fn map_assets_by_extension() {
+        assert!(is_map_asset(Path::new("maps/level.map")));
+        assert!(is_map_asset(Path::new("maps/LEVEL.MAP")));
+        assert!(is_map_asset(Path::new("maps/level.tmx")));
+        assert!(!is_map_asset(Path::new("maps/level.json")));
+        assert!(!is_map_asset(Path::new("maps/level")));
+    }
+
+    #[test]
+    fn map_path_from_args() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(MapPath::from_args(&args(&["pacman", "--map", "no/such/level.map"])).map(|path| path.0), Some(PathBuf::from("no/such/level.map")));
+        assert!(MapPath::from_args(&args(&["pacman", "--map"])).is_none());
+        assert!(MapPath::from_args(&args(&["pacman"])).is_none());
+    }
+}
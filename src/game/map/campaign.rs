@@ -0,0 +1,271 @@
+use std::{fs, path::Path};
+
+use anyhow::{Result, bail};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{analysis::validate, generator, tiled::read_map, DEFAULT_MAP};
+use super::super::{hud::{fruit_for_level, FRUIT_NAMES}, ENERGIZED_MAX_LENGTH};
+
+/// Looked for in the working directory, used instead of clevel.map when present
+pub const CAMPAIGN_FILE: &str = "./campaign.toml";
+
+/// Stands for the built in map in a campaign
+const BUILT_IN: &str = "default";
+
+/// campaign.toml as written
+#[derive(Debug, Clone, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    loop_from: usize,
+    #[serde(rename = "map")]
+    maps: Vec<ManifestMap>,
+    #[serde(rename = "level", default)]
+    levels: Vec<ManifestLevel>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ManifestMap {
+    file: String,
+    #[serde(default = "one")]
+    levels: usize,
+}
+
+fn one() -> usize { 1 }
+
+/// Anything left out is kept from the level before
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ManifestLevel {
+    pacman_speed: Option<f32>,
+    ghost_speed: Option<f32>,
+    frightened_time: Option<u64>,
+    fruit: Option<String>,
+}
+
+/// How a level plays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelSettings {
+    /// Fractions of full speed, ghosts can't go over 1.0
+    pub pacman_speed: f32,
+    pub ghost_speed: f32,
+    /// In ticks
+    pub frightened_time: u64,
+    /// Index into the fruit row, None for the arcade's fruit
+    pub fruit: Option<usize>,
+}
+
+impl Default for LevelSettings {
+    fn default() -> Self {
+        Self { pacman_speed: 1.0, ghost_speed: 1.0, frightened_time: ENERGIZED_MAX_LENGTH, fruit: None }
+    }
+}
+
+/// The maps played level by level, like Ms. Pac-Man each map lasts a few levels
+/// Once the last map is done it goes back to `loop_from` and carries on from there
+#[derive(Debug, Clone, Resource)]
+pub struct Campaign {
+    pub name: String,
+    /// Map text and how many levels it's played for
+    maps: Vec<(String, usize)>,
+    loop_from: usize,
+    /// The last one carries on for every level after
+    levels: Vec<LevelSettings>,
+    /// Every level is a new maze from the generator, this seed is level one's
+    /// The mazes go in `maps` as the levels are reached
+    endless: Option<u64>,
+}
+
+impl Campaign {
+    /// Every level on the same map
+    pub fn single(map: String) -> Self {
+        Self { name: String::new(), maps: vec![(map, 1)], loop_from: 0, levels: Vec::new(), endless: None }
+    }
+
+    /// A generated maze for every level, the seed picks the mazes
+    pub fn endless(seed: u64) -> Self {
+        let mut campaign = Self { name: "Endless".to_string(), maps: Vec::new(), loop_from: 0, levels: Vec::new(), endless: Some(seed) };
+        campaign.prepare(0);
+        campaign
+    }
+
+    /// An endless campaign hasn't made the maze for this level yet
+    pub fn needs_maze(&self, level: usize) -> bool {
+        self.endless.is_some() && level >= self.maps.len()
+    }
+
+    /// Makes the mazes up to this level, nothing to do for other campaigns
+    pub fn prepare(&mut self, level: usize) {
+        let Some(seed) = self.endless else { return };
+
+        while self.maps.len() <= level {
+            let maze = generator::generate(seed.wrapping_add(self.maps.len() as u64));
+            self.maps.push((maze, 1));
+        }
+    }
+
+    /// campaign.toml if there is one and it loads
+    pub fn load() -> Option<Self> {
+        if !Path::new(CAMPAIGN_FILE).exists() { return None }
+
+        match Self::from_file(CAMPAIGN_FILE) {
+            Ok(campaign) => {
+                info!("Loaded campaign {:?} with {} map(s)", campaign.name, campaign.maps.len());
+                Some(campaign)
+            },
+            Err(e) => { error!("Failed to load {}: {}", CAMPAIGN_FILE, e); None },
+        }
+    }
+
+    /// Map files are relative to the campaign file
+    pub fn from_file(path: &str) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, Path::new(path).parent().unwrap_or(Path::new(".")))
+    }
+
+    /// The text of a campaign.toml, with map files relative to `folder`
+    pub fn parse(text: &str, folder: &Path) -> Result<Self> {
+        let manifest: Manifest = toml::from_str(text)?;
+
+        if manifest.maps.is_empty() { bail!("No maps in the campaign") }
+        if manifest.loop_from >= manifest.maps.len() { bail!("loop_from {} is past the last map", manifest.loop_from) }
+
+        let mut maps = Vec::new();
+        for entry in &manifest.maps {
+            let map = if entry.file == BUILT_IN { DEFAULT_MAP.to_string() } else { read_map(&folder.join(&entry.file))? };
+            if let Err(e) = validate(&map) { bail!("{} can't be played: {}", entry.file, e) }
+
+            maps.push((map, entry.levels.max(1)));
+        }
+
+        let mut levels = Vec::new();
+        let mut settings = LevelSettings::default();
+        for entry in &manifest.levels {
+            if let Some(speed) = entry.pacman_speed { settings.pacman_speed = speed.max(0.0) }
+            if let Some(speed) = entry.ghost_speed { settings.ghost_speed = speed.max(0.0) }
+            if let Some(time) = entry.frightened_time { settings.frightened_time = time }
+            if let Some(fruit) = &entry.fruit {
+                match FRUIT_NAMES.iter().position(|name| name.eq_ignore_ascii_case(fruit)) {
+                    Some(index) => settings.fruit = Some(index),
+                    None => warn!("Unknown fruit {:?}", fruit),
+                }
+            }
+
+            levels.push(settings);
+        }
+
+        Ok(Self { name: manifest.name, maps: maps, loop_from: manifest.loop_from, levels: levels, endless: None })
+    }
+
+    /// The map text for a level (0 based)
+    pub fn map(&self, level: usize) -> &str {
+        let mut remaining = level;
+        let mut index = 0;
+
+        loop {
+            let (map, levels) = &self.maps[index];
+            if remaining < *levels { return map }
+
+            remaining -= levels;
+            index += 1;
+            if index == self.maps.len() { index = self.loop_from }
+        }
+    }
+
+    pub fn level(&self, level: usize) -> LevelSettings {
+        self.levels.get(level).or(self.levels.last()).copied().unwrap_or_default()
+    }
+
+    /// The fruit shown for a level (0 based)
+    pub fn fruit(&self, level: usize) -> usize {
+        self.level(level).fruit.unwrap_or_else(|| fruit_for_level(level + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPS: &str = "assets/maps";
+
+    fn campaign(text: &str) -> Result<Campaign> {
+        Campaign::parse(text, Path::new(MAPS))
+    }
+
+    fn map(file: &str) -> String {
+        read_map(&Path::new(MAPS).join(file)).unwrap()
+    }
+
+    const THREE_MAPS: &str = r#"
+        name = "Tour"
+        loop_from = 1
+
+        [[map]]
+        file = "default"
+        levels = 2
+
+        [[map]]
+        file = "crossroads.map"
+
+        [[map]]
+        file = "labyrinth.map"
+        levels = 2
+    "#;
+
+    #[test]
+    fn maps_follow_each_other() {
+        let campaign = campaign(THREE_MAPS).unwrap();
+
+        assert_eq!(campaign.name, "Tour");
+        assert_eq!(campaign.map(0), DEFAULT_MAP);
+        assert_eq!(campaign.map(1), DEFAULT_MAP);
+        assert_eq!(campaign.map(2), map("crossroads.map"));
+        assert_eq!(campaign.map(3), map("labyrinth.map"));
+        assert_eq!(campaign.map(4), map("labyrinth.map"));
+    }
+
+    #[test]
+    fn the_last_map_loops_back() {
+        let campaign = campaign(THREE_MAPS).unwrap();
+
+        // Past labyrinth's two levels it's back to crossroads (loop_from = 1), never the first map
+        assert_eq!(campaign.map(5), map("crossroads.map"));
+        assert_eq!(campaign.map(6), map("labyrinth.map"));
+        assert_eq!(campaign.map(8), map("crossroads.map"));
+        assert!((5..50).all(|level| campaign.map(level) != DEFAULT_MAP));
+    }
+
+    #[test]
+    fn level_settings_carry_on() {
+        let campaign = campaign(r#"
+            [[map]]
+            file = "default"
+
+            [[level]]
+            ghost_speed = 0.8
+            fruit = "Apple"
+
+            [[level]]
+            pacman_speed = 0.9
+            frightened_time = 0
+        "#).unwrap();
+
+        assert_eq!(campaign.level(0), LevelSettings { ghost_speed: 0.8, fruit: Some(FRUIT_NAMES.iter().position(|name| *name == "apple").unwrap()), ..Default::default() });
+        assert_eq!(campaign.level(1), LevelSettings { pacman_speed: 0.9, frightened_time: 0, ..campaign.level(0) });
+        assert_eq!(campaign.level(20), campaign.level(1));
+    }
+
+    #[test]
+    fn malformed_campaigns_fail() {
+        // No maps, loop_from past the end, a list that isn't a list, a map that can't be played and one that isn't there
+        for text in [
+            "name = \"Empty\"\nmap = []",
+            "loop_from = 1\n[[map]]\nfile = \"default\"",
+            "map = \"default\"",
+            "[[map]]\nfile = \"../../src/tests/teleport_test_level.map\"",
+            "[[map]]\nfile = \"missing.map\"",
+        ] {
+            assert!(campaign(text).is_err(), "{}", text);
+        }
+    }
+}
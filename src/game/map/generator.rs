@@ -0,0 +1,232 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+
+use super::super::util::Xorshift;
+
+/// Generated mazes are the arcade's size
+pub const WIDTH: usize = 28;
+pub const HEIGHT: usize = 31;
+
+/// Corridors run along these, the left half is made and then mirrored onto the right
+const COLUMNS: [usize; 8] = [1, 6, 9, 12, 15, 18, 21, 26];
+const ROWS: [usize; 10] = [1, 5, 8, 11, 14, 17, 20, 23, 26, 29];
+
+/// The arcade's ghost house and the corridor around it
+const HOUSE: [&str; 7] = [
+    "    B     ",
+    " ###GG### ",
+    " #      # ",
+    " #I PH C# ",
+    " #      # ",
+    " ######## ",
+    "    F     ",
+];
+/// Top left of HOUSE in tiles
+const HOUSE_AT: (usize, usize) = (9, 11);
+
+const TUNNEL_ROW: usize = 14;
+const SPAWN: (usize, usize) = (13, 23);
+
+/// Power pellets go on the dots nearest these (and their mirrors)
+const PELLET_TARGETS: [(usize, usize); 2] = [(1, 3), (1, 23)];
+
+/// Chance of each corridor being taken out (if the maze still works without it)
+const REMOVE_CHANCE: f32 = 0.65;
+
+/// A point where corridors can meet, as indices into COLUMNS and ROWS
+type Node = (usize, usize);
+
+/// Inside the ghost house and at the mouths of the tunnels
+fn is_blocked(node: Node) -> bool {
+    matches!(node, (3, 4) | (4, 4) | (0, 4) | (7, 4))
+}
+
+/// Round the ghost house, into the tunnels and past the spawn, these are always there
+fn is_fixed(edge: (Node, Node)) -> bool {
+    matches!(edge,
+        ((2, 3), (3, 3)) | ((3, 3), (4, 3)) | ((4, 3), (5, 3)) |
+        ((2, 5), (3, 5)) | ((3, 5), (4, 5)) | ((4, 5), (5, 5)) |
+        ((2, 3), (2, 4)) | ((2, 4), (2, 5)) | ((5, 3), (5, 4)) | ((5, 4), (5, 5)) |
+        ((1, 4), (2, 4)) | ((5, 4), (6, 4)) |
+        ((3, 7), (4, 7))
+    )
+}
+
+fn mirror(edge: (Node, Node)) -> (Node, Node) {
+    let flip = |(column, row): Node| (COLUMNS.len() - 1 - column, row);
+    let (a, b) = (flip(edge.0), flip(edge.1));
+    if a <= b { (a, b) } else { (b, a) }
+}
+
+/// The corridors between neighbouring nodes, each with whether it's open
+struct Lattice {
+    edges: Vec<(Node, Node)>,
+    open: Vec<bool>,
+}
+
+impl Lattice {
+    /// Every corridor open
+    fn full() -> Self {
+        let mut edges = Vec::new();
+
+        for column in 0..COLUMNS.len() {
+            for row in 0..ROWS.len() {
+                let node = (column, row);
+                if is_blocked(node) { continue }
+
+                for next in [(column + 1, row), (column, row + 1)] {
+                    if next.0 < COLUMNS.len() && next.1 < ROWS.len() && !is_blocked(next) {
+                        edges.push((node, next));
+                    }
+                }
+            }
+        }
+
+        let open = vec![true; edges.len()];
+        Self { edges: edges, open: open }
+    }
+
+    fn set(&mut self, edge: (Node, Node), open: bool) {
+        for mirrored in [edge, mirror(edge)] {
+            if let Some(index) = self.edges.iter().position(|existing| *existing == mirrored) {
+                self.open[index] = open;
+            }
+        }
+    }
+
+    fn open_edges(&self) -> impl Iterator<Item = (Node, Node)> + '_ {
+        self.edges.iter().zip(&self.open).filter(|(_, open)| **open).map(|(edge, _)| *edge)
+    }
+
+    /// No dead ends and every corridor can be reached from the ghost house
+    fn is_valid(&self) -> bool {
+        let mut degree = vec![vec![0; ROWS.len()]; COLUMNS.len()];
+        for (a, b) in self.open_edges() {
+            degree[a.0][a.1] += 1;
+            degree[b.0][b.1] += 1;
+        }
+
+        if degree.iter().flatten().any(|count| *count == 1) { return false }
+
+        let mut seen = vec![vec![false; ROWS.len()]; COLUMNS.len()];
+        let mut queue = vec![(2, 3)];
+        seen[2][3] = true;
+
+        while let Some(node) = queue.pop() {
+            for (a, b) in self.open_edges() {
+                let next = if a == node { b } else if b == node { a } else { continue };
+                if !seen[next.0][next.1] {
+                    seen[next.0][next.1] = true;
+                    queue.push(next);
+                }
+            }
+        }
+
+        (0..COLUMNS.len()).all(|column| (0..ROWS.len()).all(|row| degree[column][row] == 0 || seen[column][row]))
+    }
+}
+
+/// A symmetric arcade sized maze with a ghost house, tunnels, four power pellets and dots down every corridor
+/// Every corridor joins up and none of them are dead ends, the same seed always gives the same maze
+pub fn generate(seed: u64) -> String {
+    let mut rng = Xorshift::new(seed);
+    let mut lattice = Lattice::full();
+
+    // The left half and the middle, the right half follows
+    let mut candidates: Vec<(Node, Node)> = lattice.edges.iter().copied()
+        .filter(|(a, b)| a.0 < COLUMNS.len() / 2 && b.0 <= COLUMNS.len() / 2 && !is_fixed((*a, *b)))
+        .collect();
+
+    for i in (1..candidates.len()).rev() {
+        candidates.swap(i, rng.below(i + 1));
+    }
+
+    for edge in candidates {
+        if rng.next_f32() >= REMOVE_CHANCE { continue }
+
+        lattice.set(edge, false);
+        if !lattice.is_valid() { lattice.set(edge, true) }
+    }
+
+    let mut tiles = vec![vec!['#'; WIDTH]; HEIGHT];
+
+    for (a, b) in lattice.open_edges() {
+        let (x1, y1) = (COLUMNS[a.0], ROWS[a.1]);
+        let (x2, y2) = (COLUMNS[b.0], ROWS[b.1]);
+
+        for y in y1..=y2 {
+            for x in x1..=x2 {
+                tiles[y][x] = '.';
+            }
+        }
+    }
+
+    for x in 0..COLUMNS[1] {
+        tiles[TUNNEL_ROW][x] = '~';
+        tiles[TUNNEL_ROW][WIDTH - 1 - x] = '~';
+    }
+    tiles[TUNNEL_ROW][0] = '|';
+    tiles[TUNNEL_ROW][WIDTH - 1] = '|';
+
+    for (row, line) in HOUSE.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            tiles[HOUSE_AT.1 + row][HOUSE_AT.0 + column] = c;
+        }
+    }
+
+    for (target_x, target_y) in PELLET_TARGETS {
+        let nearest = (0..HEIGHT).flat_map(|y| (0..WIDTH / 2).map(move |x| (x, y)))
+            .filter(|(x, y)| tiles[*y][*x] == '.')
+            .min_by_key(|(x, y)| x.abs_diff(target_x).pow(2) + y.abs_diff(target_y).pow(2));
+
+        if let Some((x, y)) = nearest {
+            tiles[y][x] = '@';
+            tiles[y][WIDTH - 1 - x] = '@';
+        }
+    }
+
+    tiles[SPAWN.1][SPAWN.0] = 'S';
+
+    let mut map = format!("---\ntitle = \"Maze {}\"\n---\n", seed);
+    for row in tiles {
+        map.extend(row);
+        map.push('\n');
+    }
+
+    map
+}
+
+/// `--endless` plays a new maze every level, `--seed <n>` plays the same ones again
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct Endless(pub u64);
+
+impl Endless {
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        if !args.iter().any(|arg| arg == "--endless") { return None }
+
+        let seed = args.iter().position(|arg| arg == "--seed").and_then(|i| args.get(i + 1)).and_then(|seed| seed.parse().ok());
+        let seed = seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or(0));
+
+        Some(Self(seed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{map::analysis::analyze, editor::problems};
+
+    #[test]
+    fn generated_mazes_are_playable() {
+        for seed in 0..200 {
+            let map = generate(seed);
+            let report = analyze(&map).unwrap();
+
+            assert!(report.is_playable(), "seed {}: {}", seed, report.text());
+            assert!(report.asymmetric.is_empty(), "seed {} isn't symmetric", seed);
+            assert!(problems(&map).is_empty(), "seed {} has problems", seed);
+            assert_eq!(map.matches('@').count(), 4, "seed {}", seed);
+        }
+    }
+}
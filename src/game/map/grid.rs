@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+
+/// The map as a grid of characters, (0, 0) is the top left
+/// Lines can be different lengths, anything past the end of a line is treated as empty space
+#[derive(Debug, Clone, Default, PartialEq, Resource)]
+pub struct MapGrid {
+    rows: Vec<Vec<char>>,
+    pub width: usize,
+    pub height: usize,
+    /// Teleports in reading order, they are paired up 1st with 2nd, 3rd with 4th...
+    teleports: Vec<IVec2>,
+}
+
+impl MapGrid {
+    pub fn new(map: &str) -> Self {
+        let rows: Vec<Vec<char>> = map.lines().map(|line| line.chars().collect()).collect();
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let height = rows.len();
+
+        let teleports = rows.iter().enumerate()
+            .flat_map(|(y, row)| row.iter().enumerate().filter(|(_, c)| **c == '|').map(move |(x, _)| IVec2::new(x as i32, y as i32)))
+            .collect();
+
+        Self { rows: rows, width: width, height: height, teleports: teleports }
+    }
+
+    /// None if outside the map
+    pub fn get(&self, x: isize, y: isize) -> Option<char> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None
+        }
+
+        Some(self.rows[y as usize].get(x as usize).copied().unwrap_or(' '))
+    }
+
+    pub fn is_wall(&self, x: isize, y: isize) -> bool {
+        self.get(x, y) == Some('#')
+    }
+
+    pub fn in_bounds(&self, tile: IVec2) -> bool {
+        self.get(tile.x as isize, tile.y as isize).is_some()
+    }
+
+    /// Pac-Man can stand here, walls and the ghost house door block him
+    pub fn is_walkable(&self, tile: IVec2) -> bool {
+        match self.get(tile.x as isize, tile.y as isize) {
+            Some(c) => c != '#' && c != 'G',
+            None => false,
+        }
+    }
+
+    /// Tunnel tiles (~) and the teleports at the ends, ghosts slow down here
+    pub fn is_tunnel(&self, tile: IVec2) -> bool {
+        matches!(self.get(tile.x as isize, tile.y as isize), Some('~') | Some('|'))
+    }
+
+    /// Where walking off the map from this teleport comes out
+    pub fn teleport_destination(&self, tile: IVec2) -> Option<IVec2> {
+        let index = self.teleports.iter().position(|teleport| *teleport == tile)?;
+        self.teleports.get(index ^ 1).copied()
+    }
+}
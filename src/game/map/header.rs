@@ -0,0 +1,182 @@
+use anyhow::{Result, bail};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::super::{enemy::GhostPersonality, util::hash_str};
+
+/// The newest header version this build understands
+pub const MAP_FORMAT_VERSION: u32 = 1;
+
+/// Opens and closes the header
+const FENCE: &str = "---";
+
+/// Scatter targets in tiles, they can be off the map like the arcade's
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ScatterCorners {
+    pub blinky: Option<[i32; 2]>,
+    pub pinky: Option<[i32; 2]>,
+    pub inky: Option<[i32; 2]>,
+    pub clyde: Option<[i32; 2]>,
+}
+
+impl ScatterCorners {
+    pub fn get(&self, personality: GhostPersonality) -> Option<IVec2> {
+        let corner = match personality {
+            GhostPersonality::Blinky => self.blinky,
+            GhostPersonality::Pinky => self.pinky,
+            GhostPersonality::Inky => self.inky,
+            GhostPersonality::Clyde => self.clyde,
+        };
+
+        corner.map(IVec2::from)
+    }
+}
+
+/// Optional TOML between --- lines at the top of a map, everything in it can be left out
+/// ```text
+/// ---
+/// title = "Arcade"
+/// author = "Namco"
+/// version = 1
+/// wall_colour = "2121de"
+/// tunnel_speed = 0.5
+///
+/// [scatter]
+/// blinky = [25, -3]
+/// ---
+/// ############################
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MapHeader {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Format version, 1 if left out
+    pub version: u32,
+    /// Hex, with or without the #
+    pub wall_colour: Option<String>,
+    /// How fast ghosts go through the tunnels, as a fraction of their speed
+    pub tunnel_speed: f32,
+    pub scatter: ScatterCorners,
+}
+
+impl Default for MapHeader {
+    fn default() -> Self {
+        Self { title: None, author: None, version: MAP_FORMAT_VERSION, wall_colour: None, tunnel_speed: 1.0, scatter: ScatterCorners::default() }
+    }
+}
+
+impl MapHeader {
+    /// The text between the fences and the map after them, None if there's no header
+    fn front_matter(map: &str) -> Result<Option<(&str, &str)>> {
+        let mut lines = map.split_inclusive('\n');
+
+        let start = match lines.next() {
+            Some(first) if first.trim_end() == FENCE => first.len(),
+            _ => return Ok(None),
+        };
+
+        let mut end = start;
+        for line in lines {
+            if line.trim_end() == FENCE { return Ok(Some((&map[start..end], &map[end + line.len()..]))) }
+            end += line.len();
+        }
+
+        bail!("The header is never closed with {}", FENCE)
+    }
+
+    fn from_toml(text: &str) -> Result<Self> {
+        let header: Self = toml::from_str(text)?;
+        if header.version > MAP_FORMAT_VERSION { bail!("Map format version {} is newer than {}", header.version, MAP_FORMAT_VERSION) }
+
+        Ok(header)
+    }
+
+    /// The header and the rest of the map, maps without a header get the default one
+    pub fn parse(map: &str) -> Result<(Self, &str)> {
+        match Self::front_matter(map)? {
+            Some((text, body)) => Ok((Self::from_toml(text)?, body)),
+            None => Ok((Self::default(), map)),
+        }
+    }
+
+    /// Like parse but a header that can't be read is logged and skipped over, the map can still be played
+    /// Fails if the header is never closed, there's no telling where the map starts
+    pub fn split(map: &str) -> Result<(Self, &str)> {
+        match Self::front_matter(map)? {
+            Some((text, body)) => match Self::from_toml(text) {
+                Ok(header) => Ok((header, body)),
+                Err(e) => { warn!("Ignoring the map header: {}", e); Ok((Self::default(), body)) },
+            },
+            None => Ok((Self::default(), map)),
+        }
+    }
+
+    pub fn wall_colour(&self) -> Option<Color> {
+        let hex = self.wall_colour.as_ref()?;
+
+        match Color::hex(hex.trim_start_matches('#')) {
+            Ok(colour) => Some(colour),
+            Err(e) => { warn!("Bad wall colour {:?}: {:?}", hex, e); None },
+        }
+    }
+}
+
+/// Tells maps apart for high scores, only the maze counts so a new title or author keeps the scores
+pub fn map_hash(map: &str) -> String {
+    hash_str(MapHeader::split(map).map_or(map, |(_, body)| body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = "#####\n#S..#\n#####\n";
+
+    #[test]
+    fn maps_without_a_header() {
+        let (header, body) = MapHeader::parse(BODY).unwrap();
+        assert_eq!((header, body), (MapHeader::default(), BODY));
+        assert_eq!(MapHeader::split(BODY).unwrap().1, BODY);
+    }
+
+    #[test]
+    fn reads_the_header() {
+        let map = format!("---\ntitle = \"Tiny\"\nauthor = \"Me\"\ntunnel_speed = 0.5\n\n[scatter]\nblinky = [3, -2]\n---\n{}", BODY);
+        let (header, body) = MapHeader::parse(&map).unwrap();
+
+        assert_eq!(body, BODY);
+        assert_eq!((header.title.as_deref(), header.author.as_deref(), header.tunnel_speed), (Some("Tiny"), Some("Me"), 0.5));
+        assert_eq!(header.scatter.get(GhostPersonality::Blinky), Some(IVec2::new(3, -2)));
+        assert_eq!(header.scatter.get(GhostPersonality::Pinky), None);
+    }
+
+    #[test]
+    fn an_unclosed_header_is_an_error() {
+        let map = format!("---\ntitle = \"Tiny\"\n{}", BODY);
+
+        assert!(MapHeader::parse(&map).is_err());
+        assert!(MapHeader::split(&map).is_err());
+    }
+
+    #[test]
+    fn a_bad_header_is_skipped_when_splitting() {
+        let map = format!("---\ntitle = \n---\n{}", BODY);
+
+        assert!(MapHeader::parse(&map).is_err());
+        assert_eq!(MapHeader::split(&map).unwrap(), (MapHeader::default(), BODY));
+
+        let newer = format!("---\nversion = {}\n---\n{}", MAP_FORMAT_VERSION + 1, BODY);
+        assert!(MapHeader::parse(&newer).is_err());
+    }
+
+    #[test]
+    fn the_hash_is_only_the_maze() {
+        let titled = |title: &str| format!("---\ntitle = \"{}\"\n---\n{}", title, BODY);
+
+        assert_eq!(map_hash(&titled("One")), map_hash(&titled("Two")));
+        assert_eq!(map_hash(&titled("One")), map_hash(BODY));
+        assert_ne!(map_hash(BODY), map_hash(&BODY.replace("..", ". ")));
+    }
+}
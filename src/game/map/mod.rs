@@ -1,23 +1,42 @@
-use std::{fs, path::Path, borrow::BorrowMut};
+use std::{path::Path, borrow::BorrowMut, time::Instant};
 
 use bevy::utils::HashMap;
-use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy::prelude::*;
 use rayon::prelude::*;
 
 use crate::game::enemy::{GhostSpawn, GhostPersonality};
-use crate::game::{EntityState};
+use crate::game::{EntityState, GameState};
 use crate::game::CurrentDirection;
 
 use super::{POWER_PELLET_WORTH, ENERGIZED_MAX_LENGTH};
 use super::enemy::Ghost;
-use super::util::PowerPellets;
-use super::{TILE_SIZE, player::{PlayerMovement, PlayerData, GameData}, MovementHelper, util::{check_collosion, get_real_pos}, PAC_DOT_WORTH};
-
-const DEFAULT_MAP: &str = include_str!("../../assets/level.map");
-
-pub const DEFAULT_MAP_ORIGIN: Vec3 = Vec3::new(-329.0, 124.0, 0.0);
-
-const CUSTOM_MAP: &str = "./clevel.map";
+use super::util::hash_str;
+use super::assets::GameAssets;
+use super::movement::TileMover;
+use super::{TILE_SIZE, player::{Player, PlayerMovement, PlayerData, GameData, Seat}, players::{Players, PlayMode}, MovementHelper, util::{check_collosion, get_real_pos}, PAC_DOT_WORTH};
+
+pub mod grid;
+pub mod walls;
+pub mod campaign;
+pub mod header;
+pub mod asset;
+pub mod generator;
+pub mod analysis;
+pub mod tiled;
+pub mod render;
+
+use grid::MapGrid;
+use header::MapHeader;
+use campaign::Campaign;
+use asset::{is_map_asset, legacy_map, MapAsset, MapLoader, TiledLoader, MapPath, MapFile, ASSET_MAP};
+use tiled::read_map;
+use generator::Endless;
+use walls::{wall_piece, GATE_PIECE, WALL_COLOUR};
+
+pub const DEFAULT_MAP: &str = include_str!("../../assets/level.map");
+
+/// Tiles are placed from this row, so the first line of the map sits at y = 10 tiles
+const FIRST_ROW: f32 = -10.0;
 
 #[derive(Debug, )]
 pub struct TileMap;
@@ -25,8 +44,13 @@ pub struct TileMap;
 impl Plugin for TileMap {
     fn build(&self, app: &mut App) {
         app 
+            .add_asset::<MapAsset>()
+            .init_asset_loader::<MapLoader>()
+            .init_asset_loader::<TiledLoader>()
             .add_startup_system(TileMap::create_map)
+            .add_system(TileMap::hot_reload)
             .add_system(MapEnforcer::check_map)
+            .add_system(TileMap::follow_campaign.in_set(OnUpdate(GameState::Ready)).after(PlayerMovement::sync_transform))
             
             
             
@@ -35,36 +59,183 @@ impl Plugin for TileMap {
 }
 
 impl TileMap {
-    pub fn get_map() -> String {
-        if Path::new(CUSTOM_MAP).exists() {
-            debug!("Found Custom Map!");
-            fs::read_to_string(CUSTOM_MAP).unwrap()
-        } else {
-            DEFAULT_MAP.to_string()
-        }
-    }
-
     /// Creates the map 
     /// TODO: Rewrite
     pub fn create_map(
         mut commands: Commands, 
-        asset_server: Res<AssetServer>, 
-        mut texture_atlases: ResMut<Assets<TextureAtlas>>, 
+        assets: Res<GameAssets>,
+        texture_atlases: Res<Assets<TextureAtlas>>,
         mut player: Query<(&PlayerMovement, &mut Transform)>,
-        mut power_pellets_data: ResMut<PowerPellets>,
+        source: Option<Res<MapSource>>,
+        map_path: Option<Res<MapPath>>,
+        endless: Option<Res<Endless>>,
+        asset_server: Res<AssetServer>,
     ) {
-        let map = Self::get_map();
+        let start = Instant::now();
+
+        // Map files are played on their own, the built in map stands in until they're loaded
+        let (campaign, file) = match (source, map_path, endless) {
+            (Some(source), _, _) => (Campaign::single(source.0.clone()), None),
+            (None, Some(path), _) if is_map_asset(&path.0) => (Campaign::single(DEFAULT_MAP.to_string()), Some(path.0.clone())),
+            // Tiled's .json maps aren't assets, they're read once and not reloaded
+            (None, Some(path), _) => match read_map(&path.0) {
+                Ok(map) => (Campaign::single(map), None),
+                Err(e) => { error!("Can't play {}: {}", path.0.display(), e); (Campaign::single(DEFAULT_MAP.to_string()), None) },
+            },
+            (None, None, Some(endless)) => {
+                info!("Endless mode with seed {}", endless.0);
+                (Campaign::endless(endless.0), None)
+            },
+            (None, None, None) => match Campaign::load() {
+                Some(campaign) => (campaign, None),
+                None => {
+                    let found = asset_server.asset_io().get_metadata(Path::new(ASSET_MAP)).is_ok();
+                    match legacy_map() {
+                        // Played like --map, so the editor saves it back where it is
+                        Some(legacy) => {
+                            warn!("clevel.map goes in the assets folder now, playing {} until it's moved", legacy.display());
+                            commands.insert_resource(MapPath(legacy.clone()));
+                            (Campaign::single(DEFAULT_MAP.to_string()), Some(legacy))
+                        },
+                        None => (Campaign::single(DEFAULT_MAP.to_string()), found.then(|| ASSET_MAP.into())),
+                    }
+                },
+            },
+        };
+
+        if let Some(file) = file {
+            info!("Loading the map from {}", file.display());
+            commands.insert_resource(MapFile(asset_server.load(file)));
+        }
+
         info!("Attempting map creation");
+        let (current_map, spawn) = Self::build(&mut commands, &assets, campaign.map(0), &[]);
+        commands.insert_resource(campaign);
+
+        if player.is_empty() {
+            error!("An error occured when spawning in the Map!");
+            error!("Player not ready!");
+        }
+
+        if let Some(spawn) = spawn {
+            let pos = current_map.tile_pos(spawn.x as f32, spawn.y as f32);
+            for (_, mut transform) in &mut player {
+                transform.translation.x = pos.x;
+                transform.translation.y = pos.y;
+            }
+        }
+
+        debug!("Map created in {:?}, {} texture atlases in use", start.elapsed(), texture_atlases.len());
+    }
+
+    /// Swaps the map over when a level is on a different map (a new level, a new game or another player's turn)
+    /// Only while the Ready screen is up, so nothing is moving
+    pub fn follow_campaign(
+        mut commands: Commands,
+        assets: Res<GameAssets>,
+        campaign: Option<ResMut<Campaign>>,
+        current_map: Option<Res<CurrentMap>>,
+        players: Res<Players>,
+        old_map: Query<Entity, With<MapEnforcer>>,
+        ghosts: Query<Entity, With<Ghost>>,
+        mut pacmen: Query<(&GameData, &mut TileMover), With<PlayerMovement>>,
+    ) {
+        let (Some(mut campaign), Some(current_map)) = (campaign, current_map) else { return };
+        let Some(level) = pacmen.iter().map(|(game_data, _)| game_data.level).next() else { return };
+
+        if campaign.needs_maze(level) { campaign.prepare(level) }
+
+        let map = campaign.map(level);
+        if current_map.is_showing(map) { return }
+
+        info!("Level {} is on another map", level + 1);
+
+        // Coming back to a level part way through, the player's food is as they left it
+        let eaten = match players.slots.get(players.current) {
+            Some(slot) if !players.is_simultaneous() && slot.level == level => slot.eaten.clone(),
+            _ => Vec::new(),
+        };
+
+        let (_, spawn) = Self::replace(&mut commands, &assets, map, old_map.iter().chain(ghosts.iter()), &eaten);
+
+        if let Some(spawn) = spawn {
+            for (_, mut mover) in &mut pacmen {
+                mover.place(spawn);
+            }
+        }
+    }
+
+    /// Swaps in the map file once it's loaded and again every time it's saved
+    /// Pac-Man stays where he is if he still can, eaten food stays eaten and the ghosts go back home
+    pub fn hot_reload(
+        mut commands: Commands,
+        mut events: EventReader<AssetEvent<MapAsset>>,
+        assets: Res<GameAssets>,
+        maps: Res<Assets<MapAsset>>,
+        map_file: Option<Res<MapFile>>,
+        current_map: Option<Res<CurrentMap>>,
+        old_map: Query<Entity, With<MapEnforcer>>,
+        ghosts: Query<Entity, With<Ghost>>,
+        food: Query<(&Food, &MapTile)>,
+        mut pacmen: Query<&mut TileMover, With<PlayerMovement>>,
+    ) {
+        let Some(map_file) = map_file else { events.clear(); return };
+
+        let changed = events.iter().any(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => *handle == map_file.0,
+            AssetEvent::Removed { .. } => false,
+        });
+        if !changed { return }
+
+        let Some(map) = maps.get(&map_file.0) else { return };
+        if current_map.map_or(false, |current_map| current_map.is_showing(&map.text)) { return }
+
+        info!("Map file changed, rebuilding the maze");
+
+        let eaten: Vec<IVec2> = food.iter().filter(|(food_data, _)| food_data.is_eaten).map(|(_, tile)| tile.0).collect();
+        let (_, spawn) = Self::replace(&mut commands, &assets, &map.text, old_map.iter().chain(ghosts.iter()), &eaten);
+        commands.insert_resource(Campaign::single(map.text.clone()));
+
+        // The loader only lets through maps whose header can be split off
+        let grid = MapGrid::new(MapHeader::split(&map.text).map_or("", |(_, body)| body));
+        for mut mover in &mut pacmen {
+            match spawn {
+                Some(spawn) if !grid.is_walkable(mover.tile) => mover.place(spawn),
+                // Back to the middle of the tile in case the way ahead is walled off now
+                _ => mover.offset = IVec2::ZERO,
+            }
+        }
+    }
+
+    /// Despawns `old` (the map entity and the ghosts) and builds `map` in its place
+    /// The ghosts come back from the new spawn points by themselves
+    pub fn replace(commands: &mut Commands, assets: &GameAssets, map: &str, old: impl Iterator<Item = Entity>, eaten: &[IVec2]) -> (CurrentMap, Option<IVec2>) {
+        for entity in old {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        Self::build(commands, assets, map, eaten)
+    }
+
+    /// Spawns the walls, food and spawn points of a map under a new map entity
+    /// Food on the `eaten` tiles starts eaten, returns the map and the tile Pac-Man spawns on
+    pub fn build(commands: &mut Commands, assets: &GameAssets, map: &str, eaten: &[IVec2]) -> (CurrentMap, Option<IVec2>) {
+        let start = Instant::now();
+        // An empty maze rather than the header drawn as tiles
+        let (header, body) = MapHeader::split(map).unwrap_or_else(|e| { error!("Can't build the map: {}", e); (MapHeader::default(), "") });
+        let current_map = CurrentMap::from_parts(header, body);
+        commands.insert_resource(current_map.clone());
+
+        // Only the tiles from here on
+        let map = body;
+        let wall_colour = current_map.header.wall_colour().unwrap_or(WALL_COLOUR);
+        let mut spawn = None;
         let custom_map = true;
         let test_map_texture = false;
 
-        let texture_handle = asset_server.load("maze.png");
-        let atlas = TextureAtlas::from_grid(texture_handle, Vec2 { x: 224.0, y: 248.0 }, 1, 1, None, None);
-        let test_map = texture_atlases.add(atlas);
-        
         if test_map_texture {
             commands.spawn(SpriteSheetBundle  {
-                texture_atlas: test_map,
+                texture_atlas: assets.maze.clone(),
 
                 // /* 
                 transform: Transform {
@@ -82,8 +253,8 @@ impl TileMap {
             // Check Map Here
             {
                 if !map.contains("S") || !map.contains(".") {
-                    // If true the custom map cannot be used
-                    panic!("Custom Map has no spawn or food!")
+                    // Still drawn for the editor, maps like this are never played
+                    error!("Custom Map has no spawn or food!")
                 }
 
                 let all_opt_checks = 4;
@@ -119,19 +290,20 @@ impl TileMap {
             let mut tile_num = 0;
             let mut food_num = 0;
             let mut power_pellets = 0;
-            let mut y = -10;
-            let mut power_pellet_prio = 0;
-            let mut teleport_locations = HashMap::new();
-            for line in map.lines() {
+            let mut y = FIRST_ROW as i32;
+            let mut teleport_locations: HashMap<usize, Vec3> = HashMap::new();
+
+            // Walls pick their piece from their neighbours
+            let grid = MapGrid::new(&map);
+            commands.insert_resource(grid.clone());
+
+            for (line_num, line) in map.lines().enumerate() {
                 // For Map Creation
                 for (x, char) in line.chars().enumerate() {
                     if char.to_string() == "#" {
-                        let texture_handle = asset_server.load("test/test_blue_single_pixel.png");
-                        let atlas = TextureAtlas::from_grid(texture_handle, Vec2 { x: 15.0, y: 15.0 }, 2, 4, None, None);
-                        let texture_atlas_handle = texture_atlases.add(atlas);
-
                         let entity = commands.spawn(SpriteSheetBundle  {
-                            texture_atlas: texture_atlas_handle,
+                            texture_atlas: assets.walls.clone(),
+                            sprite: TextureAtlasSprite { index: wall_piece(&grid, x, line_num).unwrap_or(0), color: wall_colour, ..Default::default() },
                             //transform: Transform::from_scale(Vec3::splat(TILE_SIZE)),
                             
                             transform: Transform {
@@ -149,21 +321,15 @@ impl TileMap {
 
                     // TODO: complete teleport init
                     if char.to_string() == "|" {
-                        println!("Change TP sprite");
-                        let texture_handle = asset_server.load("test/test_blue_single_pixel.png");
-                        let atlas = TextureAtlas::from_grid(texture_handle, Vec2 { x: 15.0, y: 15.0 }, 2, 4, None, None);
-                        let texture_atlas_handle = texture_atlases.add(atlas);
-
                         let translation = Vec3 { x: (x as f32 * TILE_SIZE * 10.0), y: -(y as f32 * TILE_SIZE) * 10.0, z: 100.0 };
 
                         let destination = if !teleport_locations.is_empty() {
                             teleport_locations.get(&1).unwrap().clone()                            
                         } else { translation };
 
-                        let entity = commands.spawn(SpriteSheetBundle  {
-                            texture_atlas: texture_atlas_handle,
-                            //transform: Transform::from_scale(Vec3::splat(TILE_SIZE)),
-                            
+                        // Teleports are just the tunnel exits, nothing is drawn
+                        let entity = commands.spawn(SpatialBundle  {
+                            visibility: Visibility::Hidden,
                             transform: Transform {
                                 translation: translation,
                                 scale: Vec3::splat(TILE_SIZE),
@@ -180,12 +346,9 @@ impl TileMap {
                     }
 
                     if char.to_string() == "G" {
-                        let texture_handle = asset_server.load("test/test_blue_single_pixel.png");
-                        let atlas = TextureAtlas::from_grid(texture_handle, Vec2 { x: 15.0, y: 15.0 }, 2, 4, None, None);
-                        let texture_atlas_handle = texture_atlases.add(atlas);
-
                         let entity = commands.spawn(SpriteSheetBundle  {
-                            texture_atlas: texture_atlas_handle,
+                            texture_atlas: assets.walls.clone(),
+                            sprite: TextureAtlasSprite::new(GATE_PIECE),
                             //transform: Transform::from_scale(Vec3::splat(TILE_SIZE)),
                             
                             transform: Transform {
@@ -214,22 +377,11 @@ impl TileMap {
                             },
                             
                             ..Default::default()
-                        }).insert(Name::new(format!("Spawn Tile"))).insert(SpawnPoint)               
+                        }).insert(Name::new(format!("Spawn Tile"))).insert(SpawnPoint).insert(MapTile::new(x, line_num))               
                         .id();
 
                         tiles.push(entity);
-                        match player.get_single_mut() {
-                            Ok(player) => {
-                                let (player_move, mut transform) = player;
-
-                                transform.translation = pos;
-                            },
-                            Err(_) => {
-                                error!("An error occured when spawning in the Map!");
-                                error!("Player not ready!");
-                            },
-                        }
-                        
+                        spawn = Some(IVec2::new(x as i32, line_num as i32));
                     }
 
                     // For Blinky spawn point
@@ -243,7 +395,7 @@ impl TileMap {
                             }, ..Default::default() }
                             )
                             .insert(Name::new("Blinky_Spawn"))
-                            .insert(GhostSpawn{ spawn_for: GhostPersonality::Blinky, power_pellet: power_pellet_prio })
+                            .insert(GhostSpawn{ spawn_for: GhostPersonality::Blinky, scatter: current_map.scatter_corner(GhostPersonality::Blinky) })
 
                             .id();
 
@@ -262,7 +414,7 @@ impl TileMap {
                             }, ..Default::default() }
                             )
                             .insert(Name::new("Pinky_Spawn"))
-                            .insert(GhostSpawn{ spawn_for: GhostPersonality::Pinky, power_pellet: power_pellet_prio })
+                            .insert(GhostSpawn{ spawn_for: GhostPersonality::Pinky, scatter: current_map.scatter_corner(GhostPersonality::Pinky) })
 
                             .id();
 
@@ -282,7 +434,7 @@ impl TileMap {
                             }, ..Default::default() }
                             )
                             .insert(Name::new("Inky_Spawn"))
-                            .insert(GhostSpawn{ spawn_for: GhostPersonality::Inky, power_pellet: power_pellet_prio })
+                            .insert(GhostSpawn{ spawn_for: GhostPersonality::Inky, scatter: current_map.scatter_corner(GhostPersonality::Inky) })
 
                             .id();
 
@@ -302,7 +454,7 @@ impl TileMap {
                             }, ..Default::default() }
                             )
                             .insert(Name::new("Clyde_Spawn"))
-                            .insert(GhostSpawn{ spawn_for: GhostPersonality::Clyde, power_pellet: power_pellet_prio })
+                            .insert(GhostSpawn{ spawn_for: GhostPersonality::Clyde, scatter: current_map.scatter_corner(GhostPersonality::Clyde) })
 
                             .id();
 
@@ -314,14 +466,12 @@ impl TileMap {
                     // For PacDot (aka food) creation
                     if char.to_string() == "." {
 
-                        let texture_handle = asset_server.load("pacdot.png");
-                        let atlas = TextureAtlas::from_grid(texture_handle, Vec2 { x: 2.0, y: 2.0 }, 1, 1, None, None);
-                        let texture_atlas_handle = texture_atlases.add(atlas);
-
                         let pos = Vec3 { x: (x as f32 * TILE_SIZE * 10.0), y: -(y as f32 * TILE_SIZE) * 10.0, z: 100.0 };
+                        let is_eaten = eaten.contains(&IVec2::new(x as i32, line_num as i32));
 
                         let entity = commands.spawn(SpriteSheetBundle  {     
-                            texture_atlas: texture_atlas_handle,                       
+                            texture_atlas: assets.pacdot.clone(),                       
+                            visibility: if is_eaten { Visibility::Hidden } else { Visibility::Inherited },
                             transform: Transform {
                                 translation: pos,
                                 scale: Vec3::splat(TILE_SIZE),
@@ -329,7 +479,7 @@ impl TileMap {
                             },
                             
                             ..Default::default()
-                        }).insert(Name::new(format!("Food Tile ({})",food_num))).insert(Food { is_eaten: false, r#type: ConsumableType::PacDot })               
+                        }).insert(Name::new(format!("Food Tile ({})",food_num))).insert(Food { is_eaten: is_eaten, r#type: ConsumableType::PacDot }).insert(MapTile::new(x, line_num))               
                         .id();
 
                         tiles.push(entity); food_num += 1;
@@ -338,14 +488,12 @@ impl TileMap {
                     // For PacDot (aka food) creation
                     if char.to_string() == "@" {
 
-                        let texture_handle = asset_server.load("powerpellet.png");
-                        let atlas = TextureAtlas::from_grid(texture_handle, Vec2 { x: 8.0, y: 8.0 }, 1, 1, None, None);
-                        let texture_atlas_handle = texture_atlases.add(atlas);
-
                         let pos = Vec3 { x: (x as f32 * TILE_SIZE * 10.0), y: -(y as f32 * TILE_SIZE) * 10.0, z: 100.0 };
+                        let is_eaten = eaten.contains(&IVec2::new(x as i32, line_num as i32));
 
                         let entity = commands.spawn(SpriteSheetBundle  {     
-                            texture_atlas: texture_atlas_handle,                       
+                            texture_atlas: assets.power_pellet.clone(),                       
+                            visibility: if is_eaten { Visibility::Hidden } else { Visibility::Inherited },
                             transform: Transform {
                                 translation: pos,
                                 scale: Vec3::splat(TILE_SIZE),
@@ -353,11 +501,9 @@ impl TileMap {
                             },
                             
                             ..Default::default()
-                        }).insert(Name::new(format!("PP Tile ({})",power_pellets))).insert(Food { is_eaten: false, r#type: ConsumableType::PowerPellet })               
+                        }).insert(Name::new(format!("PP Tile ({})",power_pellets))).insert(Food { is_eaten: is_eaten, r#type: ConsumableType::PowerPellet }).insert(MapTile::new(x, line_num))               
                         .id();
 
-                        power_pellets_data.pellets.insert(power_pellets,pos);
-
                         tiles.push(entity); power_pellets += 1;
                     }
                 }
@@ -366,26 +512,83 @@ impl TileMap {
 
             // /* 
             commands.spawn(SpriteSheetBundle {
- 
-                transform: Transform {
-                    translation: Vec3 { x: -329.0, y: 124.0, z: 100.0 },
-                    ..Default::default()
-                },
+                transform: Transform::from_translation(current_map.origin),
                 ..Default::default()
             } )
                 .insert(Name::new("Map"))
-                .insert(Transform::default())
-                .insert(GlobalTransform::default())
                 .insert(MapEnforcer)
                 .push_children(&tiles);
 
             info!("Created collision grid");
+            debug!("Map of {} tiles created in {:?}", tiles.len(), start.elapsed());
 
 
             // */
 
         }
 
+        (current_map, spawn)
+    }
+}
+
+/// The map currently being played, high-scores are kept per map hash
+#[derive(Debug, Clone, Resource)]
+pub struct CurrentMap {
+    pub hash: String,
+    /// In tiles
+    pub width: usize,
+    pub height: usize,
+    /// Where the map entity sits, puts the middle of the map at (0, 0)
+    pub origin: Vec3,
+    pub header: MapHeader,
+}
+
+impl CurrentMap {
+    pub fn new(map: &str) -> anyhow::Result<Self> {
+        let (header, body) = MapHeader::split(map)?;
+        Ok(Self::from_parts(header, body))
+    }
+
+    /// `body` is the map without its header, only the body is hashed (see map_hash)
+    pub fn from_parts(header: MapHeader, body: &str) -> Self {
+        let width = body.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+        let height = body.lines().count();
+
+        // Tile centres run from 0 to width - 1 (and height - 1), the origin is minus the middle one
+        let middle_x = (width.max(1) - 1) as f32 / 2.0;
+        let middle_y = (height.max(1) - 1) as f32 / 2.0 + FIRST_ROW;
+        let origin = Vec3 { x: -middle_x * TILE_SIZE * 10.0, y: middle_y * TILE_SIZE * 10.0, z: 0.0 }.round();
+
+        Self { hash: hash_str(body), width: width, height: height, origin: origin, header: header }
+    }
+
+    /// This is the map being played, header and all
+    pub fn is_showing(&self, map: &str) -> bool {
+        MapHeader::split(map).map_or(false, |(header, body)| self.hash == hash_str(body) && self.header == header)
+    }
+
+    /// Where a ghost heads when scattering, from the header or else just off the nearest corner of the maze
+    /// Blinky top right, Pinky top left, Inky bottom right and Clyde bottom left like the arcade
+    pub fn scatter_corner(&self, personality: GhostPersonality) -> IVec2 {
+        let (right, bottom) = (self.width as i32 - 1, self.height as i32);
+
+        self.header.scatter.get(personality).unwrap_or(match personality {
+            GhostPersonality::Blinky => IVec2::new(right - 2, -3),
+            GhostPersonality::Pinky => IVec2::new(2, -3),
+            GhostPersonality::Inky => IVec2::new(right, bottom),
+            GhostPersonality::Clyde => IVec2::new(0, bottom),
+        })
+    }
+
+    /// World position of the centre of a tile, (0, 0) being the top left of the map
+    pub fn tile_pos(&self, x: f32, y: f32) -> Vec3 {
+        get_real_pos(Vec3 { x: x * TILE_SIZE * 10.0, y: -(y + FIRST_ROW) * TILE_SIZE * 10.0, z: 0.0 }, self.origin)
+    }
+
+    /// The tile a world position is in
+    pub fn world_to_tile(&self, pos: Vec3) -> IVec2 {
+        let local = pos - self.origin;
+        IVec2::new((local.x / (TILE_SIZE * 10.0)).round() as i32, (-local.y / (TILE_SIZE * 10.0) - FIRST_ROW).round() as i32)
     }
 }
 
@@ -395,12 +598,12 @@ pub struct MapEnforcer;
 /// Allows for Correction of the map
 impl MapEnforcer {
     /// Checks and Corrects the map
-    pub fn check_map(mut map: Query<(&MapEnforcer, &mut Transform)>) {
+    pub fn check_map(mut map: Query<(&MapEnforcer, &mut Transform)>, current_map: Res<CurrentMap>) {
         
         let (_current_map, mut transform) = map.single_mut();
 
-        if transform.translation != DEFAULT_MAP_ORIGIN {
-            transform.translation = DEFAULT_MAP_ORIGIN;
+        if transform.translation != current_map.origin {
+            transform.translation = current_map.origin;
             warn!("Made correction to map!")
         }
 
@@ -412,10 +615,45 @@ pub enum WallType {
     Blocking, Teleport {destination: Vec3}, Gate
 }
 
+impl WallType {
+    /// The variant by name, as Tiled maps give it, a teleport's destination is found when the map is built
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Blocking" => Some(Self::Blocking),
+            "Teleport" => Some(Self::Teleport { destination: Vec3::ZERO }),
+            "Gate" => Some(Self::Gate),
+            _ => None,
+        }
+    }
+
+    /// How it's written in a .map
+    pub fn tile(&self) -> char {
+        match self {
+            Self::Blocking => '#',
+            Self::Teleport { .. } => '|',
+            Self::Gate => 'G',
+        }
+    }
+}
+
+
+/// Used instead of clevel.map / the built in map when present at startup
+#[derive(Debug, Clone, Resource)]
+pub struct MapSource(pub String);
 
 #[derive(Component, )]
 pub struct SpawnPoint;
 
+/// The tile something was placed on, (0, 0) is the top left of the map
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapTile(pub IVec2);
+
+impl MapTile {
+    pub fn new(x: usize, y: usize) -> Self {
+        Self(IVec2::new(x as i32, y as i32))
+    }
+}
+
 #[derive(Component, Reflect)]
 pub struct TileCollider{
     pub r#type: WallType,
@@ -433,13 +671,35 @@ pub enum ConsumableType {
     PacDot, PowerPellet, Fruit
 }
 
+impl ConsumableType {
+    /// The variant by name, as Tiled maps give it
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "PacDot" => Some(Self::PacDot),
+            "PowerPellet" => Some(Self::PowerPellet),
+            "Fruit" => Some(Self::Fruit),
+            _ => None,
+        }
+    }
+
+    /// How it's written in a .map
+    pub fn tile(&self) -> char {
+        match self {
+            Self::PacDot => '.',
+            Self::PowerPellet => '@',
+            Self::Fruit => 'F',
+        }
+    }
+}
+
 #[derive(Debug, )]
 pub struct FoodSystem;
 
 impl Plugin for FoodSystem {
     fn build(&self, app: &mut App) {
-        app.add_system(Self::check_food)
-        .add_system(Self::level_checker)
+        app.add_system(Self::check_food.in_set(OnUpdate(GameState::Playing)).after(PlayerMovement::tick))
+        // Deaths are handled first, ghosts are let out of the house for the next tick
+        .add_system(Self::level_checker.in_set(OnUpdate(GameState::Playing)).after(Self::check_food).after(Player::state_checks))
         
         
         ;
@@ -452,31 +712,31 @@ impl FoodSystem {
     }
 
     /// Makes the game change level if all food is gone
+    /// Every Pac-Man carries the same GameData, so all of them are kept in step
     pub fn level_checker (
         mut food: Query<(&mut Food, &mut Visibility), (Without<PlayerMovement>)>,
-        mut player: Query<(&mut Transform, &mut PlayerData, &mut GameData, &mut MovementHelper), (With<PlayerMovement>, Without<Food>)>,
+        mut player: Query<(&Seat, &mut PlayerData, &mut GameData, &mut MovementHelper), (With<PlayerMovement>, Without<Food>)>,
         mut ghosts: Query<&mut Ghost, Without<PlayerMovement>>,
+        mut players: ResMut<Players>,
+        mut next_state: ResMut<NextState<GameState>>,
     ) {
 
         // info!("Level checking!");
 
         let mut active_food = 0;
-        let mut inactive_food = 0;
-        for (mut food_data, mut visability) in &mut food {
-            if *visability == Visibility::Hidden  && food_data.is_eaten {
-                inactive_food += 1;
-            } else {
+        for (food_data, visability) in &food {
+            if !(*visability == Visibility::Hidden && food_data.is_eaten) {
                 active_food += 1;
             }
         }
 
-        let (player_transform, mut player_data, mut game_data, mut helper) = player.single_mut();
+        let Some(transitioning) = player.iter().map(|(_, _, game_data, _)| game_data.transitioning).next() else { return };
 
         // Do checks to ensure the game is ready to move on!
-        if game_data.transitioning {
+        if transitioning {
             let mut ready_ghosts: usize = 0;
             let all_ghosts = ghosts.iter().len();
-            for (mut ghost) in &mut ghosts {
+            for mut ghost in &mut ghosts {
                 if ghost.state == EntityState::Created {
                     ready_ghosts += 1;
                 } else {
@@ -486,69 +746,93 @@ impl FoodSystem {
 
             if ready_ghosts == all_ghosts {
                 info!("All Ghosts Ready!");
-                game_data.transitioning = false;
+                for (_, _, mut game_data, _) in &mut player {
+                    game_data.transitioning = false;
+                }
             } else {
                 // info!("{}/{} Ghost Ready", ready_ghosts, all_ghosts);
             }
         }
 
-        if active_food == 0 && !game_data.transitioning {
+        if active_food == 0 && !transitioning {
+            // A versus game is one maze
+            if players.mode == PlayMode::Versus {
+                info!("All Food is eaten! Versus game over");
+                players.finish(player.iter().map(|(seat, data, game_data, _)| (seat.0, data, game_data)));
+                next_state.set(GameState::EnteringInitials);
+                return
+            }
+
             info!("All Food is eaten! Changing Level");
-            game_data.transitioning = true;
-            game_data.level += 1;
-            helper.direction = Some(CurrentDirection::Idle);
-            player_data.state = EntityState::Created;
             for (mut food_data, mut visability) in &mut food {
                 food_data.is_eaten = false;
                 *visability = Visibility::Visible;
             }
 
-            helper.direction = None;
             // Do checks to ensure the game is ready to move on!
             let mut ready_ghosts: usize = 0;
             let all_ghosts = ghosts.iter().len();
-            for (ghost) in &ghosts {
+            for ghost in &ghosts {
                 if ghost.state == EntityState::Created {
                     ready_ghosts += 1;
                 }
             }
 
-            if ready_ghosts == all_ghosts {
-                game_data.transitioning = false;
-                info!("Level Transitidion");
-            } else {
-                game_data.transitioning = true;
+            for (_, mut player_data, mut game_data, mut helper) in &mut player {
+                game_data.level += 1;
+                helper.direction = None;
+                if !player_data.out { player_data.state = EntityState::Created; }
+                game_data.transitioning = ready_ghosts != all_ghosts;
             }
-            
+
+            if ready_ghosts == all_ghosts { info!("Level Transitidion"); }
+            next_state.set(GameState::Ready);
         }
     }
 
+    /// Whoever is on the tile gets the points
     pub fn check_food(
         // mut commands: Commands,
-        mut food: Query<(&mut Food, &mut Transform, &mut Visibility), (Without<PlayerMovement>)>,
-        mut player: Query<(&mut Transform, &mut GameData, &mut PlayerData), (With<PlayerMovement>, Without<Food>)>
+        mut food: Query<(&mut Food, &MapTile, &mut Visibility), (Without<PlayerMovement>)>,
+        mut player: Query<(&TileMover, &GameData, &mut PlayerData), (With<PlayerMovement>, Without<Food>)>,
+        players: Res<Players>,
+        campaign: Option<Res<Campaign>>,
     ) {
         
-        let (player_transform, mut game_data,mut player_data) = player.single_mut();
+        if player.iter().any(|(_, game_data, _)| game_data.transitioning) { return }
+
+        let level = player.iter().map(|(_, game_data, _)| game_data.level).next().unwrap_or(0);
+        let frightened_time = campaign.map(|campaign| campaign.level(level).frightened_time).unwrap_or(ENERGIZED_MAX_LENGTH);
 
-        for (mut food_data, food_transform, mut visability) in &mut food {
+        for (mut food_data, food_tile, mut visability) in &mut food {
 
             match food_data.r#type {
                 ConsumableType::PacDot | ConsumableType::PowerPellet => {
                     let is_powerpellet = food_data.r#type == ConsumableType::PowerPellet;
 
-                    if game_data.transitioning { break }
-
                     if *visability == Visibility::Hidden || food_data.is_eaten { continue; }
-        
-                    let collided = collide(player_transform.translation, Vec2::splat(TILE_SIZE), get_real_pos(food_transform.translation, DEFAULT_MAP_ORIGIN), Vec2::splat(TILE_SIZE*20.0)).is_some();
-                    if !collided { continue; }
+
+                    let Some((_, _, mut player_data)) = player.iter_mut().find(|(mover, _, data)| !data.out && mover.tile == food_tile.0) else { continue };
                 
                     player_data.score += if is_powerpellet { POWER_PELLET_WORTH as usize } else { PAC_DOT_WORTH as usize };
+                    player_data.dots += 1;
                     food_data.is_eaten = true;
                     *visability = Visibility::Hidden;
 
-                    if is_powerpellet { player_data.state = EntityState::Energized(ENERGIZED_MAX_LENGTH); debug!("Effect Start"); };
+                    // Later levels can have no frightened time at all
+                    if is_powerpellet && frightened_time > 0 {
+                        player_data.state = EntityState::Energized(frightened_time);
+                        player_data.ghost_combo = 0;
+                        debug!("Effect Start");
+
+                        // The whole team is energized in co-op
+                        if players.mode == PlayMode::CoOp {
+                            for (_, _, mut other) in player.iter_mut().filter(|(_, _, data)| !data.out) {
+                                other.state = EntityState::Energized(frightened_time);
+                                other.ghost_combo = 0;
+                            }
+                        }
+                    };
                     
 
                 },
@@ -561,3 +845,47 @@ impl FoodSystem {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn odd_maps_centre_on_the_middle_tile() {
+        let map = CurrentMap::new("#####\n#S..#\n#####\n").unwrap();
+
+        assert_eq!((map.width, map.height), (5, 3));
+        assert_eq!(map.tile_pos(2.0, 1.0), Vec3::ZERO);
+        assert_eq!(map.tile_pos(0.0, 0.0).truncate(), -map.tile_pos(4.0, 2.0).truncate());
+        assert_eq!(map.world_to_tile(map.tile_pos(4.0, 2.0)), IVec2::new(4, 2));
+    }
+
+    #[test]
+    fn even_maps_centre_between_tiles() {
+        let map = CurrentMap::new("####\n#S.#\n#..#\n####\n").unwrap();
+        let (top_left, bottom_right) = (map.tile_pos(0.0, 0.0), map.tile_pos(3.0, 3.0));
+
+        // Off by the half pixel the origin is rounded by at most
+        assert_eq!(map.origin, map.origin.round());
+        assert!((top_left + bottom_right).abs().max_element() <= 1.0);
+        assert_eq!(bottom_right - top_left, Vec3::new(3.0, -3.0, 0.0) * TILE_SIZE * 10.0);
+        assert_eq!(map.world_to_tile(map.tile_pos(1.0, 2.0)), IVec2::new(1, 2));
+    }
+
+    #[test]
+    fn tiles_share_the_game_atlases() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_plugin(AssetPlugin::default()).add_asset::<Image>().add_asset::<TextureAtlas>()
+            .insert_resource(MapSource(DEFAULT_MAP.to_string()))
+            .init_resource::<GameAssets>()
+            .add_startup_system(TileMap::create_map);
+
+        let loaded = app.world.resource::<Assets<TextureAtlas>>().len();
+        app.update();
+
+        // Hundreds of walls, dots and pellets, still one atlas each kind
+        let sprites = app.world.query::<&TextureAtlasSprite>().iter(&app.world).count();
+        assert!(sprites > 500, "{sprites} sprites");
+        assert_eq!(app.world.resource::<Assets<TextureAtlas>>().len(), loaded);
+    }
+}
@@ -0,0 +1,162 @@
+//! Maps drawn straight into an image, no window or GPU needed
+//!
+//! Walls use the same pieces as the game (see walls.rs) so the images double as snapshots of
+//! the auto-tiler. Food and spawn points are simple shapes in their usual colours
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use image::{RgbaImage, Rgba, imageops::{self, FilterType}};
+
+use super::{grid::MapGrid, header::MapHeader, tiled::read_map, walls::{wall_piece, piece_mask, GATE_PIECE, GATE_COLOUR, WALL_COLOUR, WALL_TILE_PIXELS}};
+
+/// Longest side of a thumbnail when no size is given
+pub const THUMBNAIL_SIZE: u32 = 128;
+
+const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+const DOT_COLOUR: [u8; 4] = [255, 184, 151, 255];
+const FRUIT_COLOUR: [u8; 4] = [255, 0, 0, 255];
+
+/// Pac-Man and the ghosts at their spawn points
+fn spawn_colour(c: char) -> Option<[u8; 4]> {
+    match c {
+        'S' => Some([255, 255, 0, 255]),
+        'B' => Some([255, 0, 0, 255]),
+        'P' => Some([255, 184, 255, 255]),
+        'I' => Some([0, 255, 255, 255]),
+        'C' => Some([255, 184, 82, 255]),
+        _ => None,
+    }
+}
+
+/// A disc in the middle of the tile, in tile pixels
+fn disc(radius: f32) -> [[bool; WALL_TILE_PIXELS]; WALL_TILE_PIXELS] {
+    let mut mask = [[false; WALL_TILE_PIXELS]; WALL_TILE_PIXELS];
+    let centre = WALL_TILE_PIXELS as f32 / 2.0;
+
+    for (y, row) in mask.iter_mut().enumerate() {
+        for (x, lit) in row.iter_mut().enumerate() {
+            let (dx, dy) = (x as f32 + 0.5 - centre, y as f32 + 0.5 - centre);
+            *lit = dx * dx + dy * dy <= radius * radius;
+        }
+    }
+
+    mask
+}
+
+/// The whole maze, `scale` pixels for each of a tile's WALL_TILE_PIXELS
+pub fn render(map: &str, scale: u32) -> Result<RgbaImage> {
+    if scale == 0 { bail!("The scale has to be at least 1") }
+
+    let (header, body) = MapHeader::parse(map)?;
+    let grid = MapGrid::new(body);
+    if grid.width == 0 || grid.height == 0 { bail!("The map is empty") }
+
+    let wall_colour = header.wall_colour().unwrap_or(WALL_COLOUR).as_rgba_f32().map(|channel| (channel * 255.0).round() as u8);
+    let tile = WALL_TILE_PIXELS as u32 * scale;
+    let mut image = RgbaImage::from_pixel(grid.width as u32 * tile, grid.height as u32 * tile, Rgba(BACKGROUND));
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let Some(c) = grid.get(x as isize, y as isize) else { continue };
+
+            // Same tiles as TileMap::build
+            let (mask, colour) = match c {
+                '#' => match wall_piece(&grid, x, y) {
+                    Some(piece) => (piece_mask(piece), wall_colour),
+                    None => continue,
+                },
+                'G' => (piece_mask(GATE_PIECE), GATE_COLOUR),
+                '.' => (disc(1.0), DOT_COLOUR),
+                '@' => (disc(4.0), DOT_COLOUR),
+                'F' => (disc(3.0), FRUIT_COLOUR),
+                c => match spawn_colour(c) {
+                    Some(colour) => (disc(4.5), colour),
+                    None => continue,
+                },
+            };
+
+            for (py, row) in mask.iter().enumerate() {
+                for (px, lit) in row.iter().enumerate() {
+                    if !lit { continue }
+
+                    let (left, top) = (x as u32 * tile + px as u32 * scale, y as u32 * tile + py as u32 * scale);
+                    for sy in 0..scale {
+                        for sx in 0..scale {
+                            image.put_pixel(left + sx, top + sy, Rgba(colour));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+/// The maze shrunk to fit in a `size` pixel square, for picking maps
+pub fn thumbnail(map: &str, size: u32) -> Result<RgbaImage> {
+    let full = render(map, 1)?;
+
+    let ratio = size as f32 / full.width().max(full.height()) as f32;
+    let (width, height) = ((full.width() as f32 * ratio).round().max(1.0), (full.height() as f32 * ratio).round().max(1.0));
+
+    Ok(imageops::resize(&full, width as u32, height as u32, FilterType::Triangle))
+}
+
+/// The argument is the value of --scale or --thumbnail rather than a path
+fn value_of(args: &[String], index: usize) -> bool {
+    match index.checked_sub(1).map(|previous| args[previous].as_str()) {
+        Some("--scale") => true,
+        Some("--thumbnail") => args[index].parse::<u32>().is_ok(),
+        _ => false,
+    }
+}
+
+/// `pacman render <file.map> <out.png> [--scale <n>] [--thumbnail [size]]`
+pub fn run(args: &[String]) -> Result<()> {
+    let value = |name: &str| args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1));
+    let paths: Vec<&String> = args.iter().enumerate()
+        .filter(|(i, arg)| !arg.starts_with("--") && !value_of(args, *i))
+        .map(|(_, arg)| arg)
+        .collect();
+
+    let [input, output] = paths[..] else { bail!("Usage: pacman render <file.map> <out.png> [--scale <n>] [--thumbnail [size]]") };
+    let map = read_map(Path::new(input))?;
+
+    let image = if args.iter().any(|arg| arg == "--thumbnail") {
+        thumbnail(&map, value("--thumbnail").and_then(|size| size.parse().ok()).unwrap_or(THUMBNAIL_SIZE))?
+    } else {
+        render(&map, value("--scale").map(|scale| scale.parse()).transpose()?.unwrap_or(1))?
+    };
+
+    image.save(output)?;
+    println!("Saved {} ({}x{})", output, image.width(), image.height());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::DEFAULT_MAP;
+
+    /// Fails on any change to how walls are tiled, if it was meant `pacman render src/assets/level.map src/tests/level.png` makes a new one
+    #[test]
+    fn level_matches_the_snapshot() {
+        let snapshot = image::load_from_memory(include_bytes!("../../tests/level.png")).unwrap().to_rgba8();
+        let image = render(DEFAULT_MAP, 1).unwrap();
+
+        assert_eq!(image.dimensions(), snapshot.dimensions());
+        let wrong = image.pixels().zip(snapshot.pixels()).filter(|(pixel, expected)| pixel != expected).count();
+        assert_eq!(wrong, 0, "{} pixels differ from src/tests/level.png", wrong);
+    }
+
+    #[test]
+    fn thumbnails_fit_the_square() {
+        let image = thumbnail(DEFAULT_MAP, 64).unwrap();
+
+        assert_eq!(image.width().max(image.height()), 64);
+        assert!(render(DEFAULT_MAP, 0).is_err());
+    }
+}
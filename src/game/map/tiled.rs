@@ -0,0 +1,424 @@
+use std::{fs, path::Path};
+
+use anyhow::{Result, bail, anyhow};
+use bevy::{prelude::*, utils::HashMap};
+use serde_json::Value;
+use xml::reader::{EventReader, XmlEvent};
+
+use super::{WallType, ConsumableType};
+
+/// Files the importer handles, .json and .tmj are both Tiled's JSON format
+pub const TILED_EXTENSIONS: [&str; 3] = ["tmx", "tmj", "json"];
+
+/// The ones the asset server hands to the importer, any .json asset would be taken for a map otherwise
+/// so .json maps are only read by path (see read_map)
+pub const TILED_ASSET_EXTENSIONS: [&str; 2] = ["tmx", "tmj"];
+
+/// Tiled keeps flipping and rotation in the top bits of a tile id
+const FLIP_FLAGS: u32 = 0xf000_0000;
+
+/// Properties as text, Tiled's bools and numbers included
+type Properties = HashMap<String, String>;
+
+/// Made by Tiled rather than written by hand
+pub fn is_tiled(path: &Path) -> bool {
+    path.extension().and_then(|extension| extension.to_str()).map_or(false, |extension| TILED_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// The text of a map file, Tiled maps are converted to .map text
+pub fn read_map(path: &Path) -> Result<String> {
+    if !is_tiled(path) { return Ok(fs::read_to_string(path)?) }
+
+    let bytes = fs::read(path)?;
+    let folder = path.parent().unwrap_or(Path::new("."));
+
+    let mut tilesets = HashMap::new();
+    for source in external_tilesets(&bytes)? {
+        let tileset = fs::read(folder.join(&source))?;
+        tilesets.insert(source, tileset);
+    }
+
+    convert(&bytes, &tilesets)
+}
+
+/// A tile as drawn by the designer, read from the tile's custom properties
+/// `wall` is a WallType (Blocking, Gate or Teleport), `consumable` a ConsumableType (PacDot, PowerPellet or Fruit)
+/// `tunnel = true` makes a ghost tunnel, a tile with none of these is a plain wall
+fn tile_char(properties: &Properties) -> Result<char> {
+    if let Some(wall) = properties.get("wall") {
+        return WallType::from_name(wall).map(|wall| wall.tile()).ok_or_else(|| anyhow!("Unknown wall type {:?}", wall))
+    }
+
+    if let Some(consumable) = properties.get("consumable") {
+        return ConsumableType::from_name(consumable).map(|consumable| consumable.tile()).ok_or_else(|| anyhow!("Unknown consumable type {:?}", consumable))
+    }
+
+    if properties.get("tunnel").map_or(false, |tunnel| tunnel == "true") { return Ok('~') }
+
+    Ok('#')
+}
+
+/// Objects are told apart by their class (type in older versions of Tiled) or failing that their name
+fn object_char(kind: &str) -> Option<char> {
+    match kind.to_lowercase().as_str() {
+        "spawn" => Some('S'),
+        "house" => Some('H'),
+        "teleport" => Some('|'),
+        "fruit" => Some('F'),
+        "blinky" => Some('B'),
+        "pinky" => Some('P'),
+        "inky" => Some('I'),
+        "clyde" => Some('C'),
+        _ => None,
+    }
+}
+
+/// An object from an object layer, in pixels
+#[derive(Debug, Clone, Default)]
+struct Object {
+    kind: String,
+    position: Vec2,
+    size: Vec2,
+    /// Tile objects sit on their bottom left corner instead of the top left
+    is_tile: bool,
+}
+
+/// The parts of a Tiled map the game uses, from either format
+#[derive(Debug, Clone, Default)]
+struct TiledMap {
+    width: usize,
+    height: usize,
+    tile_size: Vec2,
+    properties: Properties,
+    /// Custom properties of each tile by global id
+    tiles: HashMap<u32, Properties>,
+    /// Tile layers bottom to top, a global id per cell with 0 for empty
+    layers: Vec<Vec<u32>>,
+    objects: Vec<Object>,
+}
+
+impl TiledMap {
+    /// Tile layers are stacked, the top tile in each cell wins, empty cells are open space
+    /// Objects go on top, on the tile under the middle of the object
+    fn to_map(&self) -> Result<String> {
+        if self.width == 0 || self.height == 0 || self.tile_size.min_element() <= 0.0 { bail!("The map has no size") }
+
+        let mut rows = vec![vec![' '; self.width]; self.height];
+
+        for layer in &self.layers {
+            for (index, gid) in layer.iter().enumerate().take(self.width * self.height) {
+                let gid = gid & !FLIP_FLAGS;
+                if gid == 0 { continue }
+
+                let empty = Properties::default();
+                rows[index / self.width][index % self.width] = tile_char(self.tiles.get(&gid).unwrap_or(&empty))?;
+            }
+        }
+
+        for object in &self.objects {
+            let Some(c) = object_char(&object.kind) else { warn!("Ignoring Tiled object {:?}", object.kind); continue };
+
+            let top_left = if object.is_tile { object.position - Vec2::new(0.0, object.size.y) } else { object.position };
+            let centre = (top_left + object.size / 2.0) / self.tile_size;
+            let (x, y) = (centre.x.floor() as isize, centre.y.floor() as isize);
+
+            if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                bail!("The {} object is off the map", object.kind);
+            }
+            rows[y as usize][x as usize] = c;
+        }
+
+        let mut map = String::new();
+
+        // Map properties with the same names as the header's go in the header
+        let mut header = toml::Table::new();
+        for name in ["title", "author", "wall_colour"] {
+            if let Some(value) = self.properties.get(name) { header.insert(name.to_string(), toml::Value::String(value.clone())); }
+        }
+        if let Some(speed) = self.properties.get("tunnel_speed") {
+            header.insert("tunnel_speed".to_string(), toml::Value::Float(speed.parse()?));
+        }
+        if !header.is_empty() {
+            map += &format!("---\n{}---\n", toml::to_string(&header)?);
+        }
+
+        for row in rows {
+            map.push_str(row.iter().collect::<String>().trim_end());
+            map.push('\n');
+        }
+
+        Ok(map)
+    }
+}
+
+/// Tiled JSON starts with {, TMX is XML
+fn is_json(bytes: &[u8]) -> bool {
+    bytes.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'{')
+}
+
+/// Files the map's tilesets are kept in, relative to the map, read these and pass them to `convert`
+pub fn external_tilesets(bytes: &[u8]) -> Result<Vec<String>> {
+    let sources = match is_json(bytes) {
+        true => {
+            let root: Value = serde_json::from_slice(bytes)?;
+            root["tilesets"].as_array().into_iter().flatten().filter_map(|tileset| tileset["source"].as_str().map(str::to_string)).collect()
+        },
+        false => {
+            let root = Element::parse(bytes)?;
+            root.children_named("tileset").filter_map(|tileset| tileset.attributes.get("source").cloned()).collect()
+        },
+    };
+
+    Ok(sources)
+}
+
+/// A Tiled map (TMX or JSON) as .map text, `tilesets` holds the files from `external_tilesets`
+/// Tile layers have to be saved as CSV, compressed and base64 layers aren't read
+pub fn convert(bytes: &[u8], tilesets: &HashMap<String, Vec<u8>>) -> Result<String> {
+    let map = if is_json(bytes) { from_json(bytes, tilesets)? } else { from_tmx(bytes, tilesets)? };
+    map.to_map()
+}
+
+fn json_properties(value: &Value) -> Properties {
+    value["properties"].as_array().into_iter().flatten()
+        .filter_map(|property| {
+            let name = property["name"].as_str()?;
+            let value = match &property["value"] { Value::String(text) => text.clone(), other => other.to_string() };
+            Some((name.to_string(), value))
+        })
+        .collect()
+}
+
+/// The properties of each tile in a tileset, by global id
+fn json_tileset(tileset: &Value, first_gid: u32, tiles: &mut HashMap<u32, Properties>) {
+    for tile in tileset["tiles"].as_array().into_iter().flatten() {
+        let Some(id) = tile["id"].as_u64() else { continue };
+        tiles.insert(first_gid + id as u32, json_properties(tile));
+    }
+}
+
+fn from_json(bytes: &[u8], tilesets: &HashMap<String, Vec<u8>>) -> Result<TiledMap> {
+    let root: Value = serde_json::from_slice(bytes)?;
+    if root["infinite"].as_bool() == Some(true) { bail!("Infinite Tiled maps can't be imported") }
+
+    let size = |name: &str| root[name].as_u64().ok_or_else(|| anyhow!("The map has no {}", name));
+    let mut map = TiledMap {
+        width: size("width")? as usize,
+        height: size("height")? as usize,
+        tile_size: Vec2::new(size("tilewidth")? as f32, size("tileheight")? as f32),
+        properties: json_properties(&root),
+        ..Default::default()
+    };
+
+    for tileset in root["tilesets"].as_array().into_iter().flatten() {
+        let first_gid = tileset["firstgid"].as_u64().unwrap_or(1) as u32;
+
+        match tileset["source"].as_str() {
+            Some(source) => {
+                let bytes = tilesets.get(source).ok_or_else(|| anyhow!("Missing tileset {}", source))?;
+                if is_json(bytes) {
+                    json_tileset(&serde_json::from_slice(bytes)?, first_gid, &mut map.tiles);
+                } else {
+                    tmx_tileset(&Element::parse(bytes)?, first_gid, &mut map.tiles);
+                }
+            },
+            None => json_tileset(tileset, first_gid, &mut map.tiles),
+        }
+    }
+
+    // Groups can hold more layers
+    let mut layers: Vec<&Value> = root["layers"].as_array().into_iter().flatten().collect();
+    while !layers.is_empty() {
+        let layer = layers.remove(0);
+        if layer["visible"].as_bool() == Some(false) { continue }
+
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                let Some(data) = layer["data"].as_array() else { bail!("Tile layer {:?} has to be saved as CSV", layer["name"]) };
+                map.layers.push(data.iter().map(|gid| gid.as_u64().unwrap_or(0) as u32).collect());
+            },
+            Some("objectgroup") => {
+                for object in layer["objects"].as_array().into_iter().flatten() {
+                    let kind = ["class", "type", "name"].iter().filter_map(|name| object[*name].as_str()).find(|kind| !kind.is_empty()).unwrap_or_default();
+                    let number = |name: &str| object[name].as_f64().unwrap_or(0.0) as f32;
+
+                    map.objects.push(Object {
+                        kind: kind.to_string(),
+                        position: Vec2::new(number("x"), number("y")),
+                        size: Vec2::new(number("width"), number("height")),
+                        is_tile: object["gid"].is_u64(),
+                    });
+                }
+            },
+            Some("group") => {
+                let children: Vec<&Value> = layer["layers"].as_array().into_iter().flatten().collect();
+                layers.splice(0..0, children);
+            },
+            _ => {},
+        }
+    }
+
+    Ok(map)
+}
+
+/// Just enough of an XML tree to read TMX and TSX files
+#[derive(Debug, Clone, Default)]
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut stack = vec![Element::default()];
+
+        for event in EventReader::new(bytes) {
+            match event? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    let attributes = attributes.into_iter().map(|attribute| (attribute.name.local_name, attribute.value)).collect();
+                    stack.push(Element { name: name.local_name, attributes: attributes, ..Default::default() });
+                },
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().ok_or_else(|| anyhow!("Unbalanced XML"))?;
+                    stack.last_mut().ok_or_else(|| anyhow!("Unbalanced XML"))?.children.push(element);
+                },
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    if let Some(element) = stack.last_mut() { element.text += &text }
+                },
+                _ => {},
+            }
+        }
+
+        // The document holds the root element
+        stack.pop().and_then(|document| document.children.into_iter().next()).ok_or_else(|| anyhow!("Empty XML"))
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn number(&self, name: &str) -> f32 {
+        self.attributes.get(name).and_then(|value| value.parse().ok()).unwrap_or(0.0)
+    }
+
+    fn properties(&self) -> Properties {
+        self.children_named("properties").flat_map(|properties| properties.children_named("property"))
+            .filter_map(|property| {
+                let value = property.attributes.get("value").cloned().unwrap_or_else(|| property.text.trim().to_string());
+                Some((property.attributes.get("name")?.clone(), value))
+            })
+            .collect()
+    }
+}
+
+fn tmx_tileset(tileset: &Element, first_gid: u32, tiles: &mut HashMap<u32, Properties>) {
+    for tile in tileset.children_named("tile") {
+        let Some(id) = tile.attributes.get("id").and_then(|id| id.parse::<u32>().ok()) else { continue };
+        tiles.insert(first_gid + id, tile.properties());
+    }
+}
+
+/// Tile and object layers, going into groups
+fn tmx_layers(parent: &Element, map: &mut TiledMap) -> Result<()> {
+    for layer in &parent.children {
+        if layer.attributes.get("visible").map(String::as_str) == Some("0") { continue }
+
+        match layer.name.as_str() {
+            "layer" => {
+                let Some(data) = layer.children_named("data").next() else { continue };
+                if data.attributes.get("encoding").map(String::as_str) != Some("csv") {
+                    bail!("Tile layer {:?} has to be saved as CSV", layer.attributes.get("name"));
+                }
+                if data.children_named("chunk").next().is_some() { bail!("Infinite Tiled maps can't be imported") }
+
+                let cells = data.text.split(',').map(str::trim).filter(|gid| !gid.is_empty()).map(str::parse::<u32>).collect::<Result<Vec<_>, _>>()?;
+                map.layers.push(cells);
+            },
+            "objectgroup" => {
+                for object in layer.children_named("object") {
+                    let kind = ["class", "type", "name"].iter().filter_map(|name| object.attributes.get(*name)).find(|kind| !kind.is_empty()).cloned().unwrap_or_default();
+
+                    map.objects.push(Object {
+                        kind: kind,
+                        position: Vec2::new(object.number("x"), object.number("y")),
+                        size: Vec2::new(object.number("width"), object.number("height")),
+                        is_tile: object.attributes.contains_key("gid"),
+                    });
+                }
+            },
+            "group" => tmx_layers(layer, map)?,
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+fn from_tmx(bytes: &[u8], tilesets: &HashMap<String, Vec<u8>>) -> Result<TiledMap> {
+    let root = Element::parse(bytes)?;
+    if root.name != "map" { bail!("Not a Tiled map") }
+
+    let mut map = TiledMap {
+        width: root.number("width") as usize,
+        height: root.number("height") as usize,
+        tile_size: Vec2::new(root.number("tilewidth"), root.number("tileheight")),
+        properties: root.properties(),
+        ..Default::default()
+    };
+
+    for tileset in root.children_named("tileset") {
+        let first_gid = tileset.attributes.get("firstgid").and_then(|gid| gid.parse().ok()).unwrap_or(1);
+
+        match tileset.attributes.get("source") {
+            Some(source) => {
+                let bytes = tilesets.get(source).ok_or_else(|| anyhow!("Missing tileset {}", source))?;
+                if is_json(bytes) {
+                    json_tileset(&serde_json::from_slice(bytes)?, first_gid, &mut map.tiles);
+                } else {
+                    tmx_tileset(&Element::parse(bytes)?, first_gid, &mut map.tiles);
+                }
+            },
+            None => tmx_tileset(tileset, first_gid, &mut map.tiles),
+        }
+    }
+
+    tmx_layers(&root, &mut map)?;
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_tmx() {
+        let map = convert(include_bytes!("../../tests/tiled_test_level.tmx"), &HashMap::new()).unwrap();
+        assert_eq!(map, "---\ntitle = \"Tiny\"\n---\n#######\n#B.@..#\n|~.S.~|\n#.#G#.#\n#######\n");
+    }
+
+    #[test]
+    fn converts_json() {
+        let map = convert(include_bytes!("../../tests/tiled_test_level.tmj"), &HashMap::new()).unwrap();
+        assert_eq!(map, "---\ntitle = \"Tiny\"\ntunnel_speed = 0.5\n---\n#######\n#B.@..#\n|~.S.~|\n#.#G#.#\n#######\n");
+    }
+
+    #[test]
+    fn json_needs_csv_layers_and_a_size() {
+        let base64 = r#"{"width": 1, "height": 1, "tilewidth": 8, "tileheight": 8, "layers": [{"type": "tilelayer", "data": "AQAAAA=="}]}"#;
+        assert!(convert(base64.as_bytes(), &HashMap::new()).is_err());
+        assert!(convert(br#"{"width": 1, "tilewidth": 8, "tileheight": 8}"#, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn tile_properties_go_through_the_types() {
+        let tile = |name: &str, value: &str| tile_char(&Properties::from([(name.to_string(), value.to_string())]));
+
+        assert_eq!(tile("wall", "Teleport").unwrap(), '|');
+        assert_eq!(tile("consumable", "Fruit").unwrap(), 'F');
+        assert_eq!(tile("tunnel", "true").unwrap(), '~');
+        assert!(tile("wall", "Window").is_err());
+        assert!(tile("consumable", "Cherry").is_err());
+    }
+}
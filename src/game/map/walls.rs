@@ -0,0 +1,194 @@
+//! Auto-tiling for the maze walls
+//!
+//! Every wall tile gets a piece picked from its neighbours, the line is drawn just inside
+//! the wall on each side that faces something open. This outlines any shape of wall block, so
+//! works for custom maps too. Thin walls (and the outer border, as outside the map counts as open)
+//! end up with a line on both sides like the arcade
+
+use bevy::{prelude::*, render::{render_resource::{Extent3d, TextureDimension, TextureFormat}, texture::ImageSampler}};
+
+use super::grid::MapGrid;
+
+/// Pixels along one side of a piece, a tile is drawn at TILE_SIZE scale so this covers one tile
+pub const WALL_TILE_PIXELS: usize = 10;
+
+/// How far in from the tile's edge the line sits
+const WALL_INSET: usize = 2;
+
+const ATLAS_COLUMNS: usize = 16;
+
+/// Pieces 0-255 are walls (see wall_piece), the ghost house door comes after
+pub const GATE_PIECE: usize = 256;
+const PIECE_COUNT: usize = GATE_PIECE + 1;
+
+pub const WALL_COLOUR: Color = Color::rgb(0.13, 0.13, 0.87);
+pub const GATE_COLOUR: [u8; 4] = [255, 184, 222, 255];
+
+// Open sides
+const NORTH: usize = 1;
+const EAST: usize = 2;
+const SOUTH: usize = 4;
+const WEST: usize = 8;
+
+// Open diagonals where both sides next to it are walls (inner corners)
+const NORTH_EAST: usize = 16;
+const SOUTH_EAST: usize = 32;
+const SOUTH_WEST: usize = 64;
+const NORTH_WEST: usize = 128;
+
+/// Walls block the outline, anything else (including the door and outside the map) is open
+fn is_solid(grid: &MapGrid, x: isize, y: isize) -> bool {
+    grid.is_wall(x, y)
+}
+
+/// Which piece a wall tile uses, None if the tile is not a wall
+pub fn wall_piece(grid: &MapGrid, x: usize, y: usize) -> Option<usize> {
+    let (x, y) = (x as isize, y as isize);
+    if !grid.is_wall(x, y) { return None }
+
+    let solid = |dx: isize, dy: isize| is_solid(grid, x + dx, y + dy);
+
+    let mut piece = 0;
+    if !solid(0, -1) { piece |= NORTH }
+    if !solid(1, 0) { piece |= EAST }
+    if !solid(0, 1) { piece |= SOUTH }
+    if !solid(-1, 0) { piece |= WEST }
+
+    if solid(0, -1) && solid(1, 0) && !solid(1, -1) { piece |= NORTH_EAST }
+    if solid(0, 1) && solid(1, 0) && !solid(1, 1) { piece |= SOUTH_EAST }
+    if solid(0, 1) && solid(-1, 0) && !solid(-1, 1) { piece |= SOUTH_WEST }
+    if solid(0, -1) && solid(-1, 0) && !solid(-1, -1) { piece |= NORTH_WEST }
+
+    Some(piece)
+}
+
+/// The pixels of a piece that are lit, [y][x] with y = 0 at the top
+pub fn piece_mask(piece: usize) -> [[bool; WALL_TILE_PIXELS]; WALL_TILE_PIXELS] {
+    let mut mask = [[false; WALL_TILE_PIXELS]; WALL_TILE_PIXELS];
+
+    let near = WALL_INSET;
+    let far = WALL_TILE_PIXELS - 1 - WALL_INSET;
+    let last = WALL_TILE_PIXELS - 1;
+
+    let mut horizontal = |y: usize, from: usize, to: usize| for x in from..=to { mask[y][x] = true };
+    let open = |side: usize| piece & side != 0;
+
+    if piece == GATE_PIECE {
+        // Drawn in its own colour, see wall_atlas_image
+        horizontal(WALL_TILE_PIXELS / 2 - 1, 0, last);
+        horizontal(WALL_TILE_PIXELS / 2, 0, last);
+        return mask
+    }
+
+    let start = if open(WEST) { near } else { 0 };
+    let end = if open(EAST) { far } else { last };
+    if open(NORTH) { horizontal(near, start, end) }
+    if open(SOUTH) { horizontal(far, start, end) }
+
+    if piece & NORTH_EAST != 0 { horizontal(near, far, last) }
+    if piece & SOUTH_EAST != 0 { horizontal(far, far, last) }
+    if piece & SOUTH_WEST != 0 { horizontal(far, 0, near) }
+    if piece & NORTH_WEST != 0 { horizontal(near, 0, near) }
+
+    let mut vertical = |x: usize, from: usize, to: usize| for y in from..=to { mask[y][x] = true };
+
+    let start = if open(NORTH) { near } else { 0 };
+    let end = if open(SOUTH) { far } else { last };
+    if open(WEST) { vertical(near, start, end) }
+    if open(EAST) { vertical(far, start, end) }
+
+    if piece & NORTH_EAST != 0 { vertical(far, 0, near) }
+    if piece & SOUTH_EAST != 0 { vertical(far, far, last) }
+    if piece & SOUTH_WEST != 0 { vertical(near, far, last) }
+    if piece & NORTH_WEST != 0 { vertical(near, 0, near) }
+
+    mask
+}
+
+/// Every piece drawn into one image, walls are white so the sprite colour picks the wall colour
+pub fn wall_atlas_image() -> Image {
+    let rows = (PIECE_COUNT + ATLAS_COLUMNS - 1) / ATLAS_COLUMNS;
+    let width = ATLAS_COLUMNS * WALL_TILE_PIXELS;
+    let height = rows * WALL_TILE_PIXELS;
+
+    let mut data = vec![0; width * height * 4];
+
+    for piece in 0..PIECE_COUNT {
+        let colour = if piece == GATE_PIECE { GATE_COLOUR } else { [255; 4] };
+        let origin_x = (piece % ATLAS_COLUMNS) * WALL_TILE_PIXELS;
+        let origin_y = (piece / ATLAS_COLUMNS) * WALL_TILE_PIXELS;
+
+        for (y, row) in piece_mask(piece).iter().enumerate() {
+            for (x, lit) in row.iter().enumerate() {
+                if !lit { continue }
+
+                let i = ((origin_y + y) * width + origin_x + x) * 4;
+                data[i..i + 4].copy_from_slice(&colour);
+            }
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d { width: width as u32, height: height as u32, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    image.sampler_descriptor = ImageSampler::nearest();
+
+    image
+}
+
+/// The wall atlas, ready for SpriteSheetBundles
+pub fn wall_atlas(images: &mut Assets<Image>) -> TextureAtlas {
+    let rows = (PIECE_COUNT + ATLAS_COLUMNS - 1) / ATLAS_COLUMNS;
+
+    TextureAtlas::from_grid(images.add(wall_atlas_image()), Vec2::splat(WALL_TILE_PIXELS as f32), ATLAS_COLUMNS, rows, None, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The piece for the middle of a 3x3 map
+    fn middle(map: &str) -> Option<usize> {
+        wall_piece(&MapGrid::new(map), 1, 1)
+    }
+
+    #[test]
+    fn straight() {
+        assert_eq!(middle("...\n###\n...\n"), Some(NORTH | SOUTH));
+        assert_eq!(middle(".#.\n.#.\n.#.\n"), Some(EAST | WEST));
+    }
+
+    #[test]
+    fn corner() {
+        // The inside of the bend gets a corner of its own
+        assert_eq!(middle("...\n.##\n.#.\n"), Some(NORTH | WEST | SOUTH_EAST));
+        assert_eq!(middle("...\n.##\n.##\n"), Some(NORTH | WEST));
+    }
+
+    #[test]
+    fn t_junction() {
+        assert_eq!(middle("...\n###\n.#.\n"), Some(NORTH | SOUTH_EAST | SOUTH_WEST));
+    }
+
+    #[test]
+    fn end_cap() {
+        assert_eq!(middle("...\n.##\n...\n"), Some(NORTH | SOUTH | WEST));
+    }
+
+    #[test]
+    fn isolated() {
+        assert_eq!(middle("...\n.#.\n...\n"), Some(NORTH | EAST | SOUTH | WEST));
+        // Outside the map and the ghost house door are open too
+        assert_eq!(wall_piece(&MapGrid::new("#\n"), 0, 0), Some(NORTH | EAST | SOUTH | WEST));
+        assert_eq!(middle(".G.\nG#G\n.G.\n"), Some(NORTH | EAST | SOUTH | WEST));
+    }
+
+    #[test]
+    fn filled_in_and_not_walls() {
+        assert_eq!(middle("###\n###\n###\n"), Some(0));
+        assert_eq!(middle("###\n#.#\n###\n"), None);
+    }
+}
@@ -18,12 +18,35 @@ pub mod player;
 pub mod enemy;
 pub mod map;
 pub mod util;
+pub mod score;
+pub mod fingerprint;
+pub mod hud;
+pub mod text;
+pub mod assets;
+pub mod view;
+pub mod movement;
+pub mod input;
+pub mod players;
+pub mod bot;
+pub mod browser;
+pub mod env;
+pub mod editor;
 
 pub const EXTRA_LIFE_SCORE_THRESHOLD: usize = 10000;
 
 /// PacDot Worth (in pts)
 pub const PAC_DOT_WORTH: i64 = 10;
 pub const POWER_PELLET_WORTH: i64 = 50;
+/// First ghost eaten per power pellet, doubles for each one after (200, 400, 800, 1600)
+pub const GHOST_EAT_WORTH: usize = 200;
+
+/// What a player driven ghost scores for catching Pac-Man
+pub const GHOST_CATCH_WORTH: usize = 500;
+
+use player::{PlayerData, GameData, PlayerMovement};
+use map::{Food, MapTile, SpawnPoint};
+use movement::TileMover;
+use enemy::Ghost;
 
 pub fn camera(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
@@ -101,6 +124,36 @@ impl CurrentDirection {
             CurrentDirection::Idle => CurrentDirection::Idle,
         }
     }
+
+    /// One tile in this direction, +y is down the map
+    pub fn tile_step(&self) -> IVec2 {
+        match self {
+            CurrentDirection::Up => IVec2::new(0, -1),
+            CurrentDirection::Down => IVec2::new(0, 1),
+            CurrentDirection::Left => IVec2::new(-1, 0),
+            CurrentDirection::Right => IVec2::new(1, 0),
+            CurrentDirection::Idle => IVec2::ZERO,
+        }
+    }
+}
+
+/// Title = picking one or two players
+/// Ready = the PLAYER ONE / READY! pause before a turn starts
+/// Playing = the normal game loop, all gameplay systems run here
+/// EnteringInitials = game over, the player is typing their initials for the high-score table
+/// Options = paused, the options menu is open
+/// Editor = the map editor is open
+/// MapSelect = the map browser is open, picking what to play next
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub enum GameState {
+    #[default]
+    Title,
+    Ready,
+    Playing,
+    EnteringInitials,
+    Options,
+    Editor,
+    MapSelect,
 }
 
 #[derive(Debug, )]
@@ -109,13 +162,49 @@ pub struct GameController;
 impl Plugin for GameController {
     fn build(&self, app: &mut App) {
         app
+            .add_state::<GameState>()
             .add_system(Self::animation_controller)
+            .add_system(Self::new_game.in_schedule(OnExit(GameState::Title)))
 
         ;
     }
 }
 
 impl GameController {
+    /// Puts everything back to how it was at the start of the game
+    pub fn new_game(
+        mut player: Query<(&mut PlayerData, &mut GameData, &mut MovementHelper, &mut TileMover, &mut Visibility), With<PlayerMovement>>,
+        spawn: Query<&MapTile, With<SpawnPoint>>,
+        mut food: Query<(&mut Food, &mut Visibility), Without<PlayerMovement>>,
+        mut ghosts: Query<&mut Ghost>,
+    ) {
+        let spawn = spawn.get_single().map(|spawn| spawn.0).ok();
+
+        for (mut player_data, mut game_data, mut helper, mut mover, mut visability) in &mut player {
+            *player_data = PlayerData::default();
+            game_data.level = 0;
+            game_data.transitioning = true;
+            helper.direction = None;
+            *visability = Visibility::Visible;
+
+            // Stand on the spawn for the ready screen
+            if let Some(spawn) = spawn {
+                mover.place(spawn);
+            }
+        }
+
+        for (mut food_data, mut visability) in &mut food {
+            food_data.is_eaten = false;
+            *visability = Visibility::Visible;
+        }
+
+        for mut ghost in &mut ghosts {
+            ghost.state = EntityState::Created;
+        }
+
+        info!("New Game!");
+    }
+
     pub fn animation_controller(mut entities: Query<(&mut AnimationTimer, &MovementHelper, &mut TextureAtlasSprite, &mut AnimationDescriptor)>, time: Res<Time>,) {
 
         for (mut timer, helper, mut sprite, mut desc) in &mut entities {
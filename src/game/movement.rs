@@ -0,0 +1,262 @@
+use bevy::prelude::*;
+
+use super::{map::grid::MapGrid, CurrentDirection, TILE_SIZE};
+
+/// Steps per tile, offsets are whole steps so movement never drifts
+pub const SUB_TILES: i32 = 10;
+
+/// How far past (or before) the centre of a tile a turn can still be taken
+/// The leftover is walked off diagonally, like the arcade's cornering
+pub const CORNER_WINDOW: i32 = 3;
+
+/// Sub-tile steps per tick for a world unit step size
+/// Not rounded, slower speeds only take a step every few ticks
+pub fn sub_tile_rate(step: f32) -> f32 {
+    step / (TILE_SIZE * 10.0) * SUB_TILES as f32
+}
+
+/// Pac-Man and a ghost meet when they share a tile, or when they swapped neighbouring tiles
+/// since the last check and passed through each other
+pub fn meets(pacman: IVec2, pacman_before: IVec2, ghost: IVec2, ghost_before: IVec2) -> bool {
+    if pacman == ghost { return true }
+
+    let swapped = pacman == ghost_before && ghost == pacman_before;
+    swapped && (pacman - ghost).abs().max_element() <= 1
+}
+
+/// Logical position on the map, the Transform is worked out from this
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileMover {
+    pub tile: IVec2,
+    /// Steps from the centre of the tile, +y is down the map
+    pub offset: IVec2,
+    /// The direction actually being moved in
+    pub heading: CurrentDirection,
+}
+
+/// What happened during a step
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StepResult {
+    pub entered_tile: bool,
+    pub teleported: bool,
+}
+
+impl TileMover {
+    pub fn new(tile: IVec2) -> Self {
+        Self { tile: tile, offset: IVec2::ZERO, heading: CurrentDirection::Idle }
+    }
+
+    /// Puts the mover in the centre of a tile, standing still
+    pub fn place(&mut self, tile: IVec2) {
+        *self = Self::new(tile);
+    }
+
+    /// In tiles, for drawing
+    pub fn position(&self) -> Vec2 {
+        self.tile.as_vec2() + self.offset.as_vec2() / SUB_TILES as f32
+    }
+
+    /// Steps already taken in a direction past the centre of the tile (negative if before it)
+    fn along(&self, direction: CurrentDirection) -> i32 {
+        let step = direction.tile_step();
+        self.offset.x * step.x + self.offset.y * step.y
+    }
+
+    /// The next tile in this direction can be walked into
+    /// Walking off the map is fine from a teleport
+    fn open(&self, grid: &MapGrid, direction: CurrentDirection) -> bool {
+        let next = self.tile + direction.tile_step();
+        grid.is_walkable(next) || (!grid.in_bounds(next) && grid.teleport_destination(self.tile).is_some())
+    }
+
+    fn can_turn(&self, grid: &MapGrid, wanted: CurrentDirection) -> bool {
+        // Backing up towards the centre is always possible
+        let reversing = wanted == self.heading.opposite() || self.heading == CurrentDirection::Idle;
+        if reversing && self.along(wanted) < 0 { return true }
+        if !self.open(grid, wanted) { return false }
+
+        // Turning onto the other axis has to be near the centre line
+        let step = wanted.tile_step();
+        let off_axis = if step.x != 0 { self.offset.y } else { self.offset.x };
+        off_axis.abs() <= CORNER_WINDOW
+    }
+
+    /// Turns to `wanted` if possible (otherwise keeps going), then moves `speed` steps
+    /// Idle stops in place
+    pub fn step(&mut self, grid: &MapGrid, wanted: CurrentDirection, speed: i32) -> StepResult {
+        let mut result = StepResult::default();
+
+        if wanted == CurrentDirection::Idle {
+            self.heading = CurrentDirection::Idle;
+            return result
+        }
+
+        if wanted != self.heading && self.can_turn(grid, wanted) {
+            self.heading = wanted;
+        }
+
+        let direction = self.heading.tile_step();
+        if direction == IVec2::ZERO { return result }
+
+        // One step at a time so a centre is never skipped
+        for _ in 0..speed {
+            // Ease back onto the centre line of the other axis
+            if direction.x != 0 { self.offset.y -= self.offset.y.signum() } else { self.offset.x -= self.offset.x.signum() }
+
+            let along = self.along(self.heading);
+            if along >= 0 && !self.open(grid, self.heading) {
+                self.offset -= direction * along;
+                break
+            }
+
+            self.offset += direction;
+
+            if along + 1 > SUB_TILES / 2 {
+                let from = self.tile;
+                self.tile += direction;
+                self.offset -= direction * SUB_TILES;
+                result.entered_tile = true;
+
+                if !grid.in_bounds(self.tile) {
+                    if let Some(destination) = grid.teleport_destination(from) {
+                        self.tile = destination;
+                        result.teleported = true;
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use super::*;
+    use crate::game::{map::{Food, ConsumableType, FoodSystem, MapTile}, player::{GameData, PlayerData, PlayerMovement}, players::Players, PAC_DOT_WORTH};
+
+    /// S at (1, 0) in a walled off room, the dot is at (1, 2)
+    fn room() -> MapGrid {
+        MapGrid::new(include_str!("../tests/blinky_ai_test_level.map"))
+    }
+
+    fn heading(tile: IVec2, offset: IVec2, heading: CurrentDirection) -> TileMover {
+        TileMover { tile: tile, offset: offset, heading: heading }
+    }
+
+    #[test]
+    fn stops_at_walls() {
+        let grid = room();
+
+        let mut mover = TileMover::new(IVec2::new(1, 0));
+        mover.step(&grid, CurrentDirection::Left, 20);
+        assert_eq!(mover, TileMover::new(IVec2::new(1, 0)));
+
+        // Off the top of the map isn't a teleport
+        mover.step(&grid, CurrentDirection::Up, 20);
+        assert_eq!((mover.tile, mover.offset), (IVec2::new(1, 0), IVec2::ZERO));
+
+        // Right along the top row to the wall at x = 5
+        mover.step(&grid, CurrentDirection::Right, 100);
+        assert_eq!((mover.tile, mover.offset), (IVec2::new(4, 0), IVec2::ZERO));
+    }
+
+    #[test]
+    fn corners_inside_the_window() {
+        let grid = room();
+
+        let mut mover = heading(IVec2::new(1, 0), IVec2::new(CORNER_WINDOW, 0), CurrentDirection::Right);
+        mover.step(&grid, CurrentDirection::Down, 1);
+
+        // The turn is taken and the overshoot walked off diagonally
+        assert_eq!(mover.heading, CurrentDirection::Down);
+        assert_eq!(mover.offset, IVec2::new(CORNER_WINDOW - 1, 1));
+
+        mover.step(&grid, CurrentDirection::Down, CORNER_WINDOW);
+        assert_eq!(mover.offset.x, 0);
+    }
+
+    #[test]
+    fn no_cornering_outside_the_window() {
+        let grid = room();
+
+        let mut mover = heading(IVec2::new(1, 0), IVec2::new(CORNER_WINDOW + 1, 0), CurrentDirection::Right);
+        mover.step(&grid, CurrentDirection::Down, 1);
+
+        assert_eq!(mover.heading, CurrentDirection::Right);
+        assert_eq!(mover.offset, IVec2::new(CORNER_WINDOW + 2, 0));
+    }
+
+    #[test]
+    fn reverses_anywhere() {
+        let grid = room();
+
+        let mut mover = heading(IVec2::new(2, 0), IVec2::new(4, 0), CurrentDirection::Right);
+        mover.step(&grid, CurrentDirection::Left, 1);
+
+        assert_eq!(mover.heading, CurrentDirection::Left);
+        assert_eq!(mover.offset, IVec2::new(3, 0));
+    }
+
+    #[test]
+    fn teleports_wrap_around() {
+        // Teleports at (0, 1) and (4, 1)
+        let grid = MapGrid::new(include_str!("../tests/teleport_test_level.map"));
+
+        let mut mover = TileMover::new(IVec2::new(0, 1));
+        let result = mover.step(&grid, CurrentDirection::Left, SUB_TILES / 2);
+        assert!(!result.entered_tile);
+        assert_eq!(mover.tile, IVec2::new(0, 1));
+
+        // Half a tile further comes out on the far side, still heading left
+        let result = mover.step(&grid, CurrentDirection::Left, 1);
+        assert!(result.entered_tile && result.teleported);
+        assert_eq!(mover, heading(IVec2::new(4, 1), IVec2::new(SUB_TILES / 2 - 1, 0), CurrentDirection::Left));
+
+        // And back again
+        let result = mover.step(&grid, CurrentDirection::Right, SUB_TILES);
+        assert!(result.teleported);
+        assert_eq!(mover.tile, IVec2::new(0, 1));
+    }
+
+    #[test]
+    fn catches_swaps() {
+        let (a, b) = (IVec2::new(3, 4), IVec2::new(4, 4));
+
+        assert!(meets(a, b, a, a));
+        assert!(meets(b, a, a, b));
+        assert!(!meets(b, a, IVec2::new(5, 4), b));
+        // Respawning isn't crossing
+        assert!(!meets(IVec2::new(1, 1), IVec2::new(9, 9), IVec2::new(9, 9), IVec2::new(1, 1)));
+    }
+
+    #[test]
+    fn eats_dots_on_entering_the_tile() {
+        let grid = room();
+        let dot = IVec2::new(1, 2);
+
+        let mut world = World::new();
+        world.insert_resource(Players::default());
+        let food = world.spawn((Food { is_eaten: false, r#type: ConsumableType::PacDot }, MapTile(dot), Visibility::Visible)).id();
+        let pacman = world.spawn((PlayerMovement, TileMover::new(IVec2::new(1, 1)), GameData { level: 0, transitioning: false }, PlayerData::default())).id();
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(FoodSystem::check_food);
+
+        // Up to half way is still the tile before
+        let result = world.get_mut::<TileMover>(pacman).unwrap().step(&grid, CurrentDirection::Down, SUB_TILES / 2);
+        assert!(!result.entered_tile);
+        schedule.run(&mut world);
+        assert!(!world.get::<Food>(food).unwrap().is_eaten);
+
+        let result = world.get_mut::<TileMover>(pacman).unwrap().step(&grid, CurrentDirection::Down, 1);
+        assert!(result.entered_tile);
+        schedule.run(&mut world);
+
+        assert!(world.get::<Food>(food).unwrap().is_eaten);
+        assert_eq!(world.get::<Visibility>(food), Some(&Visibility::Hidden));
+        assert_eq!(world.get::<PlayerData>(pacman).unwrap().score, PAC_DOT_WORTH as usize);
+    }
+}
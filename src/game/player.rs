@@ -1,9 +1,18 @@
-use bevy::{prelude::*}; // , render::texture::ImageSettings
+use bevy::{prelude::*, utils::HashMap}; // , render::texture::ImageSettings
 // use bevy_inspector_egui::Inspectable;
 
-use crate::game::{STEP_SIZE, util::check_collosion, AnimationDescriptor, ManualAnimationControl};
+use crate::game::{STEP_SIZE, AnimationDescriptor, ManualAnimationControl};
 
-use super::{AnimationTimer, MovementHelper, TILE_SIZE, map::{TileCollider, DEFAULT_MAP_ORIGIN, SpawnPoint}, EntityState, util::{get_real_pos, calculate_next_step}, EXTRA_LIFE_SCORE_THRESHOLD, CurrentDirection, enemy::Ghost};
+use super::{AnimationTimer, MovementHelper, TILE_SIZE, map::{CurrentMap, SpawnPoint, MapTile, grid::MapGrid}, EntityState, util::calculate_next_step, EXTRA_LIFE_SCORE_THRESHOLD, CurrentDirection, enemy::{Ghost, PlayerControlled}, GameState, assets::GameAssets, GHOST_EAT_WORTH, GHOST_CATCH_WORTH};
+use super::movement::{TileMover, sub_tile_rate, meets};
+use super::input::{Action, ActionState, SeatActions};
+use super::players::{Players, SEAT_COLOURS};
+use super::map::{Food, FoodSystem, campaign::Campaign};
+use super::bot::Autopilot;
+use super::text::{BitmapText, BitmapTextBundle, FloatingText, TextAlign};
+
+/// Above the maze and ghosts
+const FLOATING_TEXT_Z: f32 = 960.0;
 
 pub struct Player;
 
@@ -12,23 +21,33 @@ impl Plugin for Player {
         app
         .add_startup_system(Player::new)
         // .insert_resource(ImageSettings::default_nearest())
-        .add_system(PlayerMovement::tick)
-        .add_system(PlayerMovement::r#move)
-        .add_system(Player::state_checks)
-        .add_system(Player::player_checks)
+        // Pinned down so a tick plays out the same every run (the env relies on it)
+        .add_system(PlayerMovement::tick.in_set(OnUpdate(GameState::Playing)).after(PlayerMovement::r#move))
+        .add_system(PlayerMovement::sync_transform.after(PlayerMovement::tick))
+        .add_system(PlayerMovement::ghost_collisions.after(PlayerMovement::tick).in_set(OnUpdate(GameState::Playing)))
+        .add_system(PlayerMovement::r#move.in_set(OnUpdate(GameState::Playing)))
+        .add_system(Player::state_checks.in_set(OnUpdate(GameState::Playing)).after(PlayerMovement::ghost_collisions))
+        .add_system(Player::player_checks.in_set(OnUpdate(GameState::Playing)).after(FoodSystem::check_food))
         
         ;
     }
 }
 
 impl Player {
-    pub fn new(mut commands: Commands, asset_server: Res<AssetServer>, mut texture_atlases: ResMut<Assets<TextureAtlas>>,) {
-        let texture_handle = asset_server.load("sprites.png");
-        let atlas = TextureAtlas::from_grid(texture_handle, Vec2 { x: 15.0, y: 15.0 }, 2, 4, None, None);
-        let texture_atlas_handle = texture_atlases.add(atlas);
+    pub fn new(mut commands: Commands, assets: Res<GameAssets>) {
+        Self::spawn_pacman(&mut commands, &assets, 0);
+
+        info!("Player Ready!");
+    }
+
+    /// Seat 0 is always there, the others are only spawned for simultaneous games
+    pub fn spawn_pacman(commands: &mut Commands, assets: &GameAssets, seat: usize) -> Entity {
+        // Pac-Man is already yellow, the other seats are tinted
+        let colour = if seat == 0 { Color::WHITE } else { SEAT_COLOURS[seat] };
 
         commands.spawn(SpriteSheetBundle  {
-            texture_atlas: texture_atlas_handle,
+            texture_atlas: assets.player.clone(),
+            sprite: TextureAtlasSprite { color: colour, ..Default::default() },
              
             transform: Transform {
                 translation: Vec3 { x: 0.0, y: 0.0, z: 900.0 },
@@ -37,81 +56,104 @@ impl Player {
             },
             
             ..Default::default()
-        }).insert(Name::new("player"))
+        }).insert(Name::new(if seat == 0 { "player".to_string() } else { format!("player {}", seat + 1) }))
             .insert(PlayerMovement)
+            .insert(Seat(seat))
+            .insert(TileMover::new(IVec2::ZERO))
             .insert(AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)))
             .insert(MovementHelper{ direction: None })
             // still 3 lives
-            .insert(PlayerData { lives: 2, score: 0, state: EntityState::Created, extra_life_given: false })
+            .insert(PlayerData::default())
             .insert(GameData{ level: 0, transitioning: false })
             .insert(AnimationDescriptor{ main_controller: true, reset_on_idle: true, manual: ManualAnimationControl{ max_index: 8, index: 0, current_index: None, enable: false } })
 
-            ;
-
-        info!("Player Ready!");
+            .id()
     }
 
     pub fn state_checks(
-        mut player: Query<(&PlayerMovement, &mut Transform, &mut MovementHelper, &mut PlayerData)>,
-        mut spawn: Query<(&Transform), (Without<PlayerData>, With<SpawnPoint>)>
+        mut player: Query<(&Seat, &mut TileMover, &mut MovementHelper, &mut PlayerData, &mut GameData, &mut Visibility), With<PlayerMovement>>,
+        spawn: Query<&MapTile, (Without<PlayerData>, With<SpawnPoint>)>,
+        mut food: Query<(&mut Food, &MapTile, &mut Visibility), Without<PlayerMovement>>,
+        mut players: ResMut<Players>,
+        mut next_state: ResMut<NextState<GameState>>,
     ) {
-        
-        let (player, mut player_transform,mut helper, mut data) = player.single_mut();
+        let Ok(spawn) = spawn.get_single() else { return };
+
+        for (seat, mut mover, mut helper, mut data, mut game_data, mut visability) in &mut player {
+            match data.state {
+                EntityState::Alive => {},
+
+                // Everyone else plays on, a Pac-Man out of lives sits out until the game is over
+                EntityState::Dead if players.is_simultaneous() => {
+                    if data.lives == 0 {
+                        info!("{} is out! Score: {}", Players::name(seat.0), data.score);
+                        data.out = true;
+                        data.state = EntityState::Alive;
+                        *visability = Visibility::Hidden;
+                        mover.place(spawn.0);
+                        helper.direction = Some(CurrentDirection::Idle);
+                    } else {
+                        data.lives -= 1;
+                        data.state = EntityState::Respawning
+                    }
+                },
 
-        match data.state {
-            EntityState::Alive => {},
-            EntityState::Dead => {
-                data.lives -= 1;
+                EntityState::Dead => {
+                    // Either the same player goes again, the other player takes over or it's game over
+                    if !players.end_turn(&mut data, &mut game_data, &mut food) {
+                        info!("Game Over! Final score: {}", data.score);
+                        helper.direction = Some(CurrentDirection::Idle);
+                        next_state.set(GameState::EnteringInitials);
+                        return
+                    }
 
-                if data.lives == 0 {
-                    todo!("restart & score system");
-                } else {
-                    data.state = EntityState::Respawning
-                }
+                    mover.place(spawn.0);
+                    helper.direction = Some(CurrentDirection::Idle);
+                    data.state = EntityState::Alive;
+                    next_state.set(GameState::Ready);
+                },
 
-            },
+                EntityState::Respawning => {
+                    mover.place(spawn.0);
+                    warn!("No Animation attached!");
+                    helper.direction = Some(CurrentDirection::Idle);
+                    data.state = EntityState::Alive
+                },
 
-            EntityState::Respawning => {
-                let spawn = spawn.single();
-                player_transform.translation = get_real_pos(spawn.translation, DEFAULT_MAP_ORIGIN);
-                warn!("No Animation attached!");
-                helper.direction = Some(CurrentDirection::Idle);
-                data.state = EntityState::Alive
-            },
+                EntityState::Created => {
+                    mover.place(spawn.0);
+                    data.state = EntityState::Alive;
+                },
+                EntityState::Energized(time) => {
 
-            EntityState::Created => {
-                let spawn = spawn.single();
-                player_transform.translation = get_real_pos(spawn.translation, DEFAULT_MAP_ORIGIN);
-                data.state = EntityState::Alive;
-            },
-            EntityState::Energized(time) => {
+                    if time - 1 == 0 {
+                        debug!("Effect Over");
+                        data.state = EntityState::Alive
+                    } else 
+                    
 
-                if time - 1 == 0 {
-                    debug!("Effect Over");
-                    data.state = EntityState::Alive
-                } else 
-                
+                    {data.state = EntityState::Energized(time - 1);}
 
-                {data.state = EntityState::Energized(time - 1);}
+                },
+            }
+        }
 
-            },
+        // A simultaneous game is over once every Pac-Man is out
+        if players.is_simultaneous() && player.iter().all(|(_, _, _, data, _, _)| data.out) {
+            info!("Game Over!");
+            players.finish(player.iter().map(|(seat, _, _, data, game_data, _)| (seat.0, data, game_data)));
+            next_state.set(GameState::EnteringInitials);
         }
     }
 
     /// Checks Player Data for events
-    pub fn player_checks( mut player: Query<(&PlayerMovement, &mut Transform, &mut MovementHelper, &mut PlayerData)>,) {
-        let (player, mut player_transform,mut helper, mut data) = player.single_mut();
-
-        if data.lives == 0 {
-            todo!("Game Restart")
-        }
-
-        if data.score >= EXTRA_LIFE_SCORE_THRESHOLD && !data.extra_life_given {
-            data.lives += 1;
-            data.extra_life_given = true;   
+    pub fn player_checks( mut player: Query<&mut PlayerData, With<PlayerMovement>>,) {
+        for mut data in &mut player {
+            if data.score >= EXTRA_LIFE_SCORE_THRESHOLD && !data.extra_life_given {
+                data.lives += 1;
+                data.extra_life_given = true;   
+            }
         }
-        
-
     }
     
 }
@@ -121,93 +163,131 @@ impl Player {
 #[derive(Component, Reflect)]
 pub struct PlayerMovement;
 
+/// Which player a Pac-Man belongs to, 0 is player one
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seat(pub usize);
+
 impl PlayerMovement {
+    /// In a simultaneous game each Pac-Man only listens to its own player's bindings
     pub fn r#move(
-        mut player: Query<(&PlayerMovement, &mut Transform, &mut MovementHelper)>,
-        keyboard: Res<Input<KeyCode>>
+        mut player: Query<(&Seat, &mut MovementHelper), (With<PlayerMovement>, Without<Autopilot>)>,
+        actions: Res<ActionState>,
+        seat_actions: Res<SeatActions>,
+        players: Res<Players>,
     ) {
-        let (player, mut transform, mut MovementHelper) = player.single_mut();
-
-        if keyboard.pressed(KeyCode::W) || keyboard.pressed(KeyCode::Up) {
-            MovementHelper.direction = Some(CurrentDirection::Up)
+        for (seat, mut helper) in &mut player {
+            for (action, direction) in [(Action::Up, CurrentDirection::Up), (Action::Down, CurrentDirection::Down), (Action::Left, CurrentDirection::Left), (Action::Right, CurrentDirection::Right)] {
+                let pressed = if players.is_simultaneous() { seat_actions.pressed(seat.0, action) } else { actions.pressed(action) };
+                if pressed {
+                    helper.direction = Some(direction)
+                }
+            }
         }
+    }
 
-        if keyboard.pressed(KeyCode::S) || keyboard.pressed(KeyCode::Down) {
-            MovementHelper.direction = Some(CurrentDirection::Down)
-        }
+    /// Moves Pac-Man through the maze on the tile grid
+    pub fn tick(
+        grid: Res<MapGrid>,
+        campaign: Option<Res<Campaign>>,
+        mut query: Query<(&mut TileMover, &mut MovementHelper, &PlayerData, &GameData), With<PlayerMovement>>,
+        mut carry: Local<f32>,
+    ) {
+        // Slower levels skip a step now and then, every Pac-Man moves at the same speed
+        let level = query.iter().map(|(_, _, _, game_data)| game_data.level).next().unwrap_or(0);
+        let multiplier = campaign.map(|campaign| campaign.level(level).pacman_speed).unwrap_or(1.0);
 
-        if keyboard.pressed(KeyCode::A) || keyboard.pressed(KeyCode::Left) {
-            MovementHelper.direction = Some(CurrentDirection::Left)
-        }
+        *carry += sub_tile_rate(calculate_next_step(false)) * multiplier;
+        let speed = carry.floor();
+        *carry -= speed;
+        let speed = speed as i32;
 
-        if keyboard.pressed(KeyCode::D) || keyboard.pressed(KeyCode::Right) {
-            MovementHelper.direction = Some(CurrentDirection::Right)
-        }
+        for (mut mover, mut helper, data, _) in &mut query {
+            if data.out { continue }
 
-        if keyboard.pressed(KeyCode::Escape) {
-            debug!("Pausing");
-            MovementHelper.direction = Some(CurrentDirection::Idle)
+            match helper.direction {
+                Some(wanted) => {
+                    let result = mover.step(&grid, wanted, speed);
+                    if result.teleported { debug!("Teleported to {:?}", mover.tile) }
+                },
+                None => {helper.direction = Some(CurrentDirection::Left) },
+            }
         }
+    }
 
+    /// The Transform is only ever drawn from the tile position
+    pub fn sync_transform(current_map: Res<CurrentMap>, mut query: Query<(&TileMover, &mut Transform), (With<PlayerMovement>, Changed<TileMover>)>) {
+        for (mover, mut transform) in &mut query {
+            let position = mover.position();
+            let translation = current_map.tile_pos(position.x, position.y);
+            transform.translation.x = translation.x;
+            transform.translation.y = translation.y;
+        }
     }
 
-    pub fn tick(time: Res<Time>, texture_atlases: Res<Assets<TextureAtlas>>,
-    mut collision_checker: Query<(&Transform, &TileCollider), Without<PlayerMovement>>,
-    wall: Query<(&Transform, &TileCollider), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>)>,
-    mut query: Query<(
-        &mut AnimationTimer,
-        &mut TextureAtlasSprite,
-        &Handle<TextureAtlas>,
-        &PlayerMovement, 
-        &mut Transform,
-        &mut MovementHelper,
-        &mut PlayerData
-    )>,) {
-
-        for (mut timer, mut sprite, texture_atlas_handle, movement, mut transform, mut helper, mut player_data) in &mut query {
-            
-            match helper.direction {
-                Some(o) => {
-                    // println!("Modiying Direction: {:?}",o);
-                    match o {
-                        CurrentDirection::Up => {
-                            let raw_target = calculate_next_step(false);
-                            // T/t = Tiles per tick
-                            let target = transform.translation + Vec3::new(0.0, raw_target, 0.0);
-                            let touching = !check_collosion(target, &wall, DEFAULT_MAP_ORIGIN);
-                            if touching { transform.translation = target }
-                        },
-                        CurrentDirection::Down => {
-                            let raw_target = calculate_next_step(false);
-                            // T/t = Tiles per tick
-                            let target = transform.translation - Vec3::new(0.0, raw_target, 0.0);
-                            let touching = !check_collosion(target, &wall, DEFAULT_MAP_ORIGIN);
-                            if touching { transform.translation = target }
-
-                        },
-                        CurrentDirection::Left => {
-                            let raw_target = calculate_next_step(false);
-                            // T/t = Tiles per tick
-                            let target = transform.translation - Vec3::new(raw_target, 0.0, 0.0);
-                            let touching = !check_collosion(target, &wall, DEFAULT_MAP_ORIGIN);
-                            if touching { transform.translation = target }
-
-                        },
-                        CurrentDirection::Right => {
-                            let raw_target = calculate_next_step(false);
-                            // T/t = Tiles per tick
-                            let target = transform.translation + Vec3::new(raw_target, 0.0, 0.0);
-                            let touching = !check_collosion(target, &wall, DEFAULT_MAP_ORIGIN);
-                            if touching { transform.translation = target }
-                        },
-                        CurrentDirection::Idle => {}, // Don't move!
-                    }
-                },
-                None => {helper.direction = Some(CurrentDirection::Left) },
+    /// Sharing a tile with a ghost (or passing through one) either eats it or kills Pac-Man
+    /// A player driven ghost scores for the catch
+    pub fn ghost_collisions(
+        mut commands: Commands,
+        current_map: Res<CurrentMap>,
+        mut players: ResMut<Players>,
+        mut player: Query<(Entity, &TileMover, &mut PlayerData, &GameData), With<PlayerMovement>>,
+        mut ghosts: Query<(Entity, &mut Ghost, &Transform, Option<&PlayerControlled>)>,
+        mut last_tiles: Local<HashMap<Entity, IVec2>>,
+    ) {
+        let mut caught = false;
+
+        for (pacman, mover, mut data, game_data) in &mut player {
+            if game_data.transitioning || data.out { continue }
+            let pacman_before = last_tiles.get(&pacman).copied().unwrap_or(mover.tile);
+
+            for (entity, mut ghost, transform, controlled) in &mut ghosts {
+                if ghost.state != EntityState::Alive { continue }
+
+                let tile = current_map.world_to_tile(transform.translation);
+                let ghost_before = last_tiles.get(&entity).copied().unwrap_or(tile);
+                if !meets(mover.tile, pacman_before, tile, ghost_before) { continue }
+
+                match data.state {
+                    EntityState::Energized(_) => {
+                        let points = GHOST_EAT_WORTH << data.ghost_combo.min(3);
+                        data.score += points;
+                        data.ghost_combo += 1;
+                        ghost.state = EntityState::Dead;
+                        info!("Ate {:?} for {} points", ghost.personality, points);
+
+                        let position = Vec3 { z: FLOATING_TEXT_Z, ..transform.translation };
+                        commands.spawn(BitmapTextBundle::new(BitmapText::new(points.to_string()).with_colour(Color::CYAN).with_align(TextAlign::Centre), position, TILE_SIZE))
+                            .insert(FloatingText::new(1.0));
+                    },
+                    EntityState::Alive => {
+                        info!("Caught by a ghost!");
+                        data.state = EntityState::Dead;
+                        caught = true;
+
+                        if let Some(slot) = controlled.and_then(|controlled| players.slots.get_mut(controlled.seat)) {
+                            slot.score += GHOST_CATCH_WORTH;
+                        }
+                    },
+                    _ => {},
+                }
             }
+        }
 
+        if caught {
+            // Everyone goes back to the start
+            for (_, mut ghost, _, _) in &mut ghosts {
+                ghost.state = EntityState::Created;
+            }
         }
 
+        // For spotting swaps next time
+        last_tiles.clear();
+        for (entity, mover, _, _) in &player {
+            last_tiles.insert(entity, mover.tile);
+        }
+        for (entity, _, transform, _) in &ghosts {
+            last_tiles.insert(entity, current_map.world_to_tile(transform.translation));
+        }
     }
 }
 
@@ -217,10 +297,65 @@ pub struct PlayerData {
     pub score: usize,
     pub state: EntityState,
     pub extra_life_given: bool,
+    /// Ghosts eaten on the current power pellet
+    pub ghost_combo: u32,
+    /// Dots and pellets eaten this game, decides a versus game
+    pub dots: usize,
+    /// Out of lives in a simultaneous game, hidden until the game is over
+    pub out: bool,
+}
+
+impl Default for PlayerData {
+    fn default() -> Self {
+        Self { lives: 2, score: 0, state: EntityState::Created, extra_life_given: false, ghost_combo: 0, dots: 0, out: false }
+    }
 }
 
 #[derive(Component, Reflect)]
 pub struct GameData {
     pub level: usize,
     pub transitioning: bool,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::game::movement::SUB_TILES;
+
+    /// Sub-tile steps Pac-Man covers in a second along a long corridor, at the first level's speed
+    fn distance(pacman_speed: Option<f32>) -> i32 {
+        let mut app = App::new();
+        app.insert_resource(MapGrid::new(&format!("{}\n#S{}#\n{}\n", "#".repeat(80), ".".repeat(77), "#".repeat(80))));
+        if let Some(speed) = pacman_speed {
+            let manifest = format!("[[map]]\nfile = \"default\"\n\n[[level]]\npacman_speed = {}\n", speed);
+            app.insert_resource(Campaign::parse(&manifest, Path::new("assets/maps")).unwrap());
+        }
+        app.add_system(PlayerMovement::tick);
+
+        let pacman = app.world.spawn((
+            TileMover::new(IVec2::new(1, 1)),
+            MovementHelper { direction: Some(CurrentDirection::Right) },
+            PlayerData::default(),
+            GameData { level: 0, transitioning: false },
+            PlayerMovement,
+        )).id();
+
+        for _ in 0..60 {
+            app.update();
+        }
+
+        let mover = app.world.get::<TileMover>(pacman).unwrap();
+        (mover.tile.x - 1) * SUB_TILES + mover.offset.x
+    }
+
+    #[test]
+    fn the_levels_speed_moves_pacman() {
+        let full = distance(None);
+
+        assert!(full > 0);
+        assert_eq!(distance(Some(1.0)), full);
+        assert!((distance(Some(0.5)) * 2 - full).abs() <= 2);
+        assert!(distance(Some(0.8)) < full && distance(Some(1.2)) > full);
+    }
+}
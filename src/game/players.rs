@@ -0,0 +1,576 @@
+use bevy::prelude::*;
+
+use super::{player::{Player, PlayerData, GameData, PlayerMovement, Seat}, map::{Food, MapTile, SpawnPoint}, movement::TileMover, input::{Action, ActionState}, text::{BitmapText, SetBitmapText, BitmapTextBundle, TextAlign}, assets::GameAssets, enemy::{Ghost, GhostPersonality, PlayerControlled}, util::get_settings, GameState, TILE_SIZE};
+
+/// Players taking turns
+pub const MAX_PLAYERS: usize = 2;
+
+/// Pac-Men in the maze at once
+pub const MAX_PACMEN: usize = 4;
+
+/// How long the PLAYER ONE / READY! screen stays up before play starts
+const READY_TIME: f32 = 2.0;
+
+const PLAYER_NAMES: [&str; MAX_PACMEN] = ["PLAYER ONE", "PLAYER TWO", "PLAYER THREE", "PLAYER FOUR"];
+
+/// Each Pac-Man's colour, Pac-Man is yellow in sprites.png so these are also the sprite tints
+pub const SEAT_COLOURS: [Color; MAX_PACMEN] = [Color::YELLOW, Color::rgb(0.3, 1.0, 0.3), Color::rgb(1.0, 0.5, 0.0), Color::rgb(1.0, 0.25, 0.25)];
+
+/// Alternating = one Pac-Man, players take turns
+/// CoOp = a Pac-Man each, clearing the maze together
+/// Versus = a Pac-Man each, whoever eats the most dots wins
+/// Ghost = player one is Pac-Man, player two drives a ghost and scores for catching Pac-Man
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlayMode {
+    #[default]
+    Alternating,
+    CoOp,
+    Versus,
+    Ghost,
+}
+
+/// The ghost player two drives if settings.toml doesn't say
+const DEFAULT_PLAYER_GHOST: GhostPersonality = GhostPersonality::Blinky;
+
+/// Everything the title screen offers
+const TITLE_OPTIONS: [(PlayMode, usize, &str); 9] = [
+    (PlayMode::Alternating, 1, "1 PLAYER"),
+    (PlayMode::Alternating, 2, "2 PLAYERS"),
+    (PlayMode::CoOp, 2, "2P CO-OP"),
+    (PlayMode::CoOp, 3, "3P CO-OP"),
+    (PlayMode::CoOp, 4, "4P CO-OP"),
+    (PlayMode::Versus, 2, "2P VERSUS"),
+    (PlayMode::Versus, 3, "3P VERSUS"),
+    (PlayMode::Versus, 4, "4P VERSUS"),
+    (PlayMode::Ghost, 2, "2P VS GHOST"),
+];
+
+/// Everything kept for a player while the other one is playing
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerSlot {
+    pub lives: u64,
+    pub score: usize,
+    pub extra_life_given: bool,
+    pub level: usize,
+    /// Dots and pellets already eaten on this player's maze
+    pub eaten: Vec<IVec2>,
+    /// Out of lives
+    pub game_over: bool,
+    pub dots: usize,
+}
+
+impl Default for PlayerSlot {
+    fn default() -> Self {
+        let data = PlayerData::default();
+        Self { lives: data.lives, score: data.score, extra_life_given: data.extra_life_given, level: 0, eaten: Vec::new(), game_over: false, dots: 0 }
+    }
+}
+
+/// A final score, for the high-score table
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinalScore {
+    /// Empty in a one player game
+    pub name: String,
+    pub score: usize,
+    pub level: usize,
+}
+
+/// Who is playing and how
+/// Alternating: the player entity's PlayerData and GameData always belong to the current player,
+/// the others wait in their slot until their turn
+/// CoOp and Versus: every player has a Pac-Man (by Seat), the slots are only filled in once the game is over
+#[derive(Debug, Clone, Resource)]
+pub struct Players {
+    pub current: usize,
+    pub slots: Vec<PlayerSlot>,
+    pub mode: PlayMode,
+    /// Set when a versus game ends, stays None on a draw
+    pub winner: Option<usize>,
+    /// Played by the autopilot, nothing is kept afterwards
+    pub demo: bool,
+}
+
+impl Default for Players {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Players {
+    /// Alternating play
+    pub fn new(count: usize) -> Self {
+        Self { current: 0, slots: vec![PlayerSlot::default(); count.clamp(1, MAX_PLAYERS)], mode: PlayMode::Alternating, winner: None, demo: false }
+    }
+
+    /// Everyone in the maze at once
+    pub fn together(mode: PlayMode, count: usize) -> Self {
+        if mode == PlayMode::Alternating { return Self::new(count) }
+
+        Self { current: 0, slots: vec![PlayerSlot::default(); count.clamp(1, MAX_PACMEN)], mode: mode, winner: None, demo: false }
+    }
+
+    /// One player, driven by the autopilot
+    pub fn demo() -> Self {
+        Self { demo: true, ..Self::new(1) }
+    }
+
+    pub fn count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// A versus game that ended without a winner
+    pub fn drawn(&self) -> bool {
+        self.mode == PlayMode::Versus && self.winner.is_none() && self.slots.iter().all(|slot| slot.game_over)
+    }
+
+    pub fn is_simultaneous(&self) -> bool {
+        self.mode != PlayMode::Alternating
+    }
+
+    /// Pac-Men in the maze
+    pub fn pacmen(&self) -> usize {
+        match self.mode {
+            PlayMode::CoOp | PlayMode::Versus => self.count(),
+            PlayMode::Alternating | PlayMode::Ghost => 1,
+        }
+    }
+
+    pub fn name(index: usize) -> &'static str {
+        PLAYER_NAMES[index.min(MAX_PACMEN - 1)]
+    }
+
+    /// Keeps the current player's state in their slot
+    pub fn store(&mut self, data: &PlayerData, game_data: &GameData, eaten: Vec<IVec2>) {
+        let slot = &mut self.slots[self.current];
+        slot.lives = data.lives;
+        slot.score = data.score;
+        slot.extra_life_given = data.extra_life_given;
+        slot.level = game_data.level;
+        slot.dots = data.dots;
+        slot.eaten = eaten;
+    }
+
+    /// Hands the player entity over to a slot
+    pub fn load(&mut self, index: usize, data: &mut PlayerData, game_data: &mut GameData) -> &[IVec2] {
+        self.current = index;
+        let slot = &self.slots[index];
+
+        *data = PlayerData { lives: slot.lives, score: slot.score, extra_life_given: slot.extra_life_given, dots: slot.dots, ..PlayerData::default() };
+        game_data.level = slot.level;
+
+        &slot.eaten
+    }
+
+    /// Who plays after the current player, they go in turn and players out of lives are skipped
+    /// None once everyone is out
+    pub fn next_turn(&self) -> Option<usize> {
+        (1..=self.count()).map(|i| (self.current + i) % self.count()).find(|index| !self.slots[*index].game_over)
+    }
+
+    pub fn final_scores(&self) -> Vec<FinalScore> {
+        self.slots.iter().enumerate().map(|(index, slot)| FinalScore {
+            name: if self.count() > 1 { Self::name(index).to_string() } else { String::new() },
+            score: slot.score,
+            level: slot.level,
+        }).collect()
+    }
+
+    /// Called when the current player dies, returns false once the whole game is over
+    pub fn end_turn(
+        &mut self,
+        data: &mut PlayerData,
+        game_data: &mut GameData,
+        food: &mut Query<(&mut Food, &MapTile, &mut Visibility), Without<PlayerMovement>>,
+    ) -> bool {
+        // Lives are the spare lives, dying on the last one is game over for this player
+        if data.lives == 0 {
+            self.slots[self.current].game_over = true;
+            if self.count() > 1 { info!("Game over for {}", Self::name(self.current)) }
+        } else {
+            data.lives -= 1;
+        }
+
+        let eaten = food.iter().filter(|(food_data, _, _)| food_data.is_eaten).map(|(_, tile, _)| tile.0).collect();
+        self.store(data, game_data, eaten);
+
+        let Some(next) = self.next_turn() else { return false };
+        if next == self.current { return true }
+
+        let eaten = self.load(next, data, game_data).to_vec();
+        for (mut food_data, tile, mut visability) in food.iter_mut() {
+            food_data.is_eaten = eaten.contains(&tile.0);
+            *visability = if food_data.is_eaten { Visibility::Hidden } else { Visibility::Visible };
+        }
+
+        info!("{}'s turn", Self::name(next));
+        true
+    }
+
+    /// Ends a simultaneous game, every Pac-Man's final state goes in their slot
+    /// (a ghost player's score is already in theirs)
+    /// In versus whoever ate the most dots wins (the higher score breaks a tie, a tie on both is a draw)
+    pub fn finish<'a>(&mut self, pacmen: impl Iterator<Item = (usize, &'a PlayerData, &'a GameData)>) {
+        for (seat, data, game_data) in pacmen {
+            let Some(slot) = self.slots.get_mut(seat) else { continue };
+            slot.lives = data.lives;
+            slot.score = data.score;
+            slot.level = game_data.level;
+            slot.dots = data.dots;
+            slot.game_over = true;
+        }
+
+        if self.mode == PlayMode::Versus {
+            let best = self.slots.iter().map(|slot| (slot.dots, slot.score)).max();
+            let mut leaders = self.slots.iter().enumerate().filter(|(_, slot)| Some((slot.dots, slot.score)) == best).map(|(index, _)| index);
+            self.winner = leaders.next().filter(|_| leaders.next().is_none());
+
+            match self.winner {
+                Some(winner) => info!("{} wins with {} dots", Self::name(winner), self.slots[winner].dots),
+                None => info!("Draw"),
+            }
+        }
+    }
+}
+
+/// Counts down the ready screen, only exists in GameState::Ready
+#[derive(Resource)]
+pub struct ReadyTimer(pub Timer);
+
+/// Marks everything spawned for the title screen
+#[derive(Component)]
+pub struct TitleScreen;
+
+#[derive(Component)]
+pub struct TitleOption(pub usize);
+
+/// Index into TITLE_OPTIONS
+#[derive(Debug, Clone, Copy, Default, Resource)]
+pub struct TitleSelection(pub usize);
+
+pub struct PlayersPlugin;
+
+impl Plugin for PlayersPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Players>()
+            .init_resource::<TitleSelection>()
+            .add_system(Self::title_begin.in_schedule(OnEnter(GameState::Title)))
+            .add_system(Self::title_input.in_set(OnUpdate(GameState::Title)))
+            .add_system(Self::title_display.in_set(OnUpdate(GameState::Title)).after(Self::title_input))
+            .add_system(Self::title_finish.in_schedule(OnExit(GameState::Title)))
+            .add_system(Self::seat_pacmen.in_schedule(OnExit(GameState::Title)))
+            .add_system(Self::seat_ghost.in_schedule(OnExit(GameState::Title)))
+            .add_system(Self::reseat_ghost)
+            .add_system(Self::ready_begin.in_schedule(OnEnter(GameState::Ready)))
+            .add_system(Self::ready_tick.in_set(OnUpdate(GameState::Ready)))
+
+        ;
+    }
+}
+
+impl PlayersPlugin {
+    pub fn title_begin(mut commands: Commands, players: Res<Players>) {
+        let scale = TILE_SIZE;
+        let line = 8.0 * scale * 2.0;
+        let text = |text: BitmapText, y: f32| BitmapTextBundle::new(text.with_align(TextAlign::Centre), Vec3::new(0.0, y, 950.0), scale);
+
+        commands.spawn(text(BitmapText::new("PAC-MAN").with_colour(Color::YELLOW), line * 4.0)).insert(TitleScreen);
+
+        // The last versus game
+        if let Some(winner) = players.winner {
+            commands.spawn(text(BitmapText::new(format!("{} WINS", Players::name(winner))).with_colour(SEAT_COLOURS[winner]), line * 3.0)).insert(TitleScreen);
+        } else if players.drawn() {
+            commands.spawn(text(BitmapText::new("DRAW"), line * 3.0)).insert(TitleScreen);
+        }
+
+        for (index, (_, _, label)) in TITLE_OPTIONS.iter().enumerate() {
+            commands.spawn(text(BitmapText::new(*label), line * (2.0 - index as f32))).insert(TitleScreen).insert(TitleOption(index));
+        }
+    }
+
+    /// Up/Down picks how many play and how, Confirm starts
+    pub fn title_input(
+        mut commands: Commands,
+        actions: Res<ActionState>,
+        mut selection: ResMut<TitleSelection>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        let options = TITLE_OPTIONS.len();
+
+        if actions.just_pressed(Action::Up) {
+            selection.0 = (selection.0 + options - 1) % options;
+        }
+
+        if actions.just_pressed(Action::Down) {
+            selection.0 = (selection.0 + 1) % options;
+        }
+
+        if actions.just_pressed(Action::Confirm) {
+            let (mode, count, label) = TITLE_OPTIONS[selection.0.min(options - 1)];
+            info!("Starting a {} game", label);
+            commands.insert_resource(Players::together(mode, count));
+            next_state.set(GameState::Ready);
+        }
+    }
+
+    pub fn title_display(selection: Res<TitleSelection>, added: Query<(), Added<TitleOption>>, mut options: Query<(&TitleOption, &mut BitmapText)>) {
+        if !selection.is_changed() && added.is_empty() { return }
+
+        for (option, mut text) in &mut options {
+            text.set_colour(if option.0 == selection.0 { Color::YELLOW } else { Color::WHITE });
+        }
+    }
+
+    pub fn title_finish(mut commands: Commands, screen: Query<Entity, With<TitleScreen>>) {
+        for entity in &screen {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    /// One Pac-Man per player in a simultaneous game, otherwise just player one's
+    pub fn seat_pacmen(
+        mut commands: Commands,
+        assets: Res<GameAssets>,
+        players: Res<Players>,
+        pacmen: Query<(Entity, &Seat), With<PlayerMovement>>,
+        spawn: Query<&MapTile, With<SpawnPoint>>,
+    ) {
+        let wanted = players.pacmen();
+
+        for (entity, seat) in &pacmen {
+            if seat.0 >= wanted { commands.entity(entity).despawn_recursive(); }
+        }
+
+        let spawn = spawn.get_single().map(|tile| tile.0).unwrap_or(IVec2::ZERO);
+        for seat in 1..wanted {
+            if pacmen.iter().any(|(_, existing)| existing.0 == seat) { continue }
+
+            let entity = Player::spawn_pacman(&mut commands, &assets, seat);
+            commands.entity(entity).insert(TileMover::new(spawn)).insert(GameData { level: 0, transitioning: true });
+        }
+    }
+
+    /// The ghost player two drives in a ghost game
+    fn player_ghost() -> GhostPersonality {
+        match get_settings() {
+            Ok(settings) => settings["ghost_mode"]["ghost"].as_str().and_then(GhostPersonality::from_name).unwrap_or(DEFAULT_PLAYER_GHOST),
+            Err(e) => { error!("Failed to read ghost mode settings: {}", e); DEFAULT_PLAYER_GHOST },
+        }
+    }
+
+    /// Hands a ghost to player two in a ghost game, every other game is all AI
+    pub fn seat_ghost(
+        mut commands: Commands,
+        players: Res<Players>,
+        ghosts: Query<(Entity, &Ghost)>,
+    ) {
+        // Only a ghost game looks in settings.toml
+        let personality = (players.mode == PlayMode::Ghost).then(Self::player_ghost);
+
+        for (entity, ghost) in &ghosts {
+            commands.entity(entity).remove::<PlayerControlled>();
+
+            if let Some(personality) = personality.filter(|personality| *personality == ghost.personality) {
+                info!("{} is driving {:?}", Players::name(1), personality);
+                commands.entity(entity).insert(PlayerControlled::new(1));
+            }
+        }
+    }
+
+    /// The ghosts are respawned whenever the maze is rebuilt (a new level, a saved map, a map from the browser),
+    /// player two keeps driving theirs
+    pub fn reseat_ghost(
+        mut commands: Commands,
+        players: Res<Players>,
+        ghosts: Query<(Entity, &Ghost), (Added<Ghost>, Without<PlayerControlled>)>,
+    ) {
+        if players.mode != PlayMode::Ghost || ghosts.is_empty() { return }
+
+        let personality = Self::player_ghost();
+
+        for (entity, ghost) in &ghosts {
+            if ghost.personality == personality {
+                commands.entity(entity).insert(PlayerControlled::new(1));
+            }
+        }
+    }
+
+    pub fn ready_begin(mut commands: Commands) {
+        commands.insert_resource(ReadyTimer(Timer::from_seconds(READY_TIME, TimerMode::Once)));
+    }
+
+    pub fn ready_tick(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut timer: ResMut<ReadyTimer>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        timer.0.tick(time.delta());
+
+        if timer.0.finished() {
+            commands.remove_resource::<ReadyTimer>();
+            next_state.set(GameState::Playing);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+    use crate::game::map::ConsumableType;
+
+    type Turn<'w, 's> = (
+        ResMut<'w, Players>,
+        Query<'w, 's, (&'static mut PlayerData, &'static mut GameData)>,
+        Query<'w, 's, (&'static mut Food, &'static MapTile, &'static mut Visibility), Without<PlayerMovement>>,
+    );
+
+    /// Two players taking turns on a maze of three dots
+    fn game() -> World {
+        let mut world = World::new();
+        world.insert_resource(Players::new(2));
+        world.spawn((PlayerMovement, PlayerData::default(), GameData { level: 0, transitioning: false }));
+
+        for x in 0..3 {
+            world.spawn((Food { is_eaten: false, r#type: ConsumableType::PacDot }, MapTile(IVec2::new(x, 0)), Visibility::Visible));
+        }
+        world
+    }
+
+    /// The current player loses a life, false once the game is over
+    fn die(world: &mut World) -> bool {
+        let mut state: SystemState<Turn> = SystemState::new(world);
+        let (mut players, mut player, mut food) = state.get_mut(world);
+        let (mut data, mut game_data) = player.single_mut();
+        players.end_turn(&mut data, &mut game_data, &mut food)
+    }
+
+    fn eat(world: &mut World, x: i32) {
+        let mut food = world.query::<(&mut Food, &MapTile, &mut Visibility)>();
+        for (mut food_data, tile, mut visability) in food.iter_mut(world) {
+            if tile.0.x == x {
+                food_data.is_eaten = true;
+                *visability = Visibility::Hidden;
+            }
+        }
+    }
+
+    /// Dots gone from the maze, hidden as well as eaten
+    fn eaten(world: &mut World) -> Vec<i32> {
+        let mut food = world.query::<(&Food, &MapTile, &Visibility)>();
+        let mut eaten: Vec<i32> = food.iter(world).filter(|(food_data, _, _)| food_data.is_eaten).map(|(_, tile, visability)| {
+            assert_eq!(*visability, Visibility::Hidden);
+            tile.0.x
+        }).collect();
+        eaten.sort();
+        eaten
+    }
+
+    fn current(world: &World) -> usize {
+        world.resource::<Players>().current
+    }
+
+    #[test]
+    fn each_player_gets_their_own_maze_back() {
+        let mut world = game();
+        eat(&mut world, 0);
+        world.query::<&mut PlayerData>().single_mut(&mut world).score = 10;
+
+        assert!(die(&mut world));
+        assert_eq!((current(&world), eaten(&mut world)), (1, vec![]));
+        assert_eq!(world.query::<&PlayerData>().single(&world).score, 0);
+
+        eat(&mut world, 2);
+        assert!(die(&mut world));
+        assert_eq!((current(&world), eaten(&mut world)), (0, vec![0]));
+        let data = world.query::<&PlayerData>().single(&world);
+        assert_eq!((data.score, data.lives), (10, 1));
+
+        assert!(die(&mut world));
+        assert_eq!((current(&world), eaten(&mut world)), (1, vec![2]));
+    }
+
+    #[test]
+    fn players_out_of_lives_are_skipped() {
+        let mut world = game();
+        world.resource_mut::<Players>().slots[1].lives = 0;
+
+        // Player two dies on their last life, player one plays on alone
+        assert!(die(&mut world));
+        assert_eq!(current(&world), 1);
+        assert!(die(&mut world));
+        assert!(world.resource::<Players>().slots[1].game_over);
+        assert_eq!(current(&world), 0);
+
+        assert!(die(&mut world));
+        assert_eq!(current(&world), 0);
+        assert!(!die(&mut world));
+        assert!(world.resource::<Players>().slots.iter().all(|slot| slot.game_over));
+    }
+
+    #[test]
+    fn no_next_turn_once_everyone_is_out() {
+        let mut players = Players::new(2);
+        assert_eq!(players.next_turn(), Some(1));
+
+        players.slots[1].game_over = true;
+        assert_eq!(players.next_turn(), Some(0));
+
+        players.slots[0].game_over = true;
+        assert_eq!(players.next_turn(), None);
+    }
+
+    fn versus(results: &[(usize, usize)]) -> Players {
+        let mut players = Players::together(PlayMode::Versus, results.len());
+        let data: Vec<PlayerData> = results.iter().map(|&(dots, score)| PlayerData { dots: dots, score: score, ..default() }).collect();
+        let game_data = GameData { level: 0, transitioning: false };
+        players.finish(data.iter().enumerate().map(|(seat, data)| (seat, data, &game_data)));
+        players
+    }
+
+    #[test]
+    fn most_dots_wins_versus() {
+        let players = versus(&[(10, 500), (12, 100)]);
+        assert_eq!(players.winner, Some(1));
+        assert!(!players.drawn());
+        assert_eq!(players.slots[0].score, 500);
+    }
+
+    #[test]
+    fn score_breaks_a_tie_on_dots() {
+        assert_eq!(versus(&[(12, 500), (12, 100), (3, 0)]).winner, Some(0));
+    }
+
+    #[test]
+    fn tie_on_dots_and_score_is_a_draw() {
+        let players = versus(&[(12, 500), (12, 500)]);
+        assert_eq!(players.winner, None);
+        assert!(players.drawn());
+
+        // Only between the leaders
+        assert_eq!(versus(&[(12, 500), (12, 500), (13, 0)]).winner, Some(2));
+    }
+
+    #[test]
+    fn no_winner_outside_versus() {
+        let mut players = Players::together(PlayMode::CoOp, 2);
+        let data = PlayerData { dots: 5, ..default() };
+        let game_data = GameData { level: 0, transitioning: false };
+        players.finish([(0, &data, &game_data)].into_iter());
+        assert_eq!(players.winner, None);
+        assert!(!players.drawn());
+    }
+
+    #[test]
+    fn together_seats_up_to_every_pacman() {
+        let players = Players::together(PlayMode::Versus, 9);
+        assert_eq!((players.mode, players.count()), (PlayMode::Versus, MAX_PACMEN));
+        assert_eq!(Players::together(PlayMode::CoOp, 0).count(), 1);
+
+        // Alternating still only has room for MAX_PLAYERS
+        let players = Players::together(PlayMode::Alternating, 9);
+        assert_eq!((players.mode, players.count()), (PlayMode::Alternating, MAX_PLAYERS));
+    }
+}
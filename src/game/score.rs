@@ -0,0 +1,388 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{map::CurrentMap, util::today, fingerprint::{SettingsFingerprint, ranked_default}, text::{BitmapText, SetBitmapText, BitmapTextBundle, TextAlign}, input::{Action, ActionState}, players::{Players, FinalScore}, editor::TestPlay, GameState, TILE_SIZE};
+
+/// Kept next to settings.toml
+const HIGH_SCORE_FILE: &str = "./highscores.toml";
+
+/// How many entries are kept for each map
+pub const HIGH_SCORE_TABLE_SIZE: usize = 10;
+
+const INITIALS_LEN: usize = 3;
+const INITIALS_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub initials: String,
+    pub score: usize,
+    pub level: usize,
+    /// YYYY-MM-DD
+    pub date: String,
+    pub map_hash: String,
+    /// See SettingsFingerprint, older tables won't have these
+    #[serde(default)]
+    pub settings_hash: String,
+    #[serde(default = "ranked_default")]
+    pub ranked: bool,
+}
+
+/// The local high-score table
+/// Scores are kept per map (by hash) as a custom map's scores are not comparable to the default maze
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Resource)]
+pub struct HighScoreTable {
+    #[serde(default)]
+    pub maps: HashMap<String, Vec<HighScoreEntry>>,
+}
+
+impl HighScoreTable {
+    pub fn load() -> Result<Self> {
+        Self::load_from(Path::new(HIGH_SCORE_FILE))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(Path::new(HIGH_SCORE_FILE))
+    }
+
+    /// An empty table if the file isn't there yet
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+
+        Ok(toml::from_str(fs::read_to_string(path)?.as_str())?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        fs::write(path, toml::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    /// All entries for a map, highest score first
+    pub fn entries(&self, map_hash: &str) -> &[HighScoreEntry] {
+        self.maps.get(map_hash).map(|entries| entries.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn best(&self, map_hash: &str) -> Option<&HighScoreEntry> {
+        self.entries(map_hash).first()
+    }
+
+    /// Returns true if the score would make it onto the table for this map
+    pub fn qualifies(&self, map_hash: &str, score: usize) -> bool {
+        if score == 0 { return false }
+
+        let entries = self.entries(map_hash);
+        entries.len() < HIGH_SCORE_TABLE_SIZE || entries.last().map(|lowest| score > lowest.score).unwrap_or(true)
+    }
+
+    /// Inserts an entry keeping the table sorted, returns where it landed (if it stayed on the table)
+    pub fn insert(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        let entries = self.maps.entry(entry.map_hash.clone()).or_default();
+
+        let position = entries.iter().position(|other| entry.score > other.score).unwrap_or(entries.len());
+        entries.insert(position, entry);
+        entries.truncate(HIGH_SCORE_TABLE_SIZE);
+
+        if position < HIGH_SCORE_TABLE_SIZE { Some(position) } else { None }
+    }
+}
+
+/// Sent once a game is over and the high-score table has been dealt with
+/// initials is None if the score didn't make the local table
+#[derive(Debug, Clone)]
+pub struct GameFinished {
+    pub initials: Option<String>,
+    pub score: usize,
+    pub level: usize,
+}
+
+/// Arcade style initials entry, only exists while in GameState::EnteringInitials
+/// In a two player game each player that made the table gets a turn
+#[derive(Debug, Clone, Resource)]
+pub struct InitialsEntry {
+    /// Indexes into INITIALS_ALPHABET
+    pub letters: [usize; INITIALS_LEN],
+    pub cursor: usize,
+    /// Empty in a one player game
+    pub name: String,
+    pub score: usize,
+    pub level: usize,
+    /// Players still to enter their initials
+    pub pending: Vec<FinalScore>,
+}
+
+impl InitialsEntry {
+    pub fn initials(&self) -> String {
+        self.letters.iter().map(|letter| INITIALS_ALPHABET[*letter] as char).collect()
+    }
+
+    /// Takes the next score that makes the table, the ones that don't are finished straight away
+    fn next_qualifying(pending: &mut Vec<FinalScore>, table: &HighScoreTable, map_hash: &str, finished: &mut EventWriter<GameFinished>) -> Option<FinalScore> {
+        while !pending.is_empty() {
+            let score = pending.remove(0);
+            if table.qualifies(map_hash, score.score) { return Some(score) }
+
+            info!("Score of {} did not make the high-score table", score.score);
+            finished.send(GameFinished { initials: None, score: score.score, level: score.level });
+        }
+
+        None
+    }
+
+    /// Starts entry for a player, keeping whoever is still waiting
+    fn start(score: FinalScore, pending: Vec<FinalScore>) -> Self {
+        Self { letters: [0; INITIALS_LEN], cursor: 0, name: score.name, score: score.score, level: score.level, pending: pending }
+    }
+}
+
+/// Marks everything spawned for the initials screen
+#[derive(Component)]
+pub struct InitialsScreen;
+
+/// One of the three editable letters
+#[derive(Component)]
+pub struct InitialsSlot(pub usize);
+
+/// Whose initials are being entered
+#[derive(Component)]
+pub struct InitialsName;
+
+#[derive(Component)]
+pub struct InitialsScore;
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        let table = match HighScoreTable::load() {
+            Ok(table) => table,
+            Err(e) => { error!("Failed to load high-scores: {}", e); HighScoreTable::default() },
+        };
+
+        app
+            .insert_resource(table)
+            .add_event::<GameFinished>()
+            .add_system(InitialsEntry::begin.in_schedule(OnEnter(GameState::EnteringInitials)))
+            .add_system(InitialsEntry::input.in_set(OnUpdate(GameState::EnteringInitials)))
+            .add_system(InitialsEntry::display.in_set(OnUpdate(GameState::EnteringInitials)).after(InitialsEntry::input))
+            .add_system(InitialsEntry::finish.in_schedule(OnExit(GameState::EnteringInitials)))
+
+        ;
+    }
+}
+
+impl InitialsEntry {
+    pub fn begin(
+        mut commands: Commands,
+        table: Res<HighScoreTable>,
+        current_map: Res<CurrentMap>,
+        players: Res<Players>,
+        mut next_state: ResMut<NextState<GameState>>,
+        mut finished: EventWriter<GameFinished>,
+        test_play: Option<Res<TestPlay>>,
+    ) {
+        // Demo scores aren't kept
+        if players.demo {
+            next_state.set(GameState::Title);
+            return
+        }
+
+        // Nor are test plays, it's back to the editor
+        if test_play.is_some() {
+            next_state.set(GameState::Editor);
+            return
+        }
+
+        let mut pending = players.final_scores();
+
+        let Some(first) = Self::next_qualifying(&mut pending, &table, &current_map.hash, &mut finished) else {
+            next_state.set(GameState::Title);
+            return
+        };
+
+        commands.insert_resource(Self::start(first, pending));
+
+        let scale = TILE_SIZE;
+        let glyph_size = 8.0 * scale;
+
+        commands.spawn(BitmapTextBundle::new(BitmapText::new("GAME OVER").with_colour(Color::RED).with_align(TextAlign::Centre), Vec3::new(0.0, 120.0, 950.0), scale)).insert(InitialsScreen);
+        commands.spawn(BitmapTextBundle::new(BitmapText::new("").with_colour(Color::CYAN).with_align(TextAlign::Centre), Vec3::new(0.0, 90.0, 950.0), scale)).insert(InitialsScreen).insert(InitialsName);
+        commands.spawn(BitmapTextBundle::new(BitmapText::new("ENTER YOUR INITIALS").with_align(TextAlign::Centre), Vec3::new(0.0, 60.0, 950.0), scale)).insert(InitialsScreen);
+        commands.spawn(BitmapTextBundle::new(BitmapText::new("").with_align(TextAlign::Centre), Vec3::new(0.0, 0.0, 950.0), scale)).insert(InitialsScreen).insert(InitialsScore);
+
+        let start = -(INITIALS_LEN as f32 - 1.0) * glyph_size;
+        for slot in 0..INITIALS_LEN {
+            commands.spawn(BitmapTextBundle::new(BitmapText::new("A").with_align(TextAlign::Centre), Vec3::new(start + slot as f32 * glyph_size * 2.0, -60.0, 950.0), scale))
+                .insert(InitialsScreen)
+                .insert(InitialsSlot(slot));
+        }
+
+        info!("New high-score! Waiting for initials");
+    }
+
+    /// Up/Down picks the letter, Left/Right moves between letters and Confirm moves on (or finishes)
+    pub fn input(
+        actions: Res<ActionState>,
+        entry: Option<ResMut<Self>>,
+        mut table: ResMut<HighScoreTable>,
+        current_map: Res<CurrentMap>,
+        fingerprint: Option<Res<SettingsFingerprint>>,
+        mut next_state: ResMut<NextState<GameState>>,
+        mut finished: EventWriter<GameFinished>,
+    ) {
+        let (Some(mut entry), Some(fingerprint)) = (entry, fingerprint) else { return };
+        let cursor = entry.cursor;
+        let alphabet_len = INITIALS_ALPHABET.len();
+
+        if actions.just_pressed(Action::Up) {
+            entry.letters[cursor] = (entry.letters[cursor] + 1) % alphabet_len;
+        }
+
+        if actions.just_pressed(Action::Down) {
+            entry.letters[cursor] = (entry.letters[cursor] + alphabet_len - 1) % alphabet_len;
+        }
+
+        if actions.just_pressed(Action::Left) || actions.just_pressed(Action::Back) {
+            entry.cursor = cursor.saturating_sub(1);
+        }
+
+        if actions.just_pressed(Action::Right) {
+            entry.cursor = (cursor + 1).min(INITIALS_LEN - 1);
+        }
+
+        if actions.just_pressed(Action::Confirm) {
+            if cursor < INITIALS_LEN - 1 {
+                entry.cursor += 1;
+                return
+            }
+
+            let position = table.insert(HighScoreEntry {
+                initials: entry.initials(),
+                score: entry.score,
+                level: entry.level,
+                date: today(),
+                map_hash: current_map.hash.clone(),
+                settings_hash: fingerprint.hash.clone(),
+                ranked: fingerprint.ranked,
+            });
+
+            info!("{} placed {:?} on the high-score table{}", entry.initials(), position.map(|p| p + 1), if fingerprint.ranked { "" } else { " (unranked)" });
+
+            if let Err(e) = table.save() {
+                error!("Failed to save high-scores: {}", e);
+            }
+
+            finished.send(GameFinished { initials: Some(entry.initials()), score: entry.score, level: entry.level });
+
+            // Next player's turn, otherwise back to the title
+            let mut pending = std::mem::take(&mut entry.pending);
+            match Self::next_qualifying(&mut pending, &table, &current_map.hash, &mut finished) {
+                Some(next) => *entry = Self::start(next, pending),
+                None => next_state.set(GameState::Title),
+            }
+        }
+    }
+
+    pub fn display(
+        entry: Option<Res<Self>>,
+        mut slots: Query<(&InitialsSlot, &mut BitmapText), (Without<InitialsName>, Without<InitialsScore>)>,
+        mut name: Query<&mut BitmapText, (With<InitialsName>, Without<InitialsScore>)>,
+        mut score: Query<&mut BitmapText, (With<InitialsScore>, Without<InitialsName>)>,
+    ) {
+        let Some(entry) = entry else { return };
+        if !entry.is_changed() { return }
+
+        for mut text in &mut name {
+            text.set(entry.name.clone());
+        }
+
+        for mut text in &mut score {
+            text.set(entry.score.to_string());
+        }
+
+        for (slot, mut text) in &mut slots {
+            text.set((INITIALS_ALPHABET[entry.letters[slot.0]] as char).to_string());
+            text.set_colour(if slot.0 == entry.cursor { Color::YELLOW } else { Color::WHITE });
+        }
+    }
+
+    pub fn finish(mut commands: Commands, screen: Query<Entity, With<InitialsScreen>>) {
+        for entity in &screen {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        commands.remove_resource::<Self>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(initials: &str, score: usize) -> HighScoreEntry {
+        HighScoreEntry { initials: initials.to_string(), score: score, level: 1, date: "2023-04-01".to_string(), map_hash: "maze".to_string(), settings_hash: String::new(), ranked: true }
+    }
+
+    /// Scores 100, 90 ... 10
+    fn full_table() -> HighScoreTable {
+        let mut table = HighScoreTable::default();
+        for score in (1..=HIGH_SCORE_TABLE_SIZE).map(|place| place * 10) {
+            table.insert(entry("AAA", score));
+        }
+        table
+    }
+
+    fn scores(table: &HighScoreTable) -> Vec<usize> {
+        table.entries("maze").iter().map(|entry| entry.score).collect()
+    }
+
+    #[test]
+    fn ties_go_below_the_older_score() {
+        let mut table = HighScoreTable::default();
+
+        assert_eq!(table.insert(entry("OLD", 100)), Some(0));
+        assert_eq!(table.insert(entry("NEW", 100)), Some(1));
+        assert_eq!(table.best("maze").map(|best| best.initials.as_str()), Some("OLD"));
+    }
+
+    #[test]
+    fn a_full_table_drops_its_lowest() {
+        let mut table = full_table();
+
+        assert!(table.qualifies("maze", 55));
+        assert_eq!(table.insert(entry("BBB", 55)), Some(5));
+        assert_eq!(scores(&table), [100, 90, 80, 70, 60, 55, 50, 40, 30, 20]);
+    }
+
+    #[test]
+    fn low_scores_dont_qualify() {
+        let mut table = full_table();
+
+        assert!(!table.qualifies("maze", 10));
+        assert!(!table.qualifies("maze", 5));
+        assert_eq!(table.insert(entry("CCC", 5)), None);
+        assert_eq!(scores(&table).len(), HIGH_SCORE_TABLE_SIZE);
+        assert_eq!(scores(&table).last(), Some(&10));
+
+        // Other maps keep their own table, a zero is never a high score
+        assert!(table.qualifies("other", 5));
+        assert!(!HighScoreTable::default().qualifies("maze", 0));
+    }
+
+    #[test]
+    fn tables_are_saved_and_loaded() {
+        let path = std::env::temp_dir().join(format!("pacman-highscores-{}.toml", std::process::id()));
+        let table = full_table();
+
+        table.save_to(&path).unwrap();
+        let loaded = HighScoreTable::load_from(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap().maps, table.maps);
+        assert!(HighScoreTable::load_from(&path).unwrap().maps.is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use bevy::prelude::*;
+
+use super::assets::GameAssets;
+
+/// text.png is 16 glyphs wide, 28 rows of 8x8 glyphs (7 colours, 4 rows each)
+pub const GLYPH_PIXELS: f32 = 8.0;
+pub const SHEET_COLUMNS: usize = 16;
+pub const SHEET_ROWS: usize = 28;
+
+/// Index of a character in text.png (the white block, tint it for other colours)
+/// Unknown characters map to a blank glyph
+pub fn glyph_index(c: char) -> usize {
+    match c.to_ascii_uppercase() {
+        c @ 'A'..='O' => c as usize - 'A' as usize,
+        c @ 'P'..='Z' => 16 + c as usize - 'P' as usize,
+        '!' => 27,
+        c @ '0'..='9' => 32 + c as usize - '0' as usize,
+        '/' => 42,
+        '-' => 43,
+        '"' => 44,
+        _ => 15,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    /// The text starts at the entity
+    #[default]
+    Left,
+    Centre,
+    /// The text ends at the entity
+    Right,
+}
+
+/// Text drawn with the arcade font, each glyph is a child sprite
+/// Glyphs are 8 units wide before scaling, so the size is set with the entity's Transform scale
+#[derive(Component, Debug, Clone)]
+pub struct BitmapText {
+    pub text: String,
+    pub colour: Color,
+    pub align: TextAlign,
+    /// Fixed width layout in glyphs, shorter text is padded (by alignment) and longer text is cut
+    pub width: Option<usize>,
+}
+
+impl BitmapText {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into(), colour: Color::WHITE, align: TextAlign::Left, width: None }
+    }
+
+    pub fn with_colour(mut self, colour: Color) -> Self {
+        self.colour = colour;
+        self
+    }
+
+    pub fn with_align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// The characters actually drawn, after fixed width padding
+    pub fn glyphs(&self) -> Vec<char> {
+        let chars: Vec<char> = self.text.chars().collect();
+
+        let Some(width) = self.width else { return chars };
+        if chars.len() >= width {
+            return chars.into_iter().take(width).collect()
+        }
+
+        let padding = width - chars.len();
+        let (before, after) = match self.align {
+            TextAlign::Left => (0, padding),
+            TextAlign::Centre => (padding / 2, padding - padding / 2),
+            TextAlign::Right => (padding, 0),
+        };
+
+        std::iter::repeat(' ').take(before).chain(chars).chain(std::iter::repeat(' ').take(after)).collect()
+    }
+
+    /// Local x of the centre of glyph `index` out of `count`
+    fn glyph_x(&self, index: usize, count: usize) -> f32 {
+        let index = index as f32;
+        let count = count as f32;
+
+        match self.align {
+            TextAlign::Left => (index + 0.5) * GLYPH_PIXELS,
+            TextAlign::Centre => (index - (count - 1.0) / 2.0) * GLYPH_PIXELS,
+            TextAlign::Right => (index - count + 0.5) * GLYPH_PIXELS,
+        }
+    }
+}
+
+/// Setters for a queried BitmapText that only mark it changed (and redraw it) when the value is different
+/// Assigning through Mut marks it changed even if nothing is, so systems that run every frame use these
+pub trait SetBitmapText {
+    fn set(&mut self, text: impl Into<String>);
+    fn set_colour(&mut self, colour: Color);
+}
+
+impl SetBitmapText for Mut<'_, BitmapText> {
+    fn set(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        if self.text != text {
+            self.text = text;
+        }
+    }
+
+    fn set_colour(&mut self, colour: Color) {
+        if self.colour != colour {
+            self.colour = colour;
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct BitmapTextBundle {
+    pub text: BitmapText,
+    #[bundle]
+    pub spatial: SpatialBundle,
+}
+
+impl BitmapTextBundle {
+    pub fn new(text: BitmapText, translation: Vec3, scale: f32) -> Self {
+        Self {
+            text: text,
+            spatial: SpatialBundle {
+                transform: Transform { translation: translation, scale: Vec3::splat(scale), ..Default::default() },
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// A single glyph of a BitmapText
+#[derive(Component)]
+pub struct Glyph;
+
+/// Text that drifts up and disappears, e.g. the points for eating a ghost
+#[derive(Component)]
+pub struct FloatingText {
+    pub timer: Timer,
+    /// Units per second
+    pub rise: f32,
+}
+
+impl FloatingText {
+    pub fn new(seconds: f32) -> Self {
+        Self { timer: Timer::from_seconds(seconds, TimerMode::Once), rise: 20.0 }
+    }
+}
+
+pub struct TextPlugin;
+
+impl Plugin for TextPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(BitmapText::layout)
+            .add_system(FloatingText::tick)
+
+        ;
+    }
+}
+
+impl BitmapText {
+    /// Keeps the glyph sprites in line with the text
+    pub fn layout(
+        mut commands: Commands,
+        assets: Res<GameAssets>,
+        texts: Query<(Entity, &BitmapText, Option<&Children>), Changed<BitmapText>>,
+        mut glyphs: Query<(&mut TextureAtlasSprite, &mut Transform), With<Glyph>>,
+    ) {
+        for (entity, text, children) in &texts {
+            let chars = text.glyphs();
+            let existing: Vec<Entity> = children.map(|children| children.iter().copied().filter(|child| glyphs.contains(*child)).collect()).unwrap_or_default();
+
+            // Same length, just update the sprites in place
+            if existing.len() == chars.len() {
+                for (i, (child, c)) in existing.iter().zip(chars.iter()).enumerate() {
+                    if let Ok((mut sprite, mut transform)) = glyphs.get_mut(*child) {
+                        sprite.index = glyph_index(*c);
+                        sprite.color = text.colour;
+                        transform.translation.x = text.glyph_x(i, chars.len());
+                    }
+                }
+                continue;
+            }
+
+            for child in existing {
+                commands.entity(child).despawn_recursive();
+            }
+
+            commands.entity(entity).with_children(|parent| {
+                for (i, c) in chars.iter().enumerate() {
+                    parent.spawn(SpriteSheetBundle {
+                        texture_atlas: assets.font.clone(),
+                        sprite: TextureAtlasSprite { index: glyph_index(*c), color: text.colour, ..Default::default() },
+                        transform: Transform::from_xyz(text.glyph_x(i, chars.len()), 0.0, 0.0),
+                        ..Default::default()
+                    }).insert(Glyph);
+                }
+            });
+        }
+    }
+}
+
+impl FloatingText {
+    pub fn tick(mut commands: Commands, time: Res<Time>, mut texts: Query<(Entity, &mut FloatingText, &mut Transform)>) {
+        for (entity, mut floating, mut transform) in &mut texts {
+            floating.timer.tick(time.delta());
+            transform.translation.y += floating.rise * time.delta_seconds();
+
+            if floating.timer.finished() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Redraws(usize);
+
+    fn set_same(mut texts: Query<&mut BitmapText>) {
+        for mut text in &mut texts {
+            text.set("SAME");
+            text.set_colour(Color::WHITE);
+        }
+    }
+
+    fn count(mut redraws: ResMut<Redraws>, texts: Query<(), Changed<BitmapText>>) {
+        redraws.0 += texts.iter().count();
+    }
+
+    #[test]
+    fn setting_the_same_text_is_not_a_change() {
+        let mut world = World::new();
+        world.init_resource::<Redraws>();
+        world.spawn(BitmapText::new("SAME"));
+
+        let mut schedule = Schedule::new();
+        schedule.add_systems((set_same, count).chain());
+
+        // The first run sees the text being added
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Redraws>().0, 1);
+    }
+
+    #[test]
+    fn characters_map_onto_the_sheet() {
+        assert_eq!((glyph_index('A'), glyph_index('O'), glyph_index('P'), glyph_index('Z')), (0, 14, 16, 26));
+        assert_eq!(glyph_index('p'), glyph_index('P'));
+        assert_eq!((glyph_index('0'), glyph_index('9')), (32, 41));
+        assert_eq!((glyph_index('!'), glyph_index('/'), glyph_index('-'), glyph_index('"')), (27, 42, 43, 44));
+    }
+
+    #[test]
+    fn unknown_characters_are_blank() {
+        for c in [' ', '?', '.', 'é'] {
+            assert_eq!(glyph_index(c), 15, "{c:?}");
+        }
+    }
+
+    fn glyphs(text: BitmapText) -> String {
+        text.glyphs().into_iter().collect()
+    }
+
+    #[test]
+    fn fixed_width_pads_by_alignment() {
+        assert_eq!(glyphs(BitmapText::new("AB")), "AB");
+        assert_eq!(glyphs(BitmapText::new("AB").with_width(5)), "AB   ");
+        assert_eq!(glyphs(BitmapText::new("AB").with_width(5).with_align(TextAlign::Centre)), " AB  ");
+        assert_eq!(glyphs(BitmapText::new("AB").with_width(5).with_align(TextAlign::Right)), "   AB");
+    }
+
+    #[test]
+    fn fixed_width_cuts_long_text() {
+        for align in [TextAlign::Left, TextAlign::Centre, TextAlign::Right] {
+            assert_eq!(glyphs(BitmapText::new("HIGH SCORE").with_width(4).with_align(align)), "HIGH");
+        }
+        assert_eq!(glyphs(BitmapText::new("1UP").with_width(3)), "1UP");
+    }
+
+    fn xs(align: TextAlign, count: usize) -> Vec<f32> {
+        let text = BitmapText::new("").with_align(align);
+        (0..count).map(|i| text.glyph_x(i, count)).collect()
+    }
+
+    #[test]
+    fn glyphs_are_placed_by_alignment() {
+        assert_eq!(xs(TextAlign::Left, 3), vec![4.0, 12.0, 20.0]);
+        assert_eq!(xs(TextAlign::Centre, 3), vec![-8.0, 0.0, 8.0]);
+        assert_eq!(xs(TextAlign::Centre, 2), vec![-4.0, 4.0]);
+        assert_eq!(xs(TextAlign::Right, 3), vec![-20.0, -12.0, -4.0]);
+    }
+
+    /// (sprite index, x) of each glyph under the text
+    fn drawn(app: &mut App, entity: Entity) -> Vec<(usize, f32)> {
+        let children = app.world.get::<Children>(entity).map(|children| children.to_vec()).unwrap_or_default();
+        children.iter().filter_map(|child| {
+            let sprite = app.world.get::<TextureAtlasSprite>(*child)?;
+            let transform = app.world.get::<Transform>(*child)?;
+            Some((sprite.index, transform.translation.x))
+        }).collect()
+    }
+
+    #[test]
+    fn layout_draws_a_sprite_per_glyph() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_plugin(AssetPlugin::default()).add_asset::<Image>().add_asset::<TextureAtlas>()
+            .init_resource::<GameAssets>()
+            .add_system(BitmapText::layout);
+
+        let entity = app.world.spawn((BitmapText::new("A1?").with_align(TextAlign::Right), SpatialBundle::default())).id();
+        app.update();
+        assert_eq!(drawn(&mut app, entity), vec![(0, -20.0), (33, -12.0), (15, -4.0)]);
+
+        // Same length, the sprites are reused
+        let before = app.world.get::<Children>(entity).unwrap().to_vec();
+        app.world.get_mut::<BitmapText>(entity).unwrap().set("Z");
+        app.world.get_mut::<BitmapText>(entity).unwrap().width = Some(3);
+        app.update();
+        assert_eq!(app.world.get::<Children>(entity).unwrap().to_vec(), before);
+        assert_eq!(drawn(&mut app, entity), vec![(15, -20.0), (15, -12.0), (26, -4.0)]);
+
+        // A different length redraws
+        app.world.get_mut::<BitmapText>(entity).unwrap().width = None;
+        app.update();
+        assert_eq!(drawn(&mut app, entity), vec![(26, -4.0)]);
+    }
+}
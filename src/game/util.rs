@@ -1,12 +1,12 @@
-use std::{ops::Neg, path::Path, fs, io::Write};
+use std::{ops::Neg, path::Path, fs, io::Write, time::{SystemTime, UNIX_EPOCH}};
 
-use bevy::{prelude::*, sprite::collide_aabb::collide, utils::HashMap};
+use bevy::{prelude::*, sprite::collide_aabb::collide};
 // use bevy_inspector_egui::Inspectable;
 
 use anyhow::Result;
 use serde_json::Value;
 
-use super::{map::{TileCollider, Food, DEFAULT_MAP_ORIGIN}, player::PlayerMovement, TILE_SIZE, CurrentDirection, STEP_SIZE, AnimationDescriptor, MovementHelper, enemy::Ghost, GHOST_DEBUFF};
+use super::{map::{TileCollider, Food}, player::PlayerMovement, TILE_SIZE, CurrentDirection, STEP_SIZE, AnimationDescriptor, MovementHelper, enemy::Ghost, GHOST_DEBUFF};
 
 const CUSTOM_CHECKS: bool = false;
 
@@ -124,8 +124,8 @@ pub struct Moves {
 
 /// Mainly for Blinky
 /// TODO
-pub fn chase(player_pos: Vec3, my_current_direction: CurrentDirection, my_pos: Vec3, walls: &Query<(&Transform, &TileCollider), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>)>) -> Option<MoveDesc> {
-    let mut moves = determine_possible_moves(my_pos, my_current_direction, player_pos, walls);
+pub fn chase(player_pos: Vec3, my_current_direction: CurrentDirection, my_pos: Vec3, walls: &Query<(&Transform, &TileCollider), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>)>, origin: Vec3) -> Option<MoveDesc> {
+    let mut moves = determine_possible_moves(my_pos, my_current_direction, player_pos, walls, origin);
 
     let mut lowest_distance: f32 = f32::MAX;
     let mut itr = 0;
@@ -151,8 +151,8 @@ pub fn chase(player_pos: Vec3, my_current_direction: CurrentDirection, my_pos: V
     } else { None }
 }
 
-pub fn get_heighest_distance(player_pos: Vec3, my_current_direction: CurrentDirection, my_pos: Vec3, walls: &Query<(&Transform, &TileCollider), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>)>) -> Option<MoveDesc> {
-    let mut moves = determine_possible_moves(my_pos, my_current_direction, player_pos, walls);
+pub fn get_heighest_distance(player_pos: Vec3, my_current_direction: CurrentDirection, my_pos: Vec3, walls: &Query<(&Transform, &TileCollider), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>)>, origin: Vec3) -> Option<MoveDesc> {
+    let mut moves = determine_possible_moves(my_pos, my_current_direction, player_pos, walls, origin);
 
     let mut heightest_distance: f32 = f32::MIN;
     let mut itr = 0;
@@ -189,7 +189,7 @@ pub struct MoveDesc {
 }
 
 /// Reminder that ghosts are NOT allowed to turn around at crossroads this is TODO
-pub fn determine_possible_moves(my_pos: Vec3, my_current_direction: CurrentDirection, player_pos: Vec3, walls: &Query<(&Transform, &TileCollider), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>)>) -> MoveDesc {
+pub fn determine_possible_moves(my_pos: Vec3, my_current_direction: CurrentDirection, player_pos: Vec3, walls: &Query<(&Transform, &TileCollider), (Without<PlayerMovement>, Without<AnimationDescriptor>, Without<MovementHelper>, Without<Ghost>)>, origin: Vec3) -> MoveDesc {
     let up = my_pos + Vec3::new(0.0, calculate_next_step(true), 0.0);
     let down = my_pos - Vec3::new(0.0, calculate_next_step(true), 0.0);
 
@@ -204,19 +204,19 @@ pub fn determine_possible_moves(my_pos: Vec3, my_current_direction: CurrentDirec
     let mut move_data = MoveDesc { up: None, down: None, left: None, right: None, all: Vec::new(), choice: None, alt_choice: None };
 
     let mut moves = Vec::new();
-    if !check_collosion(up, walls, DEFAULT_MAP_ORIGIN) && my_current_direction.opposite() != CurrentDirection::Up {
+    if !check_collosion(up, walls, origin) && my_current_direction.opposite() != CurrentDirection::Up {
         move_data.up = Some(up);
         moves.push(up)
     }
-    if !check_collosion(down, walls, DEFAULT_MAP_ORIGIN) && my_current_direction.opposite() != CurrentDirection::Down {
+    if !check_collosion(down, walls, origin) && my_current_direction.opposite() != CurrentDirection::Down {
         move_data.down = Some(down);
         moves.push(down)
     }
-    if !check_collosion(left, walls, DEFAULT_MAP_ORIGIN) && my_current_direction.opposite() != CurrentDirection::Left {
+    if !check_collosion(left, walls, origin) && my_current_direction.opposite() != CurrentDirection::Left {
         move_data.left = Some(left);
         moves.push(left)
     }
-    if !check_collosion(right, walls, DEFAULT_MAP_ORIGIN) && my_current_direction.opposite() != CurrentDirection::Right {
+    if !check_collosion(right, walls, origin) && my_current_direction.opposite() != CurrentDirection::Right {
         move_data.right = Some(right);
         moves.push(right)
     }
@@ -246,13 +246,62 @@ pub fn drop_settings() -> Result<()> {
     Ok(())
 }
 
-#[derive(Resource, Reflect)]
-pub struct PowerPellets {
-    pub pellets: HashMap<usize, Vec3>,
+/// Stable FNV-1a hash of a string, used to tell maps (and settings) apart on disk
+pub fn hash_str(data: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{:016x}", hash)
 }
 
-impl Default for PowerPellets {
-    fn default() -> Self {
-        Self { pellets: HashMap::new() }
+/// Small seeded random numbers (xorshift64*), the same seed always gives the same sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xorshift(u64);
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Self {
+        // A state of zero would only ever give zero
+        let state = seed ^ 0x9e3779b97f4a7c15;
+        Self(if state == 0 { 0x9e3779b97f4a7c15 } else { state })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// 0 <= n < 1
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// 0 <= n < max
+    pub fn below(&mut self, max: usize) -> usize {
+        if max == 0 { return 0 }
+        (self.next_u64() % max as u64) as usize
     }
-}
\ No newline at end of file
+}
+
+/// Todays date as YYYY-MM-DD (UTC)
+pub fn today() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = (secs / 86400) as i64;
+
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,65 @@
+use bevy::{prelude::*, render::camera::Viewport, window::{PrimaryWindow, WindowResized}};
+
+use super::{map::CurrentMap, TILE_SIZE};
+
+/// Screen pixels per tile at 1x, the same as the arcade
+const TILE_PIXELS: u32 = 8;
+
+/// Rows kept for the HUD above and below the maze
+const HUD_ROWS_ABOVE: usize = 2;
+const HUD_ROWS_BELOW: usize = 2;
+
+/// Keeps the maze and HUD filling the window at a whole number scale, anything left over is letterboxed
+pub struct ViewPlugin;
+
+impl Plugin for ViewPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(Self::fit_camera)
+
+        ;
+    }
+}
+
+impl ViewPlugin {
+    /// Recomputed when the window is resized or a new map is loaded
+    pub fn fit_camera(
+        mut resized: EventReader<WindowResized>,
+        current_map: Option<Res<CurrentMap>>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        mut cameras: Query<(&mut Camera, &mut OrthographicProjection, &mut Transform), With<Camera2d>>,
+    ) {
+        let resized = resized.iter().count() > 0;
+
+        let Some(current_map) = current_map else { return };
+        if !resized && !current_map.is_changed() { return }
+
+        let Ok(window) = windows.get_single() else { return };
+        let physical = UVec2 { x: window.physical_width(), y: window.physical_height() };
+
+        // Minimised
+        if physical.x == 0 || physical.y == 0 { return }
+
+        let rows = current_map.height + HUD_ROWS_ABOVE + HUD_ROWS_BELOW;
+        let native = UVec2 { x: current_map.width.max(1) as u32, y: rows as u32 } * TILE_PIXELS;
+
+        let scale = (physical.x / native.x).min(physical.y / native.y).max(1);
+        let size = (native * scale).min(physical);
+        let position = (physical - size) / 2;
+
+        // The middle of the maze, the HUD rows are split evenly either side
+        let centre = current_map.tile_pos((current_map.width as f32 - 1.0) / 2.0, (current_map.height as f32 - 1.0) / 2.0);
+
+        for (mut camera, mut projection, mut transform) in &mut cameras {
+            camera.viewport = Some(Viewport { physical_position: position, physical_size: size, ..Default::default() });
+
+            // World units per logical pixel, the viewport is in physical pixels
+            projection.scale = TILE_SIZE * 10.0 / (TILE_PIXELS * scale) as f32 * window.scale_factor() as f32;
+
+            transform.translation.x = centre.x;
+            transform.translation.y = centre.y;
+        }
+
+        info!("Fitted {}x{} tiles to the window at {}x", current_map.width, rows, scale);
+    }
+}
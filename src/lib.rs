@@ -0,0 +1,5 @@
+//! The game itself and its score server client, main.rs adds the window and debug tools on top
+//! Split out so it can also be driven without a window (see game::env) and tested from tests/
+
+pub mod game;
+pub mod network;
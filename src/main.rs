@@ -2,12 +2,11 @@
 
 use bevy::{prelude::*, log::LogPlugin};
 use anyhow::Result;
-use game::{player::Player, map::{self, FoodSystem}, EntityState, enemy, GameController, util::PowerPellets};
-use bevy::window::WindowResolution;
+use pacman::{network, game::{self, player::Player, map::{self, FoodSystem}, EntityState, enemy, GameController, score, fingerprint, hud, text, view, input, players, bot, editor, browser, assets::GameAssets}};
+use bevy::window::{WindowResolution, PresentMode};
 
 use crate::data::cargo_toml::get_version;
 
-mod game;
 mod debug;
 mod data;
 
@@ -30,14 +29,27 @@ fn main() -> Result<()> {
 
     // println!("{}", get_version().unwrap());
 
+    let args: Vec<String> = std::env::args().collect();
+
+    // Map tools, these run without starting the game
+    match args.get(1).map(String::as_str) {
+        Some("analyze") => return map::analysis::run(&args[2..]),
+        Some("render") => return map::render::run(&args[2..]),
+        _ => {},
+    }
+
     if ALLOW_GAME_RUN {
         let mut app = App::new();
 
+        let benchmark = bot::Benchmark::from_args(&args);
+
         let mut window = WindowPlugin::default();
 
         window.primary_window = Some( Window {
             title: NAME.to_string(),
             resolution: WindowResolution::default(),
+            // Benchmarks run as fast as they can
+            present_mode: if benchmark.is_some() { PresentMode::AutoNoVsync } else { PresentMode::default() },
             ..Default::default()
         } );
 
@@ -57,10 +69,32 @@ fn main() -> Result<()> {
 
         let default_plugin = default_plugin.set(window);
 
+        // Saving a map file while playing rebuilds the maze
+        let default_plugin = default_plugin.set(AssetPlugin { watch_for_changes: true, ..Default::default() });
+
+        if let Some(benchmark) = benchmark {
+            info!("Benchmarking with seed {} for {} level(s)", benchmark.seed, benchmark.levels);
+            app.insert_resource(benchmark);
+        }
+
+        if let Some(map_path) = map::asset::MapPath::from_args(&args) {
+            app.insert_resource(map_path);
+        }
+
+        if let Some(endless) = map::generator::Endless::from_args(&args) {
+            app.insert_resource(endless);
+        }
+
+        app.insert_resource(fingerprint::Cheats::load());
+
+        if args.iter().any(|arg| arg == "--editor") {
+            app.insert_resource(editor::OpenEditor);
+        }
+
         app
             .add_plugins(default_plugin)
             .insert_resource(ClearColor(CLEAR))
-            .init_resource::<PowerPellets>()
+            .init_resource::<GameAssets>()
 
 
 
@@ -70,6 +104,17 @@ fn main() -> Result<()> {
             .add_plugin(FoodSystem)
             .add_plugin(enemy::GhostPlugin)
             .add_plugin(GameController)
+            .add_plugin(score::ScorePlugin)
+            .add_plugin(fingerprint::FingerprintPlugin)
+            .add_plugin(text::TextPlugin)
+            .add_plugin(hud::HudPlugin)
+            .add_plugin(view::ViewPlugin)
+            .add_plugin(input::InputPlugin)
+            .add_plugin(players::PlayersPlugin)
+            .add_plugin(bot::BotPlugin)
+            .add_plugin(editor::EditorPlugin)
+            .add_plugin(browser::MapBrowserPlugin)
+            .add_plugin(network::NetworkPlugin)
 
         
             .add_plugin(debug::DebugPlugin)
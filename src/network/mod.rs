@@ -0,0 +1,302 @@
+use std::{fs, path::Path, sync::{Arc, Mutex}, time::Duration};
+
+use anyhow::{Result, bail};
+use bevy::{prelude::*, tasks::IoTaskPool};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{score::GameFinished, map::CurrentMap, fingerprint::{SettingsFingerprint, ranked_default}, util::{get_settings, today}};
+
+/// Submissions that have not reached the server yet, kept next to settings.toml
+const QUEUE_FILE: &str = "./score_queue.json";
+
+/// Seconds between attempts to flush the queue
+const RETRY_INTERVAL: f32 = 30.0;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreSubmission {
+    pub initials: Option<String>,
+    pub score: usize,
+    pub level: usize,
+    pub map_hash: String,
+    /// There is no replay recording yet, this will be None until there is
+    pub replay_hash: Option<String>,
+    pub settings_hash: String,
+    /// Cheats or a modified speed table make a game unranked
+    pub ranked: bool,
+    pub cheats: Vec<String>,
+    pub date: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub initials: Option<String>,
+    pub score: usize,
+    pub level: usize,
+    #[serde(default = "ranked_default")]
+    pub ranked: bool,
+    pub date: String,
+}
+
+/// Blocking HTTP/JSON client for the score server, the plugin runs it on the IO task pool
+#[derive(Debug, Clone)]
+pub struct ScoreClient {
+    base_url: String,
+    agent: ureq::Agent,
+}
+
+impl ScoreClient {
+    /// `server` may be a bare host (hoovy.co.uk), https is assumed then
+    pub fn new(server: &str) -> Self {
+        let server = server.trim_end_matches('/');
+        let base_url = if server.starts_with("http://") || server.starts_with("https://") {
+            server.to_string()
+        } else {
+            format!("https://{}", server)
+        };
+
+        Self { base_url: base_url, agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build() }
+    }
+
+    /// Returns None if networking is turned off in settings.toml
+    pub fn from_settings() -> Result<Option<Self>> {
+        let settings = get_settings()?;
+        let networking = &settings["networking"];
+
+        if !networking["allow_networking"].as_bool().unwrap_or(false) {
+            return Ok(None)
+        }
+
+        match networking["score_server"].as_str() {
+            Some(server) if !server.is_empty() => Ok(Some(Self::new(server))),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn submit(&self, submission: &ScoreSubmission) -> Result<()> {
+        let response = self.agent.post(&format!("{}/api/scores", self.base_url)).send_json(submission)?;
+
+        if response.status() != 200 && response.status() != 201 {
+            bail!("Score server returned {}", response.status());
+        }
+
+        Ok(())
+    }
+
+    /// Sends submissions in order, stopping at the first failure, returns how many were sent
+    pub fn submit_all(&self, submissions: &[ScoreSubmission]) -> usize {
+        let mut sent = 0;
+        for submission in submissions {
+            if let Err(e) = self.submit(submission) {
+                warn!("Score submission failed, will retry: {}", e);
+                break
+            }
+            sent += 1;
+        }
+
+        sent
+    }
+
+    pub fn leaderboard(&self, map_hash: &str) -> Result<Vec<LeaderboardEntry>> {
+        let response = self.agent.get(&format!("{}/api/leaderboard/{}", self.base_url, map_hash)).call()?;
+
+        Ok(response.into_json()?)
+    }
+}
+
+/// Finished games waiting to be sent, saved to disk so they survive being offline
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubmissionQueue {
+    pub pending: Vec<ScoreSubmission>,
+}
+
+impl SubmissionQueue {
+    pub fn load() -> Result<Self> {
+        Self::load_from(Path::new(QUEUE_FILE))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(Path::new(QUEUE_FILE))
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+
+        Ok(serde_json::from_str(fs::read_to_string(path)?.as_str())?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Drops the first `count` submissions once the server has them
+    pub fn sent(&mut self, count: usize) {
+        self.pending.drain(..count.min(self.pending.len()));
+    }
+}
+
+/// Results coming back from the IO task pool
+#[derive(Debug)]
+enum NetworkResult {
+    /// How many of the queued submissions were sent (in order)
+    Submitted(usize),
+    Leaderboard { map_hash: String, entries: Vec<LeaderboardEntry> },
+}
+
+/// Ask for the online leaderboard of a map, the answer ends up in `Leaderboard`
+pub struct FetchLeaderboard(pub String);
+
+/// The last leaderboard fetched from the score server
+#[derive(Debug, Clone, Default, Resource)]
+pub struct Leaderboard {
+    pub map_hash: Option<String>,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+#[derive(Resource)]
+pub struct ScoreNetwork {
+    client: Option<ScoreClient>,
+    queue: SubmissionQueue,
+    in_flight: bool,
+    retry: Timer,
+    inbox: Arc<Mutex<Vec<NetworkResult>>>,
+}
+
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        let client = match ScoreClient::from_settings() {
+            Ok(client) => client,
+            Err(e) => { error!("Failed to read networking settings: {}", e); None },
+        };
+
+        match &client {
+            Some(client) => info!("Score server: {}", client.base_url()),
+            None => info!("Networking disabled, scores will only be kept locally"),
+        }
+
+        let queue = match SubmissionQueue::load() {
+            Ok(queue) => queue,
+            Err(e) => { error!("Failed to load queued scores: {}", e); SubmissionQueue::default() },
+        };
+
+        let mut retry = Timer::from_seconds(RETRY_INTERVAL, TimerMode::Repeating);
+        // Try anything left over from last time straight away
+        retry.set_elapsed(Duration::from_secs_f32(RETRY_INTERVAL));
+
+        app
+            .add_event::<FetchLeaderboard>()
+            .init_resource::<Leaderboard>()
+            .insert_resource(ScoreNetwork { client: client, queue: queue, in_flight: false, retry: retry, inbox: Arc::new(Mutex::new(Vec::new())) })
+            .add_system(ScoreNetwork::queue_finished_games)
+            .add_system(ScoreNetwork::flush_queue.after(ScoreNetwork::queue_finished_games))
+            .add_system(ScoreNetwork::fetch_leaderboards)
+            .add_system(ScoreNetwork::receive)
+
+        ;
+    }
+}
+
+impl ScoreNetwork {
+    pub fn queue_finished_games(mut network: ResMut<Self>, mut finished: EventReader<GameFinished>, current_map: Res<CurrentMap>, fingerprint: Option<Res<SettingsFingerprint>>) {
+        if network.client.is_none() {
+            finished.clear();
+            return
+        }
+
+        // Computed on the first frame, the events will still be around next frame
+        let Some(fingerprint) = fingerprint else { return };
+
+        let games: Vec<&GameFinished> = finished.iter().collect();
+        if games.is_empty() { return }
+
+        for game in games {
+            network.queue.pending.push(ScoreSubmission {
+                initials: game.initials.clone(),
+                score: game.score,
+                level: game.level,
+                map_hash: current_map.hash.clone(),
+                replay_hash: None,
+                settings_hash: fingerprint.hash.clone(),
+                ranked: fingerprint.ranked,
+                cheats: fingerprint.cheats.clone(),
+                date: today(),
+            });
+        }
+
+        if let Err(e) = network.queue.save() {
+            error!("Failed to save queued scores: {}", e);
+        }
+
+        // Don't wait for the retry timer for a fresh game
+        let duration = network.retry.duration();
+        network.retry.set_elapsed(duration);
+    }
+
+    /// Sends everything queued, in order, stopping at the first failure
+    pub fn flush_queue(mut network: ResMut<Self>, time: Res<Time>) {
+        network.retry.tick(time.delta());
+
+        if network.in_flight || network.queue.pending.is_empty() || !network.retry.finished() { return }
+        network.retry.reset();
+
+        let Some(client) = network.client.clone() else { return };
+        let pending = network.queue.pending.clone();
+        let inbox = network.inbox.clone();
+        network.in_flight = true;
+
+        IoTaskPool::get().spawn(async move {
+            let sent = client.submit_all(&pending);
+            inbox.lock().unwrap().push(NetworkResult::Submitted(sent));
+        }).detach();
+    }
+
+    pub fn fetch_leaderboards(network: Res<Self>, mut requests: EventReader<FetchLeaderboard>) {
+        for request in requests.iter() {
+            let Some(client) = network.client.clone() else { continue };
+            let map_hash = request.0.clone();
+            let inbox = network.inbox.clone();
+
+            IoTaskPool::get().spawn(async move {
+                match client.leaderboard(&map_hash) {
+                    Ok(entries) => inbox.lock().unwrap().push(NetworkResult::Leaderboard { map_hash: map_hash, entries: entries }),
+                    Err(e) => warn!("Failed to fetch leaderboard: {}", e),
+                }
+            }).detach();
+        }
+    }
+
+    pub fn receive(mut network: ResMut<Self>, mut leaderboard: ResMut<Leaderboard>) {
+        let results: Vec<NetworkResult> = network.inbox.lock().unwrap().drain(..).collect();
+
+        for result in results {
+            match result {
+                NetworkResult::Submitted(sent) => {
+                    network.in_flight = false;
+                    if sent == 0 { continue }
+
+                    network.queue.sent(sent);
+                    info!("Submitted {} score(s), {} still queued", sent, network.queue.pending.len());
+
+                    if let Err(e) = network.queue.save() {
+                        error!("Failed to save queued scores: {}", e);
+                    }
+                },
+                NetworkResult::Leaderboard { map_hash, entries } => {
+                    leaderboard.map_hash = Some(map_hash);
+                    leaderboard.entries = entries;
+                },
+            }
+        }
+    }
+}
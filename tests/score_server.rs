@@ -0,0 +1,101 @@
+//! The score client against the mock server, all on localhost
+
+use std::{env, fs, io::{BufRead, BufReader}, net::TcpListener, process::{Child, Command, Stdio}};
+
+use pacman::network::{ScoreClient, ScoreSubmission, SubmissionQueue};
+
+/// The mock server, stopped when dropped
+struct MockServer {
+    child: Child,
+    port: u16,
+}
+
+impl MockServer {
+    /// Port 0 for any free port
+    fn start(port: u16) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_mock_score_server"))
+            .arg(port.to_string())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("mock server starts");
+
+        // "Mock score server listening on http://127.0.0.1:<port>"
+        let mut line = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap()).read_line(&mut line).unwrap();
+        let port = line.trim().rsplit(':').next().and_then(|port| port.parse().ok()).expect("mock server prints its port");
+
+        Self { child: child, port: port }
+    }
+
+    fn client(&self) -> ScoreClient {
+        ScoreClient::new(&format!("http://127.0.0.1:{}", self.port))
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn submission(initials: &str, score: usize, map_hash: &str) -> ScoreSubmission {
+    ScoreSubmission {
+        initials: Some(initials.to_string()),
+        score: score,
+        level: 2,
+        map_hash: map_hash.to_string(),
+        replay_hash: None,
+        settings_hash: "settings".to_string(),
+        ranked: true,
+        cheats: Vec::new(),
+        date: "2023-04-01".to_string(),
+    }
+}
+
+#[test]
+fn submits_and_reads_the_leaderboard() {
+    let server = MockServer::start(0);
+    let client = server.client();
+
+    client.submit(&submission("AAA", 1200, "submit")).unwrap();
+    client.submit(&submission("BBB", 3400, "submit")).unwrap();
+
+    let leaderboard = client.leaderboard("submit").unwrap();
+    let scores: Vec<(Option<String>, usize)> = leaderboard.iter().map(|entry| (entry.initials.clone(), entry.score)).collect();
+    assert_eq!(scores, vec![(Some("BBB".to_string()), 3400), (Some("AAA".to_string()), 1200)]);
+    assert_eq!(leaderboard[0].level, 2);
+    assert!(leaderboard[0].ranked);
+
+    assert!(client.leaderboard("another map").unwrap().is_empty());
+}
+
+#[test]
+fn queue_is_flushed_once_the_server_is_up() {
+    // A port nothing is listening on, for now
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let client = ScoreClient::new(&format!("http://127.0.0.1:{}", port));
+
+    let file = env::temp_dir().join(format!("pacman_score_queue_{}.json", std::process::id()));
+    let mut queue = SubmissionQueue { pending: vec![submission("OFF", 500, "queue"), submission("LNE", 700, "queue")] };
+
+    let sent = client.submit_all(&queue.pending);
+    assert_eq!(sent, 0);
+    queue.sent(sent);
+    queue.save_to(&file).unwrap();
+
+    // Still there after a restart
+    let mut queue = SubmissionQueue::load_from(&file).unwrap();
+    assert_eq!(queue.pending.len(), 2);
+
+    let server = MockServer::start(port);
+    let sent = client.submit_all(&queue.pending);
+    assert_eq!(sent, 2);
+    queue.sent(sent);
+    assert!(queue.pending.is_empty());
+
+    let scores: Vec<usize> = server.client().leaderboard("queue").unwrap().iter().map(|entry| entry.score).collect();
+    assert_eq!(scores, vec![700, 500]);
+
+    let _ = fs::remove_file(file);
+}